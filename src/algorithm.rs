@@ -0,0 +1,166 @@
+use chroma_filter::FILTER_COEFFICIENTS;
+use error::Error;
+use fingerprint_calculator::FILTER_WIDTH;
+use fingerprinter::TARGET_SAMPLE_RATE;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+const DEFAULT_FRAME_SIZE: usize = 4096;
+const DEFAULT_FRAME_OVERLAP: usize = DEFAULT_FRAME_SIZE - DEFAULT_FRAME_SIZE / 3;
+const DEFAULT_SILENCE_THRESHOLD: i32 = 50;
+
+/// The fingerprinting algorithms implemented by Chromaprint. These mirror
+/// `CHROMAPRINT_ALGORITHM_TEST1` through `CHROMAPRINT_ALGORITHM_TEST5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Algorithm {
+    Test1,
+    Test2,
+    Test3,
+    Test4,
+    Test5,
+}
+
+impl Default for Algorithm {
+    /// `Test2` is the algorithm used by `fpcalc` and AcoustID unless told otherwise.
+    fn default() -> Algorithm {
+        Algorithm::Test2
+    }
+}
+
+impl Algorithm {
+    /// The identifier written into the header of compressed fingerprints.
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::Test1 => 0,
+            Algorithm::Test2 => 1,
+            Algorithm::Test3 => 2,
+            Algorithm::Test4 => 3,
+            Algorithm::Test5 => 4,
+        }
+    }
+
+    /// Returns the algorithm for an identifier found in a compressed fingerprint header.
+    pub fn from_id(id: u8) -> Option<Algorithm> {
+        match id {
+            0 => Some(Algorithm::Test1),
+            1 => Some(Algorithm::Test2),
+            2 => Some(Algorithm::Test3),
+            3 => Some(Algorithm::Test4),
+            4 => Some(Algorithm::Test5),
+            _ => None,
+        }
+    }
+
+    /// Number of samples (at the target sample rate) in each FFT frame.
+    pub fn frame_size(self) -> usize {
+        match self {
+            Algorithm::Test5 => DEFAULT_FRAME_SIZE / 2,
+            _ => DEFAULT_FRAME_SIZE,
+        }
+    }
+
+    /// Number of samples shared between two consecutive FFT frames.
+    pub fn frame_overlap(self) -> usize {
+        match self {
+            Algorithm::Test5 => DEFAULT_FRAME_SIZE / 2 - DEFAULT_FRAME_SIZE / 4,
+            _ => DEFAULT_FRAME_OVERLAP,
+        }
+    }
+
+    /// Duration in seconds of the audio between the starts of two consecutive items.
+    pub fn item_duration(self) -> f64 {
+        (self.frame_size() - self.frame_overlap()) as f64 / TARGET_SAMPLE_RATE as f64
+    }
+
+    /// Number of samples (at the target sample rate) of audio before an item which affect it,
+    /// like `chromaprint_get_delay`. Fingerprints of consecutive chunks overlap by this much.
+    pub fn delay(self) -> usize {
+        let item_size = self.frame_size() - self.frame_overlap();
+        (FILTER_COEFFICIENTS.len() - 1 + FILTER_WIDTH - 1) * item_size + self.frame_overlap()
+    }
+
+    /// Duration in seconds of `delay`.
+    pub fn delay_duration(self) -> f64 {
+        self.delay() as f64 / TARGET_SAMPLE_RATE as f64
+    }
+
+    /// Whether the energy of an FFT bin is split between the two nearest notes.
+    pub fn interpolate(self) -> bool {
+        self == Algorithm::Test3
+    }
+
+    /// Whether leading silence is skipped before fingerprinting.
+    pub fn remove_silence(self) -> bool {
+        self == Algorithm::Test4
+    }
+
+    /// The average absolute amplitude below which audio is considered silent.
+    pub fn silence_threshold(self) -> i32 {
+        if self.remove_silence() {
+            DEFAULT_SILENCE_THRESHOLD
+        } else {
+            0
+        }
+    }
+}
+
+impl TryFrom<u8> for Algorithm {
+    type Error = Error;
+
+    /// Like `Algorithm::from_id`, but fails with `Error::UnknownAlgorithm`.
+    fn try_from(id: u8) -> Result<Algorithm, Error> {
+        Algorithm::from_id(id).ok_or(Error::UnknownAlgorithm(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Algorithm;
+    use error::Error;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_id_round_trip() {
+        for id in 0..5 {
+            assert_eq!(id, Algorithm::from_id(id).unwrap().id());
+        }
+
+        assert_eq!(None, Algorithm::from_id(5));
+    }
+
+    #[test]
+    fn test_try_from() {
+        assert_eq!(Ok(Algorithm::Test4), Algorithm::try_from(3));
+        assert_eq!(Err(Error::UnknownAlgorithm(5)), Algorithm::try_from(5));
+        assert_eq!(Err(Error::UnknownAlgorithm(255)), Algorithm::try_from(255));
+    }
+
+    #[test]
+    fn test_default() {
+        assert_eq!(Algorithm::Test2, Algorithm::default());
+        assert_eq!(1, Algorithm::default().id());
+    }
+
+    #[test]
+    fn test_frame_sizes() {
+        assert_eq!(4096, Algorithm::Test2.frame_size());
+        assert_eq!(2731, Algorithm::Test2.frame_overlap());
+        assert_eq!(2048, Algorithm::Test5.frame_size());
+        assert_eq!(1024, Algorithm::Test5.frame_overlap());
+    }
+
+    #[test]
+    fn test_delay() {
+        assert_eq!(28666, Algorithm::Test2.delay());
+        assert_eq!(20480, Algorithm::Test5.delay());
+        assert_ulps_eq!(28666.0 / 11025.0, Algorithm::Test2.delay_duration());
+    }
+
+    #[test]
+    fn test_item_duration() {
+        assert_ulps_eq!(1365.0 / 11025.0, Algorithm::Test2.item_duration());
+        assert_ulps_eq!(1024.0 / 11025.0, Algorithm::Test5.item_duration());
+    }
+}
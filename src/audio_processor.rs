@@ -1,5 +1,15 @@
+use downmixer::Downmixer;
+use error::Error;
 use resampler::Resampler;
+use sample::{Pcm, Sample};
 use slicer::Slicer;
+use std::collections::HashMap;
+
+/// Input sample rates must be above this, like in Chromaprint.
+pub const MIN_SAMPLE_RATE: u32 = 1000;
+
+/// The highest sample rate in common use. Anything above this is most likely a mistake.
+pub const MAX_SAMPLE_RATE: u32 = 768_000;
 
 const MAX_BUFFER_SIZE: usize = 1024 * 32;
 const RESAMPLE_FILTER_LENGTH: i32 = 16;
@@ -8,56 +18,195 @@ const RESAMPLE_LINEAR: bool = false;
 const RESAMPLE_SAMPLE_CUTOFF: f64 = 0.8;
 
 pub struct AudioProcessor {
-    slicer: Option<Slicer<i16>>,
+    target_sample_rate: u32,
+    input_sample_rate: u32,
+
+    /// Only present when the input has more than one channel.
+    downmixer: Option<Downmixer>,
+    buffer: Buffer,
+
+    /// Downmixed or converted samples, kept to reuse the allocation.
+    i16_samples: Vec<i16>,
+    f32_samples: Vec<f32>,
     resampler: Resampler,
+
+    /// Resamplers for the other input sample rates used so far, kept for their filter banks.
+    resampler_cache: HashMap<u32, Resampler>,
 }
 
 impl AudioProcessor {
-    pub fn new(target_sample_rate: u16, input_sample_rate: u16) -> AudioProcessor {
-        AudioProcessor {
-            slicer: Some(Slicer::new(MAX_BUFFER_SIZE)),
-            resampler: Resampler::new(
-                target_sample_rate as i32,
-                input_sample_rate as i32,
-                RESAMPLE_FILTER_LENGTH,
-                RESAMPLE_PHASE_SHIFT,
-                RESAMPLE_LINEAR,
-                RESAMPLE_SAMPLE_CUTOFF,
-            ),
+    pub fn new(
+        target_sample_rate: u32,
+        input_sample_rate: u32,
+        channels: u16,
+    ) -> Result<AudioProcessor, Error> {
+        validate_sample_rate(input_sample_rate)?;
+
+        Ok(AudioProcessor {
+            target_sample_rate,
+            input_sample_rate,
+            downmixer: downmixer(channels),
+            buffer: Buffer::I16(Slicer::new(MAX_BUFFER_SIZE)),
+            i16_samples: Vec::new(),
+            f32_samples: Vec::new(),
+            resampler: new_resampler(target_sample_rate, input_sample_rate)?,
+            resampler_cache: HashMap::new(),
+        })
+    }
+
+    /// Prepares for a new input with a possibly different format. The resampler filter bank is
+    /// reused if the sample rate was used before.
+    pub fn start(&mut self, input_sample_rate: u32, channels: u16) -> Result<(), Error> {
+        validate_sample_rate(input_sample_rate)?;
+
+        if input_sample_rate != self.input_sample_rate {
+            let resampler = match self.resampler_cache.remove(&input_sample_rate) {
+                Some(resampler) => resampler,
+                None => new_resampler(self.target_sample_rate, input_sample_rate)?,
+            };
+            let previous = std::mem::replace(&mut self.resampler, resampler);
+            self.resampler_cache
+                .insert(self.input_sample_rate, previous);
+            self.input_sample_rate = input_sample_rate;
         }
+
+        self.downmixer = downmixer(channels);
+        self.reset();
+        Ok(())
     }
 
-    pub fn feed<C: FnMut(Vec<i16>)>(&mut self, data: &[i16], mut consumer: C) {
-        let mut slicer = self.slicer.take().unwrap();
+    /// Drops any buffered samples to start over with a new input of the same format.
+    pub fn reset(&mut self) {
+        if let Some(ref mut downmixer) = self.downmixer {
+            downmixer.reset();
+        }
+        self.buffer = Buffer::I16(Slicer::new(MAX_BUFFER_SIZE));
+        self.resampler.reset();
+    }
+
+    /// Feeds interleaved samples into the processor. The resampled samples are passed to
+    /// `consumer` on a 16-bit scale.
+    ///
+    /// 16-bit input is resampled in 16 bits like in Chromaprint. Once anything else is fed, the
+    /// rest of the input is processed in floating point.
+    pub fn feed<S: Sample, C: FnMut(Vec<f32>)>(&mut self, data: &[S], mut consumer: C) {
+        let resampler = &mut self.resampler;
 
-        slicer.process(data, |src| {
-            let (consumed_size, dst) = self.resample_slice(src);
+        if let Buffer::I16(ref mut slicer) = self.buffer {
+            if let Some(data) = S::as_i16_slice(data) {
+                let data = match self.downmixer {
+                    Some(ref mut downmixer) => {
+                        self.i16_samples.clear();
+                        downmixer.process(data, &mut self.i16_samples);
+                        &self.i16_samples
+                    }
+                    None => data,
+                };
+
+                slicer.process(data, |src| {
+                    let (consumed_size, dst) = resample_slice(resampler, src);
+                    consumer(dst.into_iter().map(|sample| sample as f32).collect());
+                    consumed_size
+                });
+                return;
+            }
+        }
+
+        self.f32_samples.clear();
+        match self.downmixer {
+            Some(ref mut downmixer) => downmixer.process(data, &mut self.f32_samples),
+            None => self
+                .f32_samples
+                .extend(data.iter().map(|sample| sample.to_f32())),
+        }
+
+        let slicer = self.buffer.switch_to_f32();
+        slicer.process(&self.f32_samples, |src| {
+            let (consumed_size, dst) = resample_slice(resampler, src);
             consumer(dst);
             consumed_size
         });
-
-        self.slicer = Some(slicer);
     }
 
     /// Transcodes any un-transcoded samples and returns if any are left.
-    pub fn flush(&mut self) -> Option<Vec<i16>> {
-        let mut slicer = self.slicer.take().unwrap();
-        let remaining = slicer.flush();
-        self.slicer = Some(slicer);
-        if remaining.is_empty() {
-            return None;
+    pub fn flush(&mut self) -> Option<Vec<f32>> {
+        match self.buffer {
+            Buffer::I16(ref mut slicer) => {
+                let remaining = slicer.flush();
+                if remaining.is_empty() {
+                    return None;
+                }
+
+                let (_, dst) = resample_slice(&mut self.resampler, remaining);
+                Some(dst.into_iter().map(|sample| sample as f32).collect())
+            }
+            Buffer::F32(ref mut slicer) => {
+                let remaining = slicer.flush();
+                if remaining.is_empty() {
+                    return None;
+                }
+
+                let (_, dst) = resample_slice(&mut self.resampler, remaining);
+                Some(dst)
+            }
         }
+    }
+}
 
-        let (_, dst) = self.resample_slice(remaining);
-        Some(dst)
+/// Samples waiting to be resampled.
+enum Buffer {
+    I16(Slicer<i16>),
+    F32(Slicer<f32>),
+}
+
+impl Buffer {
+    /// Switches to floating point, converting any buffered samples.
+    fn switch_to_f32(&mut self) -> &mut Slicer<f32> {
+        if let Buffer::I16(ref mut slicer) = *self {
+            let mut converted = Slicer::new(MAX_BUFFER_SIZE);
+            converted.extend(slicer.flush().into_iter().map(|sample| sample as f32));
+            *self = Buffer::F32(converted);
+        }
+
+        match *self {
+            Buffer::F32(ref mut slicer) => slicer,
+            Buffer::I16(_) => unreachable!(),
+        }
     }
+}
+
+fn resample_slice<T: Pcm>(resampler: &mut Resampler, src: Vec<T>) -> (usize, Vec<T>) {
+    let mut dst = vec![T::default(); MAX_BUFFER_SIZE];
 
-    fn resample_slice(&mut self, src: Vec<i16>) -> (usize, Vec<i16>) {
-        let mut dst = vec![0i16; MAX_BUFFER_SIZE];
+    let (consumed_size, last_idx) = resampler.resample(&src, &mut dst);
+    dst.truncate(last_idx + 1);
 
-        let (consumed_size, last_idx) = self.resampler.resample(&src, &mut dst);
-        dst.truncate(last_idx + 1);
+    (consumed_size, dst)
+}
 
-        (consumed_size, dst)
+pub fn validate_sample_rate(sample_rate: u32) -> Result<(), Error> {
+    if sample_rate <= MIN_SAMPLE_RATE || sample_rate > MAX_SAMPLE_RATE {
+        return Err(Error::InvalidSampleRate(sample_rate));
     }
+
+    Ok(())
+}
+
+fn downmixer(channels: u16) -> Option<Downmixer> {
+    if channels > 1 {
+        Some(Downmixer::new(channels as usize))
+    } else {
+        None
+    }
+}
+
+fn new_resampler(target_sample_rate: u32, input_sample_rate: u32) -> Result<Resampler, Error> {
+    Resampler::new(
+        target_sample_rate,
+        input_sample_rate,
+        RESAMPLE_FILTER_LENGTH,
+        RESAMPLE_PHASE_SHIFT,
+        RESAMPLE_LINEAR,
+        RESAMPLE_SAMPLE_CUTOFF,
+    )
 }
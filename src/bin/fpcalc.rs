@@ -0,0 +1,633 @@
+//! A pure Rust replacement for Chromaprint's `fpcalc`, fingerprinting raw PCM or WAV audio read
+//! from files or the standard input.
+
+extern crate chromaprint;
+
+use chromaprint::wav::{SampleFormat, WavError, WavReader, WavSpec};
+use chromaprint::{Algorithm, Chunk, FeedStatus, Fingerprint, Fingerprinter};
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Cursor, Read, Write};
+use std::process;
+
+// Only part of the test audio shared with the library is used here.
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../test_audio.rs"]
+mod test_audio;
+
+const DEFAULT_MAX_DURATION: f64 = 120.0;
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+const DEFAULT_CHANNELS: u16 = 2;
+
+const USAGE: &str = "Usage: fpcalc [OPTIONS] FILE [FILE...]
+
+Generate fingerprints from audio files/streams.
+
+Options:
+  -format NAME   Set the input format name (wav or s16le, detected by default)
+  -rate NUM      Set the sample rate of raw input audio (default 44100)
+  -channels NUM  Set the number of channels in raw input audio (default 2)
+  -length SECS   Restrict the duration of the processed input audio (default 120, 0 for all)
+  -chunk SECS    Split the input audio into chunks of this duration
+  -algorithm NUM Set the algorithm method (default 2)
+  -overlap       Overlap the chunks slightly to make sure audio on the edges is fingerprinted
+  -raw           Output fingerprints in the uncompressed format
+  -signed        Change the uncompressed format from unsigned integers to signed
+  -json          Print the output in JSON format
+  -text          Print the output in text format
+  -plain         Print just the fingerprint in text format
+  -version       Print version information
+
+Use - as the file name to read from the standard input.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InputFormat {
+    /// WAV if the input starts with a RIFF header, raw PCM otherwise.
+    Auto,
+    Wav,
+
+    /// Signed 16-bit little endian samples without a header.
+    S16le,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Plain,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Options {
+    files: Vec<String>,
+    input_format: InputFormat,
+    sample_rate: u32,
+    channels: u16,
+
+    /// `None` processes the whole input.
+    max_duration: Option<f64>,
+    chunk_duration: Option<f64>,
+    overlap: bool,
+    algorithm: Algorithm,
+    raw: bool,
+    signed: bool,
+    output_format: OutputFormat,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            files: Vec::new(),
+            input_format: InputFormat::Auto,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            channels: DEFAULT_CHANNELS,
+            max_duration: Some(DEFAULT_MAX_DURATION),
+            chunk_duration: None,
+            overlap: false,
+            algorithm: Algorithm::default(),
+            raw: false,
+            signed: false,
+            output_format: OutputFormat::Text,
+        }
+    }
+}
+
+/// What the command line asks for.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Fingerprint(Options),
+    Help,
+    Version,
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Command::Fingerprint(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("fpcalc version {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            process::exit(2);
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let mut failed = false;
+
+    for (idx, file) in options.files.iter().enumerate() {
+        let result = if file == "-" {
+            fingerprint(io::stdin().lock(), file, idx == 0, &options, &mut out)
+        } else {
+            File::open(file)
+                .map_err(|err| format!("unable to open: {}", err).into())
+                .and_then(|input| fingerprint(input, file, idx == 0, &options, &mut out))
+        };
+
+        if let Err(err) = result {
+            let _ = out.flush();
+            eprintln!("ERROR: {}: {}", file, err);
+            failed = true;
+        }
+    }
+
+    if let Err(err) = out.flush() {
+        eprintln!("ERROR: {}", err);
+        failed = true;
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut options = Options::default();
+    let mut max_duration_set = false;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", name))
+        };
+
+        match arg.as_str() {
+            "-format" => {
+                options.input_format = match value(&arg)?.as_str() {
+                    "wav" => InputFormat::Wav,
+                    "s16le" => InputFormat::S16le,
+                    format => return Err(format!("unsupported input format {}", format)),
+                }
+            }
+            "-rate" => {
+                options.sample_rate = parse_number(&arg, &value(&arg)?)?;
+            }
+            "-channels" => {
+                options.channels = parse_number(&arg, &value(&arg)?)?;
+                if options.channels == 0 {
+                    return Err("the number of channels must be positive".to_string());
+                }
+            }
+            "-length" => {
+                let max_duration = parse_duration(&arg, &value(&arg)?)?;
+                if max_duration < 0.0 {
+                    return Err("the length can't be negative".to_string());
+                }
+                options.max_duration = Some(max_duration).filter(|&duration| duration > 0.0);
+                max_duration_set = true;
+            }
+            "-chunk" => {
+                let chunk_duration = parse_duration(&arg, &value(&arg)?)?;
+                if chunk_duration < 0.0 {
+                    return Err("the chunk duration can't be negative".to_string());
+                }
+                options.chunk_duration = Some(chunk_duration).filter(|&duration| duration > 0.0);
+            }
+            "-algorithm" => {
+                let id: u8 = parse_number(&arg, &value(&arg)?)?;
+                options.algorithm = id
+                    .checked_sub(1)
+                    .and_then(Algorithm::from_id)
+                    .ok_or_else(|| format!("invalid algorithm {}", id))?;
+            }
+            "-overlap" => options.overlap = true,
+            "-raw" => options.raw = true,
+            "-signed" => options.signed = true,
+            "-json" => options.output_format = OutputFormat::Json,
+            "-text" => options.output_format = OutputFormat::Text,
+            "-plain" => options.output_format = OutputFormat::Plain,
+            "-h" | "-help" | "--help" => return Ok(Command::Help),
+            "-v" | "-version" | "--version" => return Ok(Command::Version),
+            "-" => options.files.push(arg),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => options.files.push(arg),
+        }
+    }
+
+    if options.files.is_empty() {
+        return Err(format!("no input files\n\n{}", USAGE));
+    }
+
+    // Chunks are meant for streams, which are processed until they end unless limited.
+    if options.chunk_duration.is_some() && !max_duration_set {
+        options.max_duration = None;
+    }
+
+    Ok(Command::Fingerprint(options))
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+/// Parses a number of seconds, which unlike with `parse_number` can't be infinite or NaN.
+fn parse_duration(name: &str, value: &str) -> Result<f64, String> {
+    let duration: f64 = parse_number(name, value)?;
+    if !duration.is_finite() {
+        return Err(format!("invalid value for {}: {}", name, value));
+    }
+
+    Ok(duration)
+}
+
+/// Fingerprints an input and prints the results.
+fn fingerprint<R: Read, W: Write>(
+    input: R,
+    file: &str,
+    first: bool,
+    options: &Options,
+    out: &mut W,
+) -> Result<(), Box<dyn Error>> {
+    let mut reader = open_input(input, options)?;
+    let spec = *reader.spec();
+
+    let mut fingerprinter =
+        Fingerprinter::with_algorithm(spec.sample_rate, spec.channels, options.algorithm)?;
+    fingerprinter.set_max_duration(options.max_duration)?;
+    fingerprinter.set_chunk_duration(options.chunk_duration, options.overlap)?;
+
+    let mut printer = Printer {
+        options,
+        file,
+        first_output: first,
+        first_result: true,
+    };
+
+    while let Some(status) = reader.feed_block(&mut fingerprinter)? {
+        for chunk in fingerprinter.take_chunks() {
+            printer.print_chunk(out, &chunk)?;
+        }
+
+        // The rest of the input is only read to find its duration if the header doesn't have it.
+        if status == FeedStatus::Done
+            && (options.chunk_duration.is_some() || reader.frame_count().is_some())
+        {
+            break;
+        }
+    }
+
+    fingerprinter.finish()?;
+
+    if options.chunk_duration.is_some() {
+        for chunk in fingerprinter.take_chunks() {
+            printer.print_chunk(out, &chunk)?;
+        }
+        if printer.first_result {
+            return Err("empty fingerprint".into());
+        }
+    } else {
+        let frames = reader.frame_count().unwrap_or_else(|| reader.frames_read());
+        let duration = frames as f64 / spec.sample_rate as f64;
+        printer.print(out, None, duration, &fingerprinter.fingerprint())?;
+    }
+
+    Ok(())
+}
+
+/// Reads the header of the input, if it has one.
+fn open_input<'a, R: Read + 'a>(
+    mut input: R,
+    options: &Options,
+) -> Result<WavReader<Box<dyn Read + 'a>>, WavError> {
+    let raw_spec = WavSpec {
+        sample_rate: options.sample_rate,
+        channels: options.channels,
+        sample_format: SampleFormat::I16,
+        channel_mask: None,
+    };
+
+    match options.input_format {
+        InputFormat::Wav => WavReader::new(Box::new(input)),
+        InputFormat::S16le => WavReader::with_spec(Box::new(input), raw_spec, None),
+        InputFormat::Auto => {
+            let mut magic = Vec::with_capacity(4);
+            input.by_ref().take(4).read_to_end(&mut magic)?;
+            let is_wav = magic == b"RIFF" || magic == b"RF64";
+            let input = Box::new(Cursor::new(magic).chain(input));
+
+            if is_wav {
+                WavReader::new(input)
+            } else {
+                WavReader::with_spec(input, raw_spec, None)
+            }
+        }
+    }
+}
+
+/// Prints results in the requested format.
+struct Printer<'a> {
+    options: &'a Options,
+    file: &'a str,
+
+    /// Whether nothing was printed for previous inputs.
+    first_output: bool,
+
+    /// Whether nothing was printed for this input yet.
+    first_result: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn print_chunk<W: Write>(&mut self, out: &mut W, chunk: &Chunk) -> Result<(), Box<dyn Error>> {
+        // Inputs ending right on a chunk boundary leave an empty last chunk.
+        if chunk.fingerprint.is_empty() && !self.first_result {
+            return Ok(());
+        }
+
+        self.print(
+            out,
+            Some(chunk.start),
+            chunk.duration,
+            &chunk.as_fingerprint(),
+        )
+    }
+
+    fn print<W: Write>(
+        &mut self,
+        out: &mut W,
+        timestamp: Option<f64>,
+        duration: f64,
+        fingerprint: &Fingerprint,
+    ) -> Result<(), Box<dyn Error>> {
+        if fingerprint.items().is_empty() {
+            return Err("empty fingerprint".into());
+        }
+
+        let encoded = if self.options.raw {
+            fingerprint
+                .items()
+                .iter()
+                .map(|&item| {
+                    if self.options.signed {
+                        (item as i32).to_string()
+                    } else {
+                        item.to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        } else {
+            fingerprint.compress()?.encode()
+        };
+
+        match self.options.output_format {
+            OutputFormat::Text => {
+                if !(self.first_output && self.first_result) {
+                    writeln!(out)?;
+                }
+                if self.options.files.len() > 1 {
+                    writeln!(out, "FILE={}", self.file)?;
+                }
+                if let Some(timestamp) = timestamp {
+                    writeln!(out, "TIMESTAMP={:.2}", timestamp)?;
+                }
+                writeln!(out, "DURATION={}", duration as u64)?;
+                writeln!(out, "FINGERPRINT={}", encoded)?;
+            }
+            OutputFormat::Json => {
+                write!(out, "{{")?;
+                if let Some(timestamp) = timestamp {
+                    write!(out, "\"timestamp\": {:.2}, ", timestamp)?;
+                }
+                write!(out, "\"duration\": {:.2}, ", duration)?;
+                if self.options.raw {
+                    writeln!(out, "\"fingerprint\": [{}]}}", encoded)?;
+                } else {
+                    writeln!(out, "\"fingerprint\": \"{}\"}}", encoded)?;
+                }
+            }
+            OutputFormat::Plain => writeln!(out, "{}", encoded)?,
+        }
+
+        self.first_result = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fingerprint, parse_args, Command, InputFormat, Options, OutputFormat};
+    use chromaprint::{Algorithm, Fingerprint};
+    use std::error::Error;
+    use std::io::Cursor;
+    use test_audio::{fingerprint_samples, generate_chords, pcm16_bytes, wav_bytes};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn options(arguments: &[&str]) -> Options {
+        match parse_args(args(arguments)) {
+            Ok(Command::Fingerprint(options)) => options,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn run(input: Vec<u8>, options: &Options) -> Result<String, Box<dyn Error>> {
+        let mut out = Vec::new();
+        fingerprint(Cursor::new(input), "-", true, options, &mut out)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    fn expected(
+        samples: &[i16],
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<String, Box<dyn Error>> {
+        let items = fingerprint_samples(samples, sample_rate, channels)?;
+        Ok(Fingerprint::new(&items, Algorithm::default())
+            .compress()?
+            .encode())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let defaults = options(&["a.wav"]);
+        assert_eq!(vec!["a.wav".to_string()], defaults.files);
+        assert_eq!(InputFormat::Auto, defaults.input_format);
+        assert_eq!(Some(120.0), defaults.max_duration);
+        assert_eq!(Algorithm::Test2, defaults.algorithm);
+        assert_eq!(OutputFormat::Text, defaults.output_format);
+
+        let parsed = options(&[
+            "-format",
+            "s16le",
+            "-rate",
+            "11025",
+            "-channels",
+            "1",
+            "-length",
+            "0",
+            "-algorithm",
+            "4",
+            "-raw",
+            "-signed",
+            "-json",
+            "-",
+            "b.wav",
+        ]);
+        assert_eq!(InputFormat::S16le, parsed.input_format);
+        assert_eq!(11025, parsed.sample_rate);
+        assert_eq!(1, parsed.channels);
+        assert_eq!(None, parsed.max_duration);
+        assert_eq!(Algorithm::Test4, parsed.algorithm);
+        assert!(parsed.raw && parsed.signed);
+        assert_eq!(OutputFormat::Json, parsed.output_format);
+        assert_eq!(vec!["-".to_string(), "b.wav".to_string()], parsed.files);
+
+        let chunked = options(&["-chunk", "10", "-overlap", "a.wav"]);
+        assert_eq!(Some(10.0), chunked.chunk_duration);
+        assert!(chunked.overlap);
+        assert_eq!(None, chunked.max_duration);
+        assert_eq!(
+            Some(30.0),
+            options(&["-chunk", "10", "-length", "30", "a.wav"]).max_duration
+        );
+
+        assert_eq!(Ok(Command::Help), parse_args(args(&["-h"])));
+        assert!(parse_args(args(&[])).is_err());
+        assert!(parse_args(args(&["-algorithm", "0", "a.wav"])).is_err());
+        assert!(parse_args(args(&["-algorithm", "6", "a.wav"])).is_err());
+        assert!(parse_args(args(&["-rate", "fast", "a.wav"])).is_err());
+        assert!(parse_args(args(&["-length"])).is_err());
+        for value in &["inf", "-inf", "NaN", "1e400"] {
+            assert!(parse_args(args(&["-length", value, "a.wav"])).is_err());
+            assert!(parse_args(args(&["-chunk", value, "a.wav"])).is_err());
+        }
+        assert!(parse_args(args(&["-length", "-1", "a.wav"])).is_err());
+        assert!(parse_args(args(&["-chunk", "-1", "a.wav"])).is_err());
+        assert!(parse_args(args(&["-unknown", "a.wav"])).is_err());
+    }
+
+    #[test]
+    fn test_raw_input() -> Result<(), Box<dyn Error>> {
+        // A rate divisible by 4, so the chords make up exactly 10 seconds.
+        let samples = generate_chords(8000, 10);
+        let options = options(&["-rate", "8000", "-channels", "1", "-"]);
+
+        assert_eq!(
+            format!(
+                "DURATION=10\nFINGERPRINT={}\n",
+                expected(&samples, 8000, 1)?
+            ),
+            run(pcm16_bytes(&samples), &options)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wav_input() -> Result<(), Box<dyn Error>> {
+        let samples = generate_chords(22050, 12);
+        let stereo: Vec<i16> = samples.iter().flat_map(|&s| vec![s, s]).collect();
+
+        let mut file = wav_bytes(&stereo, 22050, 2);
+        // An unknown chunk with an odd size, which is padded.
+        file.splice(12..12, b"LIST\x01\0\0\0\0\0".iter().cloned());
+
+        let options = options(&["-plain", "-"]);
+        assert_eq!(
+            format!("{}\n", expected(&stereo, 22050, 2)?),
+            run(file, &options)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_length() -> Result<(), Box<dyn Error>> {
+        let samples = generate_chords(11025, 12);
+        let options = options(&["-length", "5", "-json", "-format", "wav", "-"]);
+
+        // The duration is of the whole input, the fingerprint only of the first seconds.
+        assert_eq!(
+            format!(
+                "{{\"duration\": 12.00, \"fingerprint\": \"{}\"}}\n",
+                expected(&samples[..(11025 * 5)], 11025, 1)?
+            ),
+            run(wav_bytes(&samples, 11025, 1), &options)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_raw_output() -> Result<(), Box<dyn Error>> {
+        let samples = generate_chords(11025, 10);
+
+        let items = fingerprint_samples(&samples, 11025, 1)?;
+
+        let output = run(
+            wav_bytes(&samples, 11025, 1),
+            &options(&["-raw", "-plain", "-"]),
+        )?;
+        let unsigned: Vec<u32> = output
+            .trim_end()
+            .split(',')
+            .map(|item| item.parse())
+            .collect::<Result<_, _>>()?;
+        assert_eq!(items, unsigned);
+
+        let output = run(
+            wav_bytes(&samples, 11025, 1),
+            &options(&["-raw", "-signed", "-plain", "-"]),
+        )?;
+        let signed: Vec<u32> = output
+            .trim_end()
+            .split(',')
+            .map(|item| item.parse::<i32>().map(|item| item as u32))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(items, signed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunks() -> Result<(), Box<dyn Error>> {
+        let samples = generate_chords(11025, 25);
+        let output = run(
+            wav_bytes(&samples, 11025, 1),
+            &options(&["-chunk", "10", "-"]),
+        )?;
+
+        let timestamps: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("TIMESTAMP="))
+            .collect();
+        assert_eq!(
+            vec!["TIMESTAMP=0.00", "TIMESTAMP=10.00", "TIMESTAMP=20.00"],
+            timestamps
+        );
+        assert!(output.starts_with(&format!(
+            "TIMESTAMP=0.00\nDURATION=10\nFINGERPRINT={}\n\n",
+            expected(&samples[..110_250], 11025, 1)?
+        )));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let options = options(&["-"]);
+
+        assert!(run(Vec::new(), &options).is_err());
+        assert!(run(b"RIFF\0\0\0\0AVI ".to_vec(), &options).is_err());
+        assert!(run(
+            pcm16_bytes(&[0; 100]),
+            &super::Options {
+                input_format: InputFormat::Wav,
+                ..options.clone()
+            }
+        )
+        .is_err());
+    }
+}
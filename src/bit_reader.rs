@@ -0,0 +1,66 @@
+pub struct BitReader<'a> {
+    /// Input from which stuff will be read.
+    input: &'a [u8],
+
+    /// Index of the next byte to be read from `input`.
+    input_index: usize,
+
+    /// A staging area for bits read from `input` but not yet returned.
+    buffer: u16,
+
+    /// A number between 0 and 16 indicating how many bits of `buffer` are full.
+    buffer_size: u8,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(input: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            input,
+            input_index: 0,
+            buffer: 0,
+            buffer_size: 0,
+        }
+    }
+
+    /// Reads a `bits` number of bits from the stream. Returns `None` once the input is exhausted.
+    pub fn read(&mut self, bits: u8) -> Option<u8> {
+        while self.buffer_size < bits {
+            let byte = *self.input.get(self.input_index)?;
+            self.buffer |= (byte as u16) << self.buffer_size;
+            self.buffer_size += 8;
+            self.input_index += 1;
+        }
+
+        let value = (self.buffer & ((1 << bits) - 1)) as u8;
+        self.buffer >>= bits;
+        self.buffer_size -= bits;
+
+        Some(value)
+    }
+
+    /// The number of bytes of the input which have been read, including partially read ones.
+    pub fn consumed(&self) -> usize {
+        self.input_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BitReader;
+    use bit_writer::BitWriter;
+
+    #[test]
+    fn test_read_written() {
+        let values = [1u8, 7, 0, 3, 5, 2, 6, 4];
+        let mut output = [0u8; 3];
+        let size = BitWriter::write_all_into(&values, 3, &mut output);
+
+        let mut reader = BitReader::new(&output[..size]);
+        for value in values.iter() {
+            assert_eq!(Some(*value), reader.read(3));
+        }
+
+        assert_eq!(size, reader.consumed());
+        assert_eq!(None, reader.read(3));
+    }
+}
@@ -0,0 +1,446 @@
+use algorithm::Algorithm;
+use audio_processor::validate_sample_rate;
+use chroma::freq_to_idx;
+use chroma_extractor::ChromaExtractor;
+use chroma_filter::{FILTER_COEFFICIENTS, MAX_FILTER_COEFFICIENTS};
+use classifiers::Classifiers;
+use error::Error;
+use fingerprinter::{CustomItems, Fingerprinter, MAX_FREQ, MIN_FREQ, TARGET_SAMPLE_RATE};
+
+/// The largest frame size, 16 times that of the standard algorithms.
+pub const MAX_FRAME_SIZE: usize = 1 << 16;
+
+/// The settings of the fingerprinting pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Config {
+    /// The algorithm the other settings started from, which fingerprints are labelled with.
+    pub algorithm: Algorithm,
+
+    /// The sample rate audio is resampled to before the FFT.
+    pub target_sample_rate: u32,
+    pub frame_size: usize,
+
+    /// Number of samples between the starts of two consecutive frames.
+    pub hop_size: usize,
+    pub min_freq: u32,
+    pub max_freq: u32,
+    pub filter_coefficients: Vec<f64>,
+    pub classifiers: Classifiers,
+}
+
+impl Config {
+    /// The settings of a standard algorithm.
+    pub fn new(algorithm: Algorithm) -> Config {
+        Config {
+            algorithm,
+            target_sample_rate: TARGET_SAMPLE_RATE,
+            frame_size: algorithm.frame_size(),
+            hop_size: algorithm.frame_size() - algorithm.frame_overlap(),
+            min_freq: MIN_FREQ,
+            max_freq: MAX_FREQ,
+            filter_coefficients: FILTER_COEFFICIENTS.to_vec(),
+            classifiers: Classifiers::for_algorithm(algorithm),
+        }
+    }
+
+    pub fn frame_overlap(&self) -> usize {
+        self.frame_size - self.hop_size
+    }
+
+    /// Duration in seconds of the audio between the starts of two consecutive items, like
+    /// `Algorithm::item_duration`.
+    pub fn item_duration(&self) -> f64 {
+        self.hop_size as f64 / self.target_sample_rate as f64
+    }
+
+    /// Describes the items computed with these settings, unless they are those of the algorithm.
+    pub fn custom_items(&self) -> Option<CustomItems> {
+        if *self == Config::new(self.algorithm) {
+            return None;
+        }
+
+        Some(CustomItems {
+            hop_size: self.hop_size,
+            sample_rate: self.target_sample_rate,
+            words: self.classifiers.words(),
+        })
+    }
+
+    /// Duration in seconds of the audio before an item which affects it, like
+    /// `Algorithm::delay_duration`.
+    pub fn delay_duration(&self) -> f64 {
+        let items = self.filter_coefficients.len() - 1 + self.classifiers.width() - 1;
+        (items * self.hop_size + self.frame_overlap()) as f64 / self.target_sample_rate as f64
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        validate_sample_rate(self.target_sample_rate)?;
+
+        if !self.frame_size.is_power_of_two() {
+            return Err(Error::InvalidConfig(
+                "the frame size must be a power of two",
+            ));
+        }
+        if self.frame_size > MAX_FRAME_SIZE {
+            return Err(Error::InvalidConfig(
+                "the frame size can't be more than 65536",
+            ));
+        }
+        if self.hop_size == 0 || self.hop_size > self.frame_size {
+            return Err(Error::InvalidConfig(
+                "the hop size must be positive and at most the frame size",
+            ));
+        }
+
+        if self.min_freq >= self.max_freq || self.max_freq > self.target_sample_rate / 2 {
+            return Err(Error::InvalidConfig(
+                "the frequency range must be increasing and below half the target sample rate",
+            ));
+        }
+        let bin = |freq| freq_to_idx(freq, self.frame_size as u32, self.target_sample_rate);
+        if bin(self.min_freq).max(1) >= bin(self.max_freq).min(self.frame_size as u32 / 2) {
+            return Err(Error::InvalidConfig(
+                "the frequency range doesn't cover any FFT bin",
+            ));
+        }
+
+        if self.filter_coefficients.is_empty()
+            || self.filter_coefficients.len() > MAX_FILTER_COEFFICIENTS
+        {
+            return Err(Error::InvalidConfig(
+                "the chroma filter must have between 1 and 8 coefficients",
+            ));
+        }
+        if !self.filter_coefficients.iter().all(|c| c.is_finite()) {
+            return Err(Error::InvalidConfig(
+                "the chroma filter coefficients must be finite",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Creates fingerprinters, or chroma extractors, with settings other than those of the standard
+/// algorithms.
+///
+/// The settings start from those of an algorithm. Fingerprints computed with other settings
+/// carry them as `CustomItems`, can't be compared with those of the algorithm, and can't be
+/// compressed or looked up in AcoustID. The combination of settings is checked when the
+/// fingerprinter is built.
+#[derive(Debug, Clone)]
+pub struct FingerprinterBuilder {
+    config: Config,
+
+    /// Whether the hop size was set, rather than following the frame size.
+    hop_size_set: bool,
+}
+
+impl Default for FingerprinterBuilder {
+    fn default() -> FingerprinterBuilder {
+        FingerprinterBuilder::new(Algorithm::default())
+    }
+}
+
+impl FingerprinterBuilder {
+    /// Starts from the settings of `algorithm`.
+    pub fn new(algorithm: Algorithm) -> FingerprinterBuilder {
+        FingerprinterBuilder {
+            config: Config::new(algorithm),
+            hop_size_set: false,
+        }
+    }
+
+    /// Sets the sample rate audio is resampled to before it is analysed, 11025 Hz by default.
+    pub fn target_sample_rate(mut self, sample_rate: u32) -> FingerprinterBuilder {
+        self.config.target_sample_rate = sample_rate;
+        self
+    }
+
+    /// Sets the number of samples, at the target sample rate, in each FFT frame. It must be a
+    /// power of two, at most `MAX_FRAME_SIZE`.
+    ///
+    /// Unless a hop size is set, frames keep overlapping by the same proportion as in the
+    /// algorithm.
+    pub fn frame_size(mut self, frame_size: usize) -> FingerprinterBuilder {
+        if !self.hop_size_set {
+            let algorithm = self.config.algorithm;
+            let hop_size = algorithm.frame_size() - algorithm.frame_overlap();
+            // Frame sizes too large for the product are rejected when building.
+            self.config.hop_size = frame_size
+                .checked_mul(hop_size)
+                .map_or(0, |size| size / algorithm.frame_size());
+        }
+        self.config.frame_size = frame_size;
+        self
+    }
+
+    /// Sets the number of samples, at the target sample rate, between the starts of two
+    /// consecutive frames, and so items. It must be positive and at most the frame size.
+    pub fn hop_size(mut self, hop_size: usize) -> FingerprinterBuilder {
+        self.config.hop_size = hop_size;
+        self.hop_size_set = true;
+        self
+    }
+
+    /// Sets the range of frequencies, in Hz, which are folded into the 12 pitch classes. Only
+    /// the frequencies below half the target sample rate are available.
+    pub fn frequency_range(mut self, min_freq: u32, max_freq: u32) -> FingerprinterBuilder {
+        self.config.min_freq = min_freq;
+        self.config.max_freq = max_freq;
+        self
+    }
+
+    /// Sets the weights of the consecutive chroma vectors combined into each filtered one. There
+    /// can be between 1 and 8 of them.
+    pub fn filter_coefficients(mut self, coefficients: &[f64]) -> FingerprinterBuilder {
+        self.config.filter_coefficients = coefficients.to_vec();
+        self
+    }
+
+    /// Sets the classifiers computing the bits of the items, for instance ones trained for other
+    /// kinds of audio and parsed with `Classifiers::from_str`.
+    pub fn classifiers(mut self, classifiers: Classifiers) -> FingerprinterBuilder {
+        self.config.classifiers = classifiers;
+        self
+    }
+
+    /// Creates a fingerprinter with these settings.
+    ///
+    /// # Errors
+    /// `Error::InvalidConfig` if the settings don't work together, `Error::InvalidSampleRate` if
+    /// the target sample rate is outside of the range supported for input, and the errors of
+    /// `Fingerprinter::new`.
+    pub fn build(&self, sample_rate: u32, channels: u16) -> Result<Fingerprinter, Error> {
+        self.config.validate()?;
+        Fingerprinter::with_config(sample_rate, channels, self.config.clone())
+    }
+
+    /// Creates a chroma extractor with these settings, which computes the chroma vectors
+    /// fingerprinters built with them would use.
+    ///
+    /// # Errors
+    /// The errors of `build`.
+    pub fn build_extractor(
+        &self,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<ChromaExtractor, Error> {
+        self.config.validate()?;
+        ChromaExtractor::with_config(sample_rate, channels, &self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, FingerprinterBuilder, MAX_FRAME_SIZE};
+    use algorithm::Algorithm;
+    use chroma_extractor::{ChromaExtractor, ChromaStage};
+    use classifiers::Classifiers;
+    use error::Error;
+    use fingerprint_matcher::FingerprintMatcher;
+    use fingerprinter::{CustomItems, Fingerprinter};
+    use std::error;
+    use tests;
+
+    fn fingerprint(fingerprinter: &mut Fingerprinter, samples: &[i16]) -> Result<Vec<u32>, Error> {
+        fingerprinter.feed(samples)?;
+        fingerprinter.finish()?;
+        Ok(fingerprinter.fingerprint().items().to_vec())
+    }
+
+    #[test]
+    fn test_defaults() -> Result<(), Box<dyn error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+
+        for &algorithm in &[Algorithm::Test1, Algorithm::Test2, Algorithm::Test5] {
+            let config = Config::new(algorithm);
+            assert_eq!(algorithm.item_duration(), config.item_duration());
+            assert_eq!(algorithm.delay_duration(), config.delay_duration());
+
+            let mut expected = Fingerprinter::with_algorithm(11025, 1, algorithm)?;
+            let mut built = FingerprinterBuilder::new(algorithm).build(11025, 1)?;
+            assert_eq!(algorithm, built.algorithm());
+            assert_eq!(None, built.fingerprint().custom());
+            assert_eq!(
+                fingerprint(&mut expected, &samples)?,
+                fingerprint(&mut built, &samples)?
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_frame_and_hop_size() -> Result<(), Box<dyn error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+
+        // Test5 is Test2 with smaller frames.
+        let mut expected = Fingerprinter::with_algorithm(11025, 1, Algorithm::Test5)?;
+        let mut built = FingerprinterBuilder::new(Algorithm::Test5)
+            .frame_size(2048)
+            .hop_size(1024)
+            .build(11025, 1)?;
+        assert_eq!(
+            fingerprint(&mut expected, &samples)?,
+            fingerprint(&mut built, &samples)?
+        );
+
+        // The hop follows the frame size unless it's set.
+        let builder = FingerprinterBuilder::new(Algorithm::Test2).frame_size(1024);
+        assert_eq!(341, builder.config.hop_size);
+        assert_eq!(
+            200,
+            builder
+                .clone()
+                .hop_size(200)
+                .frame_size(512)
+                .config
+                .hop_size
+        );
+
+        let mut fingerprinter = builder.build(11025, 1)?;
+        let items = fingerprint(&mut fingerprinter, &samples)?;
+        // Four times as many frames, less those used to fill the filters.
+        let frames = (samples.len() - 1024) / 341 + 1;
+        assert_eq!(frames - 4 - 15, items.len());
+
+        fingerprinter.reset();
+        fingerprinter.feed(&samples)?;
+        let drained: Vec<_> = fingerprinter.drain().collect();
+        assert_relative_eq!(341.0 / 11025.0, drained[1].timestamp);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_other_settings() -> Result<(), Box<dyn error::Error>> {
+        let samples = tests::generate_chords(22050, 10);
+        let mut expected = Fingerprinter::new(22050, 1)?;
+        let expected = fingerprint(&mut expected, &samples)?;
+
+        let builders = [
+            FingerprinterBuilder::default().target_sample_rate(22050),
+            FingerprinterBuilder::default().frequency_range(100, 2000),
+            FingerprinterBuilder::default().filter_coefficients(&[1.0, 1.0, 1.0]),
+            FingerprinterBuilder::default()
+                .classifiers(Classifiers::for_algorithm(Algorithm::Test1)),
+        ];
+        for builder in &builders {
+            let items = fingerprint(&mut builder.build(22050, 1)?, &samples)?;
+            assert!(!items.is_empty());
+            assert_ne!(expected, items);
+        }
+
+        // The target sample rate changes the duration of items.
+        let mut fingerprinter = builders[0].build(22050, 1)?;
+        fingerprinter.feed(&samples)?;
+        let drained: Vec<_> = fingerprinter.drain().collect();
+        assert_relative_eq!(1365.0 / 22050.0, drained[1].timestamp);
+
+        // A shorter filter yields frames sooner.
+        let count = |builder: FingerprinterBuilder| -> Result<usize, Error> {
+            let mut extractor: ChromaExtractor = builder.build_extractor(22050, 1)?;
+            extractor.set_stage(ChromaStage::Filtered);
+            extractor.feed(&samples)?;
+            extractor.finish()?;
+            Ok(extractor.drain().count())
+        };
+        assert_eq!(
+            count(FingerprinterBuilder::default())? + 4,
+            count(FingerprinterBuilder::default().filter_coefficients(&[1.0]))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_items() -> Result<(), Box<dyn error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let mut fingerprinter = FingerprinterBuilder::default()
+            .frame_size(1024)
+            .build(11025, 1)?;
+        fingerprint(&mut fingerprinter, &samples)?;
+
+        let fingerprint = fingerprinter.fingerprint();
+        let custom = CustomItems {
+            hop_size: 341,
+            sample_rate: 11025,
+            words: 1,
+        };
+        assert_eq!(Some(custom), fingerprint.custom());
+        assert_relative_eq!(341.0 / 11025.0, fingerprint.item_duration());
+
+        // The header can only name the algorithm, which would be wrong.
+        assert_eq!(Some(Error::CustomFingerprint), fingerprint.compress().err());
+        let owned = fingerprinter.to_owned_fingerprint();
+        assert_eq!(Some(custom), owned.custom);
+        assert_eq!(Some(Error::CustomFingerprint), owned.encode().err());
+
+        // Matches are timed with the duration of the custom items.
+        let segments = FingerprintMatcher::new().find_segments(&fingerprint, &fingerprint);
+        assert_eq!(1, segments.len());
+        assert_relative_eq!(
+            fingerprint.items().len() as f64 * 341.0 / 11025.0,
+            segments[0].duration
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        let invalid = |builder: FingerprinterBuilder| builder.build(44100, 2).err();
+        let builder = FingerprinterBuilder::default;
+
+        assert_eq!(
+            Some(Error::InvalidSampleRate(500)),
+            invalid(builder().target_sample_rate(500))
+        );
+        for &frame_size in &[3000, MAX_FRAME_SIZE * 2, usize::MAX] {
+            assert!(matches!(
+                invalid(builder().frame_size(frame_size)),
+                Some(Error::InvalidConfig(_))
+            ));
+        }
+        for &hop_size in &[0, 4097] {
+            assert!(matches!(
+                invalid(builder().hop_size(hop_size)),
+                Some(Error::InvalidConfig(_))
+            ));
+        }
+        for &(min_freq, max_freq) in &[(500, 500), (500, 100), (28, 6000), (1000, 1001)] {
+            assert!(matches!(
+                invalid(builder().frequency_range(min_freq, max_freq)),
+                Some(Error::InvalidConfig(_))
+            ));
+        }
+        assert!(matches!(
+            invalid(builder().frame_size(4)),
+            Some(Error::InvalidConfig(_))
+        ));
+        for coefficients in &[vec![], vec![1.0; 9], vec![1.0, f64::NAN]] {
+            assert!(matches!(
+                invalid(builder().filter_coefficients(coefficients)),
+                Some(Error::InvalidConfig(_))
+            ));
+        }
+        assert!(matches!(
+            builder().frequency_range(10, 11).build_extractor(44100, 2),
+            Err(Error::InvalidConfig(_))
+        ));
+
+        // The settings are only checked together, so the hop following a frame size too large
+        // to work with doesn't get in the way.
+        assert!(builder()
+            .frame_size(usize::MAX)
+            .frame_size(1024)
+            .build(44100, 2)
+            .is_ok());
+        assert!(builder().frame_size(MAX_FRAME_SIZE).build(44100, 2).is_ok());
+        assert_eq!(
+            Some(Error::InvalidChannelCount(0)),
+            builder().build(44100, 0).err()
+        );
+    }
+}
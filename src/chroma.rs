@@ -1,11 +1,19 @@
 pub struct Chroma {
     note_range: NoteRange,
+    interpolate: bool,
 }
 
 impl Chroma {
-    pub fn new(min_freq: u32, max_freq: u32, frame_size: u32, sample_rate: u32) -> Chroma {
+    pub fn new(
+        min_freq: u32,
+        max_freq: u32,
+        frame_size: u32,
+        sample_rate: u32,
+        interpolate: bool,
+    ) -> Chroma {
         Chroma {
             note_range: NoteRange::new(min_freq, max_freq, frame_size, sample_rate),
+            interpolate,
         }
     }
 
@@ -14,7 +22,26 @@ impl Chroma {
         let note_for_idx = self.note_range.notes();
 
         for idx in self.note_range.min_idx..self.note_range.max_idx {
-            notes[note_for_idx[idx as usize] as usize] += frame[idx as usize];
+            let idx = idx as usize;
+            let note = note_for_idx[idx] as usize;
+            let energy = frame[idx];
+
+            if self.interpolate {
+                // Splits the energy between this note and the closest neighbouring one.
+                let frac = self.note_range.notes_frac[idx];
+                let (other_note, a) = if frac < 0.5 {
+                    ((note + 11) % 12, 0.5 + frac)
+                } else if frac > 0.5 {
+                    ((note + 1) % 12, 1.5 - frac)
+                } else {
+                    (note, 1.0)
+                };
+
+                notes[note] += energy * a;
+                notes[other_note] += energy * (1.0 - a);
+            } else {
+                notes[note] += energy;
+            }
         }
 
         notes
@@ -26,6 +53,9 @@ struct NoteRange {
     max_idx: u32,
 
     notes: Vec<u8>,
+
+    /// The fractional part of the note at each index. Used for interpolation.
+    notes_frac: Vec<f64>,
 }
 
 impl NoteRange {
@@ -36,18 +66,21 @@ impl NoteRange {
             freq_to_idx(max_freq, frame_size, sample_rate),
         );
         let mut notes = vec![0u8; frame_size as usize];
+        let mut notes_frac = vec![0f64; frame_size as usize];
 
         for idx in min_idx..max_idx {
             let freq = idx_to_freq(idx, frame_size, sample_rate);
             let note = note_from_freq(freq);
 
-            notes[idx as usize] = note
+            notes[idx as usize] = note as u8;
+            notes_frac[idx as usize] = note - note.floor();
         }
 
         NoteRange {
             min_idx,
             max_idx,
             notes,
+            notes_frac,
         }
     }
 
@@ -62,7 +95,7 @@ impl NoteRange {
 /// * `freq` - The frequency to convert to an index.
 /// * `frame_size` - Size of an FFT frame. Returns a value in `[0, `frame_size`]`.
 /// * `sample_rate` - The maximum frequency.
-fn freq_to_idx(freq: u32, frame_size: u32, sample_rate: u32) -> u32 {
+pub fn freq_to_idx(freq: u32, frame_size: u32, sample_rate: u32) -> u32 {
     let size_per_frequency = (frame_size as f32) / (sample_rate as f32);
     (freq as f32 * size_per_frequency).round() as u32
 }
@@ -81,10 +114,10 @@ fn idx_to_freq(idx: u32, frame_size: u32, sample_rate: u32) -> f64 {
 /// Converts a frequency in Hz into a note.
 ///
 /// # Returns
-/// A value between 0 and 11. 0 corresponds to A and 11 to GSharp.
-fn note_from_freq(frequency: f64) -> u8 {
-    let octave = ((frequency as f64) / (440f64 / 16f64)).log2();
-    (12f64 * (octave - octave.floor())) as u8
+/// A value in `[0, 12)`. The integer part is the note, 0 corresponds to A and 11 to GSharp.
+fn note_from_freq(frequency: f64) -> f64 {
+    let octave = (frequency / (440f64 / 16f64)).log2();
+    12f64 * (octave - octave.floor())
 }
 
 #[cfg(test)]
@@ -100,7 +133,7 @@ mod tests {
 
     #[test]
     fn chroma_normal_a() {
-        let chroma = Chroma::new(10, 510, 256, 1000);
+        let chroma = Chroma::new(10, 510, 256, 1000, false);
         let mut frame = [0.0f64; 128];
         frame[113] = 1.0;
 
@@ -112,7 +145,7 @@ mod tests {
 
     #[test]
     fn chroma_normal_g_sharp() {
-        let chroma = Chroma::new(10, 510, 256, 1000);
+        let chroma = Chroma::new(10, 510, 256, 1000, false);
         let mut frame = [0.0f64; 128];
         frame[112] = 1.0;
 
@@ -124,7 +157,7 @@ mod tests {
 
     #[test]
     fn chroma_normal_b() {
-        let chroma = Chroma::new(10, 510, 256, 1000);
+        let chroma = Chroma::new(10, 510, 256, 1000, false);
         let mut frame = [0.0f64; 128];
         frame[64] = 1.0;
 
@@ -154,7 +187,7 @@ mod tests {
         const TARGET_SAMPLE_RATE: u32 = 11025;
 
         let expected = test_data::get_chroma_features();
-        let chroma = Chroma::new(MIN_FREQ, MAX_FREQ, FRAME_SIZE, TARGET_SAMPLE_RATE);
+        let chroma = Chroma::new(MIN_FREQ, MAX_FREQ, FRAME_SIZE, TARGET_SAMPLE_RATE, false);
 
         let features: Vec<_> = fft_frames
             .into_iter()
@@ -163,4 +196,15 @@ mod tests {
 
         assert_eq!(expected, features);
     }
+
+    #[test]
+    fn chroma_interpolated_keeps_energy() {
+        let chroma = Chroma::new(10, 510, 256, 1000, true);
+        let mut frame = [0.0f64; 128];
+        frame[113] = 1.0;
+
+        let notes = chroma.handle_frame(&frame);
+        assert_ulps_eq!(1.0, notes.iter().sum::<f64>());
+        assert!(notes[0] > 0.5);
+    }
 }
@@ -0,0 +1,423 @@
+use algorithm::Algorithm;
+use audio_processor::AudioProcessor;
+use builder::Config;
+use chroma::Chroma;
+use chroma_filter::ChromaFilter;
+use chroma_normalize::normalize_vector;
+use error::Error;
+use fft::Fft;
+use sample::Sample;
+use silence_remover::SilenceRemover;
+use std::vec::Drain;
+
+/// How far chroma vectors are processed before a `ChromaExtractor` returns them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaStage {
+    /// The energy of each of the 12 pitch classes in a frame of the spectrum.
+    Raw,
+
+    /// Raw vectors smoothed over 5 consecutive frames.
+    Filtered,
+
+    /// Filtered vectors scaled to unit length, which is what fingerprints are computed from.
+    Normalized,
+}
+
+/// A 12-bin chroma vector, starting at the pitch class A.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChromaFrame {
+    /// Position of the frame among the frames of its stage computed from the input.
+    pub index: usize,
+
+    /// Start of the audio the frame is computed from, in seconds from the start of the input.
+    pub timestamp: f64,
+
+    pub chroma: [f64; 12],
+}
+
+/// Computes the chromagram of audio, the features fingerprints are computed from.
+///
+/// Frames cover `Algorithm::frame_size` samples at 11025 Hz and start
+/// `Algorithm::item_duration` seconds apart. Filtering combines 5 raw frames, so there are 4 fewer
+/// filtered or normalized frames than raw ones. A `FingerprinterBuilder` creates extractors with
+/// other settings.
+pub struct ChromaExtractor {
+    algorithm: Algorithm,
+
+    /// Whether `finish` has been called since the last reset.
+    finished: bool,
+    audio_processor: AudioProcessor,
+    pipeline: Pipeline,
+}
+
+/// The stages following resampling.
+struct Pipeline {
+    stage: ChromaStage,
+    target_sample_rate: u32,
+    item_duration: f64,
+
+    /// Only present when leading silence is removed.
+    silence_remover: Option<SilenceRemover>,
+    fft: Fft,
+    chroma: Chroma,
+    chroma_filter: ChromaFilter,
+
+    /// Number of frames computed so far.
+    frame_count: usize,
+    frames: Vec<ChromaFrame>,
+}
+
+impl ChromaExtractor {
+    /// Creates an extractor of normalized chroma vectors, with the settings of the default
+    /// algorithm.
+    ///
+    /// # Errors
+    /// The errors of `Fingerprinter::new`.
+    pub fn new(sample_rate: u32, channels: u16) -> Result<ChromaExtractor, Error> {
+        ChromaExtractor::with_algorithm(sample_rate, channels, Algorithm::default())
+    }
+
+    /// Creates an extractor of normalized chroma vectors, with the frame size, overlap, note
+    /// interpolation and silence removal of `algorithm`. `FingerprinterBuilder::build_extractor`
+    /// creates extractors with other settings.
+    pub fn with_algorithm(
+        sample_rate: u32,
+        channels: u16,
+        algorithm: Algorithm,
+    ) -> Result<ChromaExtractor, Error> {
+        ChromaExtractor::with_config(sample_rate, channels, &Config::new(algorithm))
+    }
+
+    /// Creates an extractor with settings which have been validated.
+    pub(crate) fn with_config(
+        sample_rate: u32,
+        channels: u16,
+        config: &Config,
+    ) -> Result<ChromaExtractor, Error> {
+        if channels == 0 {
+            return Err(Error::InvalidChannelCount(channels));
+        }
+        let algorithm = config.algorithm;
+        let target_sample_rate = config.target_sample_rate;
+
+        Ok(ChromaExtractor {
+            algorithm,
+            finished: false,
+            audio_processor: AudioProcessor::new(target_sample_rate, sample_rate, channels)?,
+            pipeline: Pipeline {
+                stage: ChromaStage::Normalized,
+                target_sample_rate,
+                item_duration: config.item_duration(),
+                silence_remover: if algorithm.remove_silence() {
+                    Some(SilenceRemover::new(algorithm.silence_threshold()))
+                } else {
+                    None
+                },
+                fft: Fft::new(config.frame_size, config.frame_overlap()),
+                chroma: Chroma::new(
+                    config.min_freq,
+                    config.max_freq,
+                    config.frame_size as u32,
+                    target_sample_rate,
+                    algorithm.interpolate(),
+                ),
+                chroma_filter: ChromaFilter::new(&config.filter_coefficients),
+                frame_count: 0,
+                frames: Vec::new(),
+            },
+        })
+    }
+
+    /// Chooses how far the returned vectors are processed. This must be called before feeding
+    /// any samples.
+    pub fn set_stage(&mut self, stage: ChromaStage) {
+        self.pipeline.stage = stage;
+    }
+
+    pub fn stage(&self) -> ChromaStage {
+        self.pipeline.stage
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Enables or disables skipping the leading silence of the input, like
+    /// `Fingerprinter::set_silence_threshold`. This must be called before feeding any samples.
+    pub fn set_silence_threshold(&mut self, threshold: Option<i32>) {
+        self.pipeline.silence_remover = threshold.map(SilenceRemover::new);
+    }
+
+    /// Number of leading silent samples, at the target sample rate, which were skipped.
+    pub fn removed_samples(&self) -> usize {
+        self.pipeline.removed_samples()
+    }
+
+    /// Duration in seconds of the leading silence which was skipped.
+    pub fn removed_duration(&self) -> f64 {
+        self.pipeline.removed_duration()
+    }
+
+    /// Feeds interleaved samples into the extractor. The frames they complete are returned by
+    /// `drain`.
+    ///
+    /// # Errors
+    /// `Error::AlreadyFinished` if `finish` was called since the extractor was created, reset or
+    /// started.
+    pub fn feed<S: Sample>(&mut self, raw_pcm: &[S]) -> Result<(), Error> {
+        if self.finished {
+            return Err(Error::AlreadyFinished);
+        }
+
+        self.process(raw_pcm);
+        Ok(())
+    }
+
+    /// Processes the audio still buffered. Afterwards, nothing can be fed until the extractor is
+    /// reset or started again.
+    ///
+    /// # Errors
+    /// `Error::AlreadyFinished` if `finish` was already called.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if self.finished {
+            return Err(Error::AlreadyFinished);
+        }
+        self.finished = true;
+
+        self.flush();
+        Ok(())
+    }
+
+    /// Removes the frames computed so far and returns them.
+    pub fn drain(&mut self) -> Drain<'_, ChromaFrame> {
+        self.pipeline.frames.drain(..)
+    }
+
+    /// Discards all audio fed so far to start over with a new input of the same format.
+    pub fn reset(&mut self) {
+        self.audio_processor.reset();
+        self.pipeline.reset();
+        self.finished = false;
+    }
+
+    /// Like `reset`, but for an input with a possibly different format.
+    ///
+    /// # Errors
+    /// The errors of `Fingerprinter::start`, in which case the extractor is left unchanged.
+    pub fn start(&mut self, sample_rate: u32, channels: u16) -> Result<(), Error> {
+        if channels == 0 {
+            return Err(Error::InvalidChannelCount(channels));
+        }
+        self.audio_processor.start(sample_rate, channels)?;
+
+        self.reset();
+        Ok(())
+    }
+
+    /// Feeds samples without checking whether the extractor has finished.
+    pub(crate) fn process<S: Sample>(&mut self, raw_pcm: &[S]) {
+        let pipeline = &mut self.pipeline;
+        self.audio_processor
+            .feed(raw_pcm, |samples| pipeline.handle_resampled(&samples));
+    }
+
+    /// Processes the audio buffered by the resampler, without finishing.
+    pub(crate) fn flush(&mut self) {
+        if let Some(last_samples) = self.audio_processor.flush() {
+            self.pipeline.handle_resampled(&last_samples);
+        }
+    }
+}
+
+impl Pipeline {
+    fn removed_samples(&self) -> usize {
+        self.silence_remover
+            .as_ref()
+            .map_or(0, |silence_remover| silence_remover.removed())
+    }
+
+    fn removed_duration(&self) -> f64 {
+        self.removed_samples() as f64 / self.target_sample_rate as f64
+    }
+
+    fn reset(&mut self) {
+        if let Some(ref mut silence_remover) = self.silence_remover {
+            silence_remover.reset();
+        }
+        self.fft.reset();
+        self.chroma_filter.reset();
+        self.frame_count = 0;
+        self.frames.clear();
+    }
+
+    fn handle_resampled(&mut self, samples: &[f32]) {
+        let samples = match self.silence_remover {
+            Some(ref mut silence_remover) => silence_remover.process(samples),
+            None => samples,
+        };
+        // Frames only start once the silence has ended, so this doesn't change while they are
+        // computed.
+        let silence = self.removed_duration();
+
+        let Pipeline {
+            stage,
+            item_duration,
+            ref mut fft,
+            ref chroma,
+            ref mut chroma_filter,
+            ref mut frame_count,
+            ref mut frames,
+            ..
+        } = *self;
+
+        fft.consume(samples, |frame| {
+            let features = chroma.handle_frame(&frame);
+            let chroma = match stage {
+                ChromaStage::Raw => features,
+                ChromaStage::Filtered => match chroma_filter.handle_features(features) {
+                    Some(filtered) => filtered,
+                    None => return,
+                },
+                ChromaStage::Normalized => match chroma_filter.handle_features(features) {
+                    Some(filtered) => normalize_vector(filtered),
+                    None => return,
+                },
+            };
+
+            frames.push(ChromaFrame {
+                index: *frame_count,
+                timestamp: silence + *frame_count as f64 * item_duration,
+                chroma,
+            });
+            *frame_count += 1;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChromaExtractor, ChromaFrame, ChromaStage};
+    use algorithm::Algorithm;
+    use chroma_normalize::euclidean_norm;
+    use error::Error;
+    use sample::{Sample, I24};
+    use std::path::PathBuf;
+    use tests;
+
+    fn extract<S: Sample>(stage: ChromaStage, samples: &[S]) -> Result<Vec<ChromaFrame>, Error> {
+        let mut extractor = ChromaExtractor::new(44100, 1)?;
+        extractor.set_stage(stage);
+        extractor.feed(samples)?;
+        extractor.finish()?;
+
+        Ok(extractor.drain().collect())
+    }
+
+    #[test]
+    fn test_stages() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::load_audio_file(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw"),
+        )?;
+
+        let raw = extract(ChromaStage::Raw, &samples)?;
+        let filtered = extract(ChromaStage::Filtered, &samples)?;
+        let normalized = extract(ChromaStage::Normalized, &samples)?;
+
+        assert_eq!(raw.len(), filtered.len() + 4);
+        assert_eq!(filtered.len(), normalized.len());
+
+        let item_duration = Algorithm::default().item_duration();
+        for (idx, frame) in raw.iter().enumerate() {
+            assert_eq!(idx, frame.index);
+            assert_relative_eq!(idx as f64 * item_duration, frame.timestamp);
+        }
+
+        // Filtering weights 5 raw frames by 0.25, 0.75, 1, 0.75 and 0.25.
+        let expected = raw[10].chroma[3] * 0.25
+            + raw[11].chroma[3] * 0.75
+            + raw[12].chroma[3]
+            + raw[13].chroma[3] * 0.75
+            + raw[14].chroma[3] * 0.25;
+        assert_relative_eq!(expected, filtered[10].chroma[3], max_relative = 1e-12);
+
+        for (filtered, normalized) in filtered.iter().zip(&normalized) {
+            let norm = euclidean_norm(&filtered.chroma);
+            if norm > 0.01 {
+                assert_relative_eq!(1.0, euclidean_norm(&normalized.chroma), epsilon = 1e-9);
+                assert_relative_eq!(
+                    filtered.chroma[0] / norm,
+                    normalized.chroma[0],
+                    epsilon = 1e-9
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_precision() -> Result<(), Box<dyn std::error::Error>> {
+        // Chords which are lost when rounded to 16 bits.
+        let samples: Vec<I24> = tests::generate_chords(44100, 5)
+            .into_iter()
+            .map(|sample| I24::from_i32(sample as i32 / 512))
+            .collect();
+        assert!(samples.iter().all(|sample| sample.to_i16() == 0));
+
+        let frames = extract(ChromaStage::Raw, &samples)?;
+        assert!(!frames.is_empty());
+        assert!(frames
+            .iter()
+            .all(|frame| frame.chroma.iter().any(|&energy| energy > 0.0)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_input() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(44100, 10);
+        let expected = extract(ChromaStage::Normalized, &samples)?;
+
+        let mut extractor = ChromaExtractor::new(44100, 1)?;
+        let mut frames = Vec::new();
+        for chunk in samples.chunks(3000) {
+            extractor.feed(chunk)?;
+            frames.extend(extractor.drain());
+        }
+        extractor.finish()?;
+        frames.extend(extractor.drain());
+        assert_eq!(expected, frames);
+
+        assert_eq!(Err(Error::AlreadyFinished), extractor.feed(&samples));
+
+        extractor.reset();
+        extractor.feed(&samples)?;
+        extractor.finish()?;
+        assert_eq!(expected, extractor.drain().collect::<Vec<_>>());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_silence() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let mut padded = vec![0i16; 11025 * 2];
+        padded.extend_from_slice(&samples);
+
+        let mut extractor = ChromaExtractor::with_algorithm(11025, 1, Algorithm::Test4)?;
+        extractor.set_stage(ChromaStage::Raw);
+        extractor.feed(&samples)?;
+        extractor.finish()?;
+        let start = extractor.drain().next().unwrap().timestamp;
+        assert_eq!(extractor.removed_duration(), start);
+
+        extractor.reset();
+        extractor.feed(&padded)?;
+        extractor.finish()?;
+        let padded_start = extractor.drain().next().unwrap().timestamp;
+        assert_eq!(extractor.removed_duration(), padded_start);
+        assert_relative_eq!(2.0, padded_start - start, epsilon = 1e-3);
+
+        Ok(())
+    }
+}
@@ -1,32 +1,44 @@
 pub const FILTER_COEFFICIENTS: [f64; 5] = [0.25, 0.75, 1.0, 0.75, 0.25];
 
+/// The largest number of coefficients, which is the number of frames kept.
+pub const MAX_FILTER_COEFFICIENTS: usize = 8;
+
 pub struct ChromaFilter {
-    filter_coefficients: &'static [f64],
-    buffer: [[f64; 12]; 8],
+    filter_coefficients: Vec<f64>,
+    buffer: [[f64; 12]; MAX_FILTER_COEFFICIENTS],
     buffer_offset: usize,
     buffer_size: usize,
 }
 
 impl ChromaFilter {
-    pub fn new(filter_coefficients: &'static [f64]) -> ChromaFilter {
+    pub fn new(filter_coefficients: &[f64]) -> ChromaFilter {
         ChromaFilter {
-            filter_coefficients,
-            buffer: [[0f64; 12]; 8],
+            filter_coefficients: filter_coefficients.to_vec(),
+            buffer: [[0f64; 12]; MAX_FILTER_COEFFICIENTS],
             buffer_offset: 0,
             buffer_size: 1,
         }
     }
 
+    /// Forgets the previous frames.
+    pub fn reset(&mut self) {
+        self.buffer_offset = 0;
+        self.buffer_size = 1;
+    }
+
     pub fn handle_features(&mut self, features: [f64; 12]) -> Option<[f64; 12]> {
         self.buffer[self.buffer_offset] = features;
-        self.buffer_offset = (self.buffer_offset + 1) % 8;
+        self.buffer_offset = (self.buffer_offset + 1) % MAX_FILTER_COEFFICIENTS;
         if self.buffer_size >= self.filter_coefficients.len() {
-            let offset = (self.buffer_offset + 8 - self.filter_coefficients.len()) % 8;
+            let offset = (self.buffer_offset + MAX_FILTER_COEFFICIENTS
+                - self.filter_coefficients.len())
+                % MAX_FILTER_COEFFICIENTS;
             let mut result = [0.0f64; 12];
 
             for i in 0..12 {
                 for j in 0..self.filter_coefficients.len() {
-                    result[i] += self.buffer[(offset + j) % 8][i] * self.filter_coefficients[j];
+                    result[i] += self.buffer[(offset + j) % MAX_FILTER_COEFFICIENTS][i]
+                        * self.filter_coefficients[j];
                 }
             }
 
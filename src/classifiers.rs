@@ -1,74 +1,531 @@
-use filter::Filter;
-use quantizer::Quantizer;
-
-pub type Classifier = (Filter, Quantizer);
-pub type Classifiers = [Classifier; 16];
-
-pub fn get_default_classifier() -> Classifiers {
-    [
-        (
-            Filter::new(0, 4, 3, 15),
-            Quantizer::new(1.98215, 2.35817, 2.63523),
-        ),
-        (
-            Filter::new(4, 4, 6, 15),
-            Quantizer::new(-1.03809, -0.651211, -0.282167),
-        ),
-        (
-            Filter::new(1, 0, 4, 16),
-            Quantizer::new(-0.298702, 0.119262, 0.558497),
-        ),
-        (
-            Filter::new(3, 8, 2, 12),
-            Quantizer::new(-0.105439, 0.0153946, 0.135898),
-        ),
-        (
-            Filter::new(3, 4, 4, 8),
-            Quantizer::new(-0.142891, 0.0258736, 0.200632),
-        ),
-        (
-            Filter::new(4, 0, 3, 5),
-            Quantizer::new(-0.826319, -0.590612, -0.368214),
-        ),
-        (
-            Filter::new(1, 2, 2, 9),
-            Quantizer::new(-0.557409, -0.233035, 0.0534525),
-        ),
-        (
-            Filter::new(2, 7, 3, 4),
-            Quantizer::new(-0.0646826, 0.00620476, 0.0784847),
-        ),
-        (
-            Filter::new(2, 6, 2, 16),
-            Quantizer::new(-0.192387, -0.029699, 0.215855),
-        ),
-        (
-            Filter::new(2, 1, 3, 2),
-            Quantizer::new(-0.0397818, -0.00568076, 0.0292026),
-        ),
-        (
-            Filter::new(5, 10, 1, 15),
-            Quantizer::new(-0.53823, -0.369934, -0.190235),
-        ),
-        (
-            Filter::new(3, 6, 2, 10),
-            Quantizer::new(-0.124877, 0.0296483, 0.139239),
-        ),
-        (
-            Filter::new(2, 1, 1, 14),
-            Quantizer::new(-0.101475, 0.0225617, 0.231971),
-        ),
-        (
-            Filter::new(3, 5, 6, 4),
-            Quantizer::new(-0.0799915, -0.00729616, 0.063262),
-        ),
-        (
-            Filter::new(1, 9, 2, 12),
-            Quantizer::new(-0.272556, 0.019424, 0.302559),
-        ),
-        (
-            Filter::new(3, 4, 2, 14),
-            Quantizer::new(-0.164292, -0.0321188, 0.0846339),
-        ),
-    ]
+use algorithm::Algorithm;
+use error::Error;
+use fingerprint_calculator::MAX_FILTER_WIDTH;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::slice;
+use std::str::FromStr;
+
+/// Number of chroma bins, the height of the image filters are applied to.
+const CHROMA_BINS: usize = 12;
+
+/// Each classifier computes 2 bits of the 32-bit words items are made of.
+pub const CLASSIFIERS_PER_WORD: usize = 16;
+
+/// Computes 2 bits of each fingerprint item, by applying a Haar-like filter to the last chroma
+/// vectors and quantizing its output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Classifier {
+    /// The shape of the filter, from 0 to 5 like in Chromaprint. Type 0 sums a rectangle, the
+    /// others compare the halves, quadrants or thirds of one.
+    pub filter_type: u8,
+
+    /// First chroma bin covered by the filter.
+    pub y: usize,
+
+    /// Number of chroma bins covered by the filter.
+    pub height: usize,
+
+    /// Number of consecutive chroma vectors covered by the filter.
+    pub width: usize,
+
+    /// Filter outputs below the first threshold are quantized to 0, below the second to 1, below
+    /// the third to 2 and the others to 3.
+    pub thresholds: [f64; 3],
+}
+
+const fn classifier(
+    filter_type: u8,
+    y: usize,
+    height: usize,
+    width: usize,
+    thresholds: [f64; 3],
+) -> Classifier {
+    Classifier {
+        filter_type,
+        y,
+        height,
+        width,
+        thresholds,
+    }
+}
+
+impl Classifier {
+    /// Checks that the filter fits in the image and the thresholds are ordered.
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.filter_type > 5 {
+            return Err("the filter type must be between 0 and 5");
+        }
+        if self.height == 0 || self.width == 0 {
+            return Err("the filter must be at least 1 by 1");
+        }
+        if self.y >= CHROMA_BINS || self.height > CHROMA_BINS - self.y {
+            return Err("the filter must fit in the 12 chroma bins");
+        }
+        if self.width > MAX_FILTER_WIDTH {
+            return Err("the filter can't cover more than 256 chroma vectors");
+        }
+
+        let [t0, t1, t2] = self.thresholds;
+        if !self.thresholds.iter().all(|t| t.is_finite()) || t0 > t1 || t1 > t2 {
+            return Err("the thresholds must be finite and increasing");
+        }
+
+        Ok(())
+    }
+}
+
+/// The classifiers computing the bits of fingerprint items, from the most significant bits of
+/// the items to the least significant ones.
+///
+/// The standard algorithms use 16 classifiers, for 32-bit items, but there can be any number of
+/// them. Items of more than 16 classifiers are made of several 32-bit words, the first word
+/// holding the bits of the first 16 classifiers, the second word those of the next 16 and so on.
+/// The classifiers can be parsed from text, one per line, as the filter type, y, height, width
+/// and the 3 thresholds separated by whitespace. Empty lines and lines starting with
+/// `#` are skipped. With the `serde` feature, they can also be deserialized from a list of
+/// `Classifier`s, for instance in JSON.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "Vec<Classifier>", into = "Vec<Classifier>")
+)]
+pub struct Classifiers(Vec<Classifier>);
+
+impl Classifiers {
+    /// Checks the classifiers and groups them.
+    ///
+    /// # Errors
+    /// `Error::InvalidClassifier` with the position of the first classifier whose filter doesn't
+    /// fit in the chroma image or whose thresholds are out of order, and `Error::InvalidConfig`
+    /// if there are no classifiers.
+    pub fn new(classifiers: Vec<Classifier>) -> Result<Classifiers, Error> {
+        if classifiers.is_empty() {
+            return Err(Error::InvalidConfig("there must be at least 1 classifier"));
+        }
+        for (idx, classifier) in classifiers.iter().enumerate() {
+            classifier
+                .validate()
+                .map_err(|reason| Error::InvalidClassifier(idx, reason))?;
+        }
+
+        Ok(Classifiers(classifiers))
+    }
+
+    /// The classifiers of a standard algorithm. `Test1` has its own, the other algorithms share
+    /// the same ones.
+    pub fn for_algorithm(algorithm: Algorithm) -> Classifiers {
+        let classifiers = match algorithm {
+            Algorithm::Test1 => TEST1_CLASSIFIERS,
+            _ => DEFAULT_CLASSIFIERS,
+        };
+        Classifiers(classifiers.to_vec())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Classifier> {
+        self.0.iter()
+    }
+
+    /// Number of bits of each item which are computed. The others, at the top of the last word,
+    /// are 0.
+    pub fn bits(&self) -> usize {
+        self.0.len() * 2
+    }
+
+    /// Number of 32-bit words each item is made of.
+    pub fn words(&self) -> usize {
+        self.0.len().div_ceil(CLASSIFIERS_PER_WORD)
+    }
+
+    /// Number of consecutive chroma vectors covered by the widest filter, which each item is
+    /// computed from.
+    pub(crate) fn width(&self) -> usize {
+        self.0
+            .iter()
+            .map(|classifier| classifier.width)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+impl TryFrom<Vec<Classifier>> for Classifiers {
+    type Error = Error;
+
+    /// Like `Classifiers::new`.
+    fn try_from(classifiers: Vec<Classifier>) -> Result<Classifiers, Error> {
+        Classifiers::new(classifiers)
+    }
+}
+
+impl From<Classifiers> for Vec<Classifier> {
+    fn from(classifiers: Classifiers) -> Vec<Classifier> {
+        classifiers.0
+    }
+}
+
+impl FromStr for Classifiers {
+    type Err = Error;
+
+    /// Parses the text format described in `Classifiers`.
+    ///
+    /// # Errors
+    /// `Error::MalformedClassifier` with the line number, counted from 1, of a line which doesn't
+    /// hold 7 numbers, and the errors of `Classifiers::new`.
+    fn from_str(text: &str) -> Result<Classifiers, Error> {
+        let mut classifiers = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let classifier = parse_classifier(line).ok_or(Error::MalformedClassifier(idx + 1))?;
+            classifiers.push(classifier);
+        }
+
+        Classifiers::new(classifiers)
+    }
+}
+
+impl fmt::Display for Classifiers {
+    /// Writes the classifiers in the text format read by `from_str`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for classifier in &self.0 {
+            let [t0, t1, t2] = classifier.thresholds;
+            writeln!(
+                f,
+                "{} {} {} {} {} {} {}",
+                classifier.filter_type,
+                classifier.y,
+                classifier.height,
+                classifier.width,
+                t0,
+                t1,
+                t2
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_classifier(line: &str) -> Option<Classifier> {
+    let mut fields = line.split_whitespace();
+    let mut next = || fields.next();
+
+    let filter_type = next()?.parse().ok()?;
+    let y = next()?.parse().ok()?;
+    let height = next()?.parse().ok()?;
+    let width = next()?.parse().ok()?;
+    let mut thresholds = [0.0; 3];
+    for threshold in thresholds.iter_mut() {
+        *threshold = next()?.parse().ok()?;
+    }
+
+    if next().is_some() {
+        return None;
+    }
+    Some(classifier(filter_type, y, height, width, thresholds))
+}
+
+const DEFAULT_CLASSIFIERS: [Classifier; 16] = [
+    classifier(0, 4, 3, 15, [1.98215, 2.35817, 2.63523]),
+    classifier(4, 4, 6, 15, [-1.03809, -0.651211, -0.282167]),
+    classifier(1, 0, 4, 16, [-0.298702, 0.119262, 0.558497]),
+    classifier(3, 8, 2, 12, [-0.105439, 0.0153946, 0.135898]),
+    classifier(3, 4, 4, 8, [-0.142891, 0.0258736, 0.200632]),
+    classifier(4, 0, 3, 5, [-0.826319, -0.590612, -0.368214]),
+    classifier(1, 2, 2, 9, [-0.557409, -0.233035, 0.0534525]),
+    classifier(2, 7, 3, 4, [-0.0646826, 0.00620476, 0.0784847]),
+    classifier(2, 6, 2, 16, [-0.192387, -0.029699, 0.215855]),
+    classifier(2, 1, 3, 2, [-0.0397818, -0.00568076, 0.0292026]),
+    classifier(5, 10, 1, 15, [-0.53823, -0.369934, -0.190235]),
+    classifier(3, 6, 2, 10, [-0.124877, 0.0296483, 0.139239]),
+    classifier(2, 1, 1, 14, [-0.101475, 0.0225617, 0.231971]),
+    classifier(3, 5, 6, 4, [-0.0799915, -0.00729616, 0.063262]),
+    classifier(1, 9, 2, 12, [-0.272556, 0.019424, 0.302559]),
+    classifier(3, 4, 2, 14, [-0.164292, -0.0321188, 0.0846339]),
+];
+
+const TEST1_CLASSIFIERS: [Classifier; 16] = [
+    classifier(0, 0, 3, 15, [2.10543, 2.45354, 2.69414]),
+    classifier(1, 0, 4, 14, [-0.345922, 0.0463746, 0.446251]),
+    classifier(1, 4, 4, 11, [-0.392132, 0.0291077, 0.443391]),
+    classifier(3, 0, 4, 14, [-0.192851, 0.00583535, 0.204053]),
+    classifier(2, 8, 2, 4, [-0.0771619, -0.00991999, 0.0575406]),
+    classifier(5, 6, 2, 15, [-0.710437, -0.518954, -0.330402]),
+    classifier(1, 9, 2, 16, [-0.353724, -0.0189719, 0.289768]),
+    classifier(3, 4, 2, 10, [-0.128418, -0.0285697, 0.0591791]),
+    classifier(3, 9, 2, 16, [-0.139052, -0.0228468, 0.0879723]),
+    classifier(2, 1, 3, 6, [-0.133562, 0.00669205, 0.155012]),
+    classifier(3, 3, 6, 2, [-0.0267, 0.00804829, 0.0459773]),
+    classifier(2, 8, 1, 10, [-0.0972417, 0.0152227, 0.129003]),
+    classifier(3, 4, 4, 14, [-0.141434, 0.00374515, 0.149935]),
+    classifier(5, 4, 2, 15, [-0.64035, -0.466999, -0.285493]),
+    classifier(5, 9, 2, 3, [-0.322792, -0.254258, -0.174278]),
+    classifier(2, 1, 8, 4, [-0.0741375, -0.00590933, 0.0600357]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{Classifier, Classifiers};
+    use algorithm::Algorithm;
+    use builder::FingerprinterBuilder;
+    use compare;
+    use error::Error;
+    use fingerprint_matcher::FingerprintMatcher;
+    use fingerprinter::{Fingerprint, Fingerprinter};
+    use std::error;
+    use std::str::FromStr;
+    use tests;
+
+    const TEXT: &str = "# filter type, y, height, width and thresholds
+0 4 3 15 1.98215 2.35817 2.63523
+
+  4 4 6 15\t-1.03809 -0.651211 -0.282167
+";
+
+    #[test]
+    fn test_standard() -> Result<(), Box<dyn error::Error>> {
+        let classifiers = Classifiers::for_algorithm(Algorithm::Test2);
+        assert_eq!(16, classifiers.len());
+        assert_eq!(32, classifiers.bits());
+        assert_eq!(16, classifiers.width());
+        assert_ne!(classifiers, Classifiers::for_algorithm(Algorithm::Test1));
+
+        assert_eq!(classifiers, classifiers.to_string().parse()?);
+        assert_eq!(
+            classifiers,
+            Classifiers::new(classifiers.iter().cloned().collect())?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> Result<(), Box<dyn error::Error>> {
+        let classifiers = Classifiers::from_str(TEXT)?;
+        assert_eq!(2, classifiers.len());
+        assert_eq!(
+            Some(&Classifier {
+                filter_type: 4,
+                y: 4,
+                height: 6,
+                width: 15,
+                thresholds: [-1.03809, -0.651211, -0.282167],
+            }),
+            classifiers.iter().nth(1)
+        );
+
+        for &(text, line) in &[
+            ("0 4 3 15 1 2", 1),
+            ("0 4 3 15 1 2 3\n0 4 3 15 1 2 3 4", 2),
+            ("# comment\n0 4 3 15.5 1 2 3", 2),
+            ("0 -4 3 15 1 2 3", 1),
+            ("0 4 3 15 1 2 x", 1),
+        ] {
+            assert_eq!(
+                Err(Error::MalformedClassifier(line)),
+                Classifiers::from_str(text)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validation() {
+        let valid = Classifier {
+            filter_type: 5,
+            y: 9,
+            height: 3,
+            width: 256,
+            thresholds: [-1.0, 0.0, 0.0],
+        };
+        assert_eq!(1, Classifiers::new(vec![valid; 16]).unwrap().words());
+        assert_eq!(2, Classifiers::new(vec![valid; 17]).unwrap().words());
+        assert_eq!(7, Classifiers::new(vec![valid; 100]).unwrap().words());
+
+        assert!(matches!(
+            Classifiers::new(vec![]),
+            Err(Error::InvalidConfig(_))
+        ));
+
+        let invalid = [
+            Classifier {
+                filter_type: 6,
+                ..valid
+            },
+            Classifier { y: 10, ..valid },
+            Classifier {
+                y: usize::MAX,
+                height: 1,
+                ..valid
+            },
+            Classifier { height: 0, ..valid },
+            Classifier {
+                width: 257,
+                ..valid
+            },
+            Classifier {
+                thresholds: [0.0, -1.0, 1.0],
+                ..valid
+            },
+            Classifier {
+                thresholds: [0.0, 1.0, f64::INFINITY],
+                ..valid
+            },
+        ];
+        for classifier in &invalid {
+            assert!(matches!(
+                Classifiers::new(vec![valid, *classifier]),
+                Err(Error::InvalidClassifier(1, _))
+            ));
+        }
+        assert!(matches!(
+            Classifiers::from_str("0 18446744073709551615 1 1 0 1 2"),
+            Err(Error::InvalidClassifier(0, _))
+        ));
+    }
+
+    #[test]
+    fn test_fewer_classifiers() -> Result<(), Box<dyn error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let mut expected = Fingerprinter::new(11025, 1)?;
+        expected.feed(&samples)?;
+        expected.finish()?;
+
+        // The first classifiers compute the most significant bits.
+        let standard = Classifiers::for_algorithm(Algorithm::Test2);
+        let classifiers = Classifiers::new(standard.iter().take(5).cloned().collect())?;
+        assert_eq!(10, classifiers.bits());
+
+        let mut fingerprinter = FingerprinterBuilder::default()
+            .classifiers(classifiers)
+            .build(11025, 1)?;
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
+
+        let items: Vec<u32> = expected
+            .fingerprint()
+            .items()
+            .iter()
+            .map(|item| item >> 22)
+            .collect();
+        assert_eq!(items, fingerprinter.fingerprint().items());
+
+        // A narrower filter yields items sooner.
+        let narrow = Classifiers::from_str("0 0 12 4 0 1 2")?;
+        let mut fingerprinter = FingerprinterBuilder::default()
+            .classifiers(narrow)
+            .build(11025, 1)?;
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
+        assert_eq!(
+            expected.fingerprint().items().len() + 12,
+            fingerprinter.fingerprint().items().len()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_more_classifiers() -> Result<(), Box<dyn error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let mut expected = Fingerprinter::new(11025, 1)?;
+        expected.feed(&samples)?;
+        expected.finish()?;
+        let expected = expected.fingerprint().items().to_vec();
+
+        // The first 4 classifiers again fill the low bits of a second word.
+        let standard = Classifiers::for_algorithm(Algorithm::Test2);
+        let classifiers = Classifiers::new(
+            standard
+                .iter()
+                .chain(standard.iter().take(4))
+                .cloned()
+                .collect(),
+        )?;
+        assert_eq!(40, classifiers.bits());
+        assert_eq!(2, classifiers.words());
+
+        let mut fingerprinter = FingerprinterBuilder::default()
+            .classifiers(classifiers)
+            .build(11025, 1)?;
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
+
+        let fingerprint = fingerprinter.fingerprint();
+        assert_eq!(2, fingerprint.words());
+        assert_eq!(expected.len(), fingerprint.len());
+        let items: Vec<u32> = expected
+            .iter()
+            .flat_map(|&item| vec![item, item >> 24])
+            .collect();
+        assert_eq!(items, fingerprint.items());
+
+        // Items are compared whole, with offsets counted in items.
+        let shifted = Fingerprint::with_custom(
+            &fingerprint.items()[10..],
+            fingerprint.algorithm(),
+            fingerprint.custom().ok_or("standard fingerprint")?,
+        );
+        assert_eq!(
+            Some(0.0),
+            compare::bit_error_rate(&fingerprint, &shifted, 5)
+        );
+        assert_eq!(
+            Some(5),
+            compare::best_offset(&fingerprint, &shifted, 10).map(|alignment| alignment.offset)
+        );
+        assert!(compare::match_score(&fingerprint, &shifted, 0) > 0.9);
+
+        let segments = FingerprintMatcher::new().find_segments(&fingerprint, &shifted);
+        assert_eq!(1, segments.len());
+        assert_relative_eq!(5.0 * fingerprint.item_duration(), segments[0].pos1);
+        assert_relative_eq!(
+            (expected.len() - 5) as f64 * fingerprint.item_duration(),
+            segments[0].duration
+        );
+
+        // Every word of an item is drained with the item's position.
+        fingerprinter.reset();
+        fingerprinter.feed(&samples)?;
+        let drained: Vec<_> = fingerprinter.drain().collect();
+        assert_eq!((1, 1), (drained[3].index, drained[3].word));
+        assert_eq!(drained[2].timestamp, drained[3].timestamp);
+        assert_eq!(items[3], drained[3].value);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() -> Result<(), Box<dyn error::Error>> {
+        use serde_json;
+
+        let classifiers = Classifiers::from_str(TEXT)?;
+        let json = serde_json::to_string(&classifiers)?;
+        assert!(json.starts_with(
+            r#"[{"filter_type":0,"y":4,"height":3,"width":15,"thresholds":[1.98215,"#
+        ));
+        assert_eq!(classifiers, serde_json::from_str(&json)?);
+
+        let invalid = r#"[{"filter_type":0,"y":11,"height":3,"width":15,"thresholds":[0,1,2]}]"#;
+        assert!(serde_json::from_str::<Classifiers>(invalid).is_err());
+
+        Ok(())
+    }
 }
@@ -18,7 +18,11 @@ where
         self.a.len() + self.b.len()
     }
 
+    /// Reads `size` elements starting at `start_idx`, or fewer if the buffer ends before that.
     pub fn read(&self, start_idx: usize, size: usize) -> Vec<T> {
+        let start_idx = start_idx.min(self.len());
+        let size = size.min(self.len() - start_idx);
+
         if start_idx + size < self.a.len() {
             return self.a[start_idx..(start_idx + size)].to_vec();
         }
@@ -35,3 +39,27 @@ where
         from_a
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CombinedBuffer;
+
+    #[test]
+    fn test_read() {
+        let buffer = CombinedBuffer::new(&[1, 2, 3], &[4, 5]);
+
+        assert_eq!(vec![1, 2], buffer.read(0, 2));
+        assert_eq!(vec![3, 4], buffer.read(2, 2));
+        assert_eq!(vec![4, 5], buffer.read(3, 2));
+    }
+
+    #[test]
+    fn test_read_past_end() {
+        let buffer = CombinedBuffer::new(&[1, 2, 3], &[4, 5]);
+
+        assert_eq!(vec![2, 3, 4, 5], buffer.read(1, 10));
+        assert_eq!(vec![5], buffer.read(4, 10));
+        assert!(buffer.read(5, 1).is_empty());
+        assert!(buffer.read(10, 1).is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+//! Similarity measures between two raw fingerprints.
+//!
+//! Offsets are counted in items (subfingerprints). A positive offset aligns the first item of `b`
+//! with item `offset` of `a`, a negative one aligns the first item of `a` with item `-offset` of
+//! `b`. Both fingerprints are expected to be computed with the same algorithm and settings.
+//! Items made of several words are aligned by their first word, and their bit errors are counted
+//! over all of their words.
+
+use fingerprinter::Fingerprint;
+
+/// Number of top bits of an item used to find candidate offsets in `match_score`.
+const MATCH_BITS: u32 = 14;
+
+/// Number of top bits of an item used to estimate the diversity of a fingerprint.
+const UNIQ_BITS: u32 = 16;
+
+/// The result of aligning two fingerprints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Alignment {
+    /// Offset of `b` relative to `a`.
+    pub offset: i32,
+
+    /// Ratio of differing bits in the overlapping items, between 0 and 1.
+    pub bit_error_rate: f64,
+}
+
+/// Computes the ratio of differing bits between the overlapping items of `a` and `b` when `b` is
+/// shifted by `offset` items.
+///
+/// # Returns
+/// `None` if the fingerprints don't overlap at the given offset.
+pub fn bit_error_rate(a: &Fingerprint, b: &Fingerprint, offset: i32) -> Option<f64> {
+    raw_bit_error_rate(
+        a.items(),
+        b.items(),
+        offset.saturating_mul(a.words() as i32),
+    )
+}
+
+/// Finds the offset in `[-max_shift, max_shift]` with the lowest bit error rate.
+///
+/// Only offsets for which the fingerprints overlap by at least half of the shorter one are
+/// considered, so that a few coincidentally matching items at the edges can't win.
+///
+/// # Returns
+/// `None` if no offset in the range overlaps enough.
+pub fn best_offset(a: &Fingerprint, b: &Fingerprint, max_shift: usize) -> Option<Alignment> {
+    raw_best_offset(a.items(), b.items(), a.words(), max_shift)
+}
+
+/// Scores how likely two fingerprints are of the same recording, between 0 and 1. This is the
+/// algorithm used by AcoustID's `match_fingerprints`: the offset is picked by voting with the top
+/// bits of every item, and fingerprints with few distinct items are penalised.
+///
+/// # Arguments
+/// * `max_offset` - The largest offset to consider, or 0 for no limit.
+pub fn match_score(a: &Fingerprint, b: &Fingerprint, max_offset: usize) -> f64 {
+    raw_match_score(a.items(), b.items(), a.words(), max_offset)
+}
+
+pub(crate) fn raw_bit_error_rate(a: &[u32], b: &[u32], offset: i32) -> Option<f64> {
+    let (a, b) = overlap(a, b, offset);
+    if a.is_empty() {
+        return None;
+    }
+
+    Some(bit_errors(a, b) as f64 / (a.len() * 32) as f64)
+}
+
+/// Like `best_offset`, for items of `words` words.
+fn raw_best_offset(a: &[u32], b: &[u32], words: usize, max_shift: usize) -> Option<Alignment> {
+    let min_overlap = (a.len() / words).min(b.len() / words).div_ceil(2) * words;
+    let max_shift = max_shift as i32;
+    let mut best: Option<Alignment> = None;
+
+    for offset in -max_shift..=max_shift {
+        let (overlap_a, overlap_b) = overlap(a, b, offset.saturating_mul(words as i32));
+        if overlap_a.is_empty() || overlap_a.len() < min_overlap {
+            continue;
+        }
+
+        let bit_error_rate =
+            bit_errors(overlap_a, overlap_b) as f64 / (overlap_a.len() * 32) as f64;
+        if best.is_none_or(|best| bit_error_rate < best.bit_error_rate) {
+            best = Some(Alignment {
+                offset,
+                bit_error_rate,
+            });
+        }
+    }
+
+    best
+}
+
+/// Like `match_score`, for items of `words` words.
+fn raw_match_score(a: &[u32], b: &[u32], words: usize, max_offset: usize) -> f64 {
+    let match_size = 1 << MATCH_BITS;
+    let mut a_offsets = vec![None; match_size];
+    let mut b_offsets = vec![None; match_size];
+
+    for (idx, &item) in a.iter().step_by(words).enumerate() {
+        a_offsets[(item >> (32 - MATCH_BITS)) as usize] = Some(idx as i32);
+    }
+    for (idx, &item) in b.iter().step_by(words).enumerate() {
+        b_offsets[(item >> (32 - MATCH_BITS)) as usize] = Some(idx as i32);
+    }
+
+    let (a_len, b_len) = (a.len() / words, b.len() / words);
+    let mut counts = vec![0u32; a_len + b_len + 1];
+    let mut top_count = 0;
+    let mut top_offset = 0;
+
+    for (a_offset, b_offset) in a_offsets.iter().zip(b_offsets.iter()) {
+        if let (Some(a_offset), Some(b_offset)) = (*a_offset, *b_offset) {
+            let offset = a_offset - b_offset;
+            if max_offset != 0 && offset.unsigned_abs() as usize > max_offset {
+                continue;
+            }
+
+            let count_idx = (offset + b_len as i32) as usize;
+            counts[count_idx] += 1;
+            if counts[count_idx] > top_count {
+                top_count = counts[count_idx];
+                top_offset = offset;
+            }
+        }
+    }
+
+    let min_size = a_len.min(b_len) & !1;
+    // Unlike the bit errors, the diversity is estimated from everything after the offset.
+    let (a, b) = shift(a, b, top_offset * words as i32);
+    let (a_len, b_len) = (a.len() / words, b.len() / words);
+    let size = a_len.min(b_len) / 2;
+    if size == 0 || min_size == 0 {
+        return 0.0;
+    }
+
+    let a_unique = count_unique(a, words);
+    let b_unique = count_unique(b, words);
+    if (top_count as f64) < (a_unique.max(b_unique) as f64) * 0.02 {
+        return 0.0;
+    }
+
+    let diversity = f64::min(
+        f64::min(1.0, (a_unique + 10) as f64 / a_len as f64 + 0.5),
+        f64::min(1.0, (b_unique + 10) as f64 / b_len as f64 + 0.5),
+    );
+
+    let compared = size * 2 * words;
+    let bit_errors = bit_errors(&a[..compared], &b[..compared]);
+    let mut score = (size as f64 * 2.0 / min_size as f64)
+        * (1.0 - 2.0 * bit_errors as f64 / (64 * size * words) as f64);
+    score = score.max(0.0);
+
+    if diversity < 1.0 {
+        score = score.powf(8.0 - 7.0 * diversity);
+    }
+
+    score
+}
+
+/// Returns the overlapping items of `a` and `b` when `b` is shifted by `offset` items.
+pub(crate) fn overlap<'a, 'b>(a: &'a [u32], b: &'b [u32], offset: i32) -> (&'a [u32], &'b [u32]) {
+    let (a, b) = shift(a, b, offset);
+
+    let size = a.len().min(b.len());
+    (&a[..size], &b[..size])
+}
+
+/// Returns the items of `a` and `b` from the first overlapping one on, when `b` is shifted by
+/// `offset` items.
+fn shift<'a, 'b>(a: &'a [u32], b: &'b [u32], offset: i32) -> (&'a [u32], &'b [u32]) {
+    if offset >= 0 {
+        (&a[(offset as usize).min(a.len())..], b)
+    } else {
+        (a, &b[(offset.unsigned_abs() as usize).min(b.len())..])
+    }
+}
+
+/// Counts the bits which differ between two items or simhashes.
+pub fn hamming_distance(a: u32, b: u32) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Counts the differing bits of two equally long fingerprints.
+pub(crate) fn bit_errors(a: &[u32], b: &[u32]) -> u32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| hamming_distance(*a, *b))
+        .sum()
+}
+
+/// Counts the distinct top bits of the first word of every item.
+fn count_unique(fingerprint: &[u32], words: usize) -> usize {
+    let mut seen = vec![false; 1 << UNIQ_BITS];
+    let mut unique = 0;
+
+    for &item in fingerprint.iter().step_by(words) {
+        let key = (item >> (32 - UNIQ_BITS)) as usize;
+        if !seen[key] {
+            seen[key] = true;
+            unique += 1;
+        }
+    }
+
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{best_offset, bit_error_rate, hamming_distance, match_score, overlap, Alignment};
+    use algorithm::Algorithm;
+    use fingerprinter::Fingerprint;
+    use std::error::Error;
+    use test_audio::{fingerprint_samples, load_samples, ITEM_SAMPLES};
+    use tests;
+
+    #[test]
+    fn test_overlap() {
+        let a = [1, 2, 3, 4];
+        let b = [5, 6];
+
+        assert_eq!((&a[..2], &b[..]), overlap(&a, &b, 0));
+        assert_eq!((&a[3..], &b[..1]), overlap(&a, &b, 3));
+        assert_eq!((&a[..1], &b[1..]), overlap(&a, &b, -1));
+        assert!(overlap(&a, &b, 4).0.is_empty());
+        assert!(overlap(&a, &b, -2).0.is_empty());
+    }
+
+    #[test]
+    fn test_hamming_distance() {
+        assert_eq!(0, hamming_distance(0xdead_beef, 0xdead_beef));
+        assert_eq!(32, hamming_distance(0, u32::MAX));
+        assert_eq!(2, hamming_distance(0b0110, 0b0101));
+    }
+
+    #[test]
+    fn test_identical() -> Result<(), Box<dyn Error>> {
+        let raw = fingerprint_samples(&load_samples()?, 11025, 1)?;
+        let a = Fingerprint::new(&raw, Algorithm::default());
+
+        assert_eq!(Some(0.0), bit_error_rate(&a, &a, 0));
+        assert_eq!(
+            Some(Alignment {
+                offset: 0,
+                bit_error_rate: 0.0
+            }),
+            best_offset(&a, &a, 10)
+        );
+        assert_eq!(1.0, match_score(&a, &a, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_shifted() -> Result<(), Box<dyn Error>> {
+        let samples = load_samples()?;
+        let raw_a = fingerprint_samples(&samples, 11025, 1)?;
+        let raw_b = fingerprint_samples(&samples[(ITEM_SAMPLES * 5)..], 11025, 1)?;
+        let a = Fingerprint::new(&raw_a, Algorithm::default());
+        let b = Fingerprint::new(&raw_b, Algorithm::default());
+
+        let alignment = best_offset(&a, &b, 20).unwrap();
+        assert_eq!(5, alignment.offset);
+        assert!(alignment.bit_error_rate < 0.05);
+        assert_eq!(Some(alignment.bit_error_rate), bit_error_rate(&a, &b, 5));
+        assert!(bit_error_rate(&a, &b, 0).unwrap() > 0.25);
+
+        let alignment = best_offset(&b, &a, 20).unwrap();
+        assert_eq!(-5, alignment.offset);
+
+        assert!(match_score(&a, &b, 0) > 0.8);
+        assert!(match_score(&a, &b, 2) < 0.5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_noise_added() -> Result<(), Box<dyn Error>> {
+        let samples = load_samples()?;
+        // Uniform noise roughly 24 dB below the signal.
+        let mut seed = 1u32;
+        let noisy: Vec<i16> = samples
+            .iter()
+            .map(|&sample| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let noise = ((seed >> 16) % 601) as i32 - 300;
+                (sample as i32 + noise).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+            })
+            .collect();
+
+        let raw_a = fingerprint_samples(&samples, 11025, 1)?;
+        let raw_b = fingerprint_samples(&noisy, 11025, 1)?;
+        let a = Fingerprint::new(&raw_a, Algorithm::default());
+        let b = Fingerprint::new(&raw_b, Algorithm::default());
+
+        let alignment = best_offset(&a, &b, 20).unwrap();
+        assert_eq!(0, alignment.offset);
+        assert!(alignment.bit_error_rate < 0.2);
+        assert!(match_score(&a, &b, 0) > 0.5);
+        assert!(hamming_distance(a.simhash(), b.simhash()) <= 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unrelated() -> Result<(), Box<dyn Error>> {
+        let raw_a = fingerprint_samples(&load_samples()?, 11025, 1)?;
+        let raw_b = fingerprint_samples(&tests::generate_chords(11025, 16), 11025, 1)?;
+        let a = Fingerprint::new(&raw_a, Algorithm::default());
+        let b = Fingerprint::new(&raw_b, Algorithm::default());
+
+        assert!(best_offset(&a, &b, 20).unwrap().bit_error_rate > 0.3);
+        assert!(match_score(&a, &b, 0) < 0.2);
+        assert!(hamming_distance(a.simhash(), b.simhash()) > 8);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diversity_after_offset() {
+        // Distinct in both the voting and the uniqueness bits.
+        let long: Vec<u32> = (0..1010).map(|idx| idx << 18).collect();
+        let a = Fingerprint::new(&long, Algorithm::default());
+        let b = Fingerprint::new(&long[..10], Algorithm::default());
+
+        // The 10 matching items are too few for the 1010 distinct items of `a`, even though they
+        // are all that overlaps.
+        assert_eq!(0.0, match_score(&a, &b, 0));
+        assert_eq!(0.0, match_score(&b, &a, 0));
+        assert_eq!(1.0, match_score(&b, &b, 0));
+    }
+}
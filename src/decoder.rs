@@ -0,0 +1,325 @@
+use error::Error;
+use fingerprinter::{FeedStatus, Fingerprinter, OwnedFingerprint, TARGET_SAMPLE_RATE};
+use sample::I24;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+#[cfg(feature = "symphonia")]
+use symphonia::core::errors::Error as SymphoniaError;
+#[cfg(feature = "symphonia")]
+use symphonia::core::io::{MediaSource, ReadOnlySource};
+#[cfg(feature = "symphonia")]
+use symphonia_decoder::SymphoniaDecoder;
+use wav::WavError;
+#[cfg(not(feature = "symphonia"))]
+use wav::WavReader;
+
+/// A block of interleaved samples decoded by a `Decoder`, in the format they were decoded to so
+/// that wider samples keep their precision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Samples<'a> {
+    I16(&'a [i16]),
+    I24(&'a [I24]),
+    I32(&'a [i32]),
+    F32(&'a [f32]),
+    F64(&'a [f64]),
+}
+
+impl<'a> Samples<'a> {
+    /// Number of interleaved samples.
+    pub fn len(&self) -> usize {
+        match *self {
+            Samples::I16(samples) => samples.len(),
+            Samples::I24(samples) => samples.len(),
+            Samples::I32(samples) => samples.len(),
+            Samples::F32(samples) => samples.len(),
+            Samples::F64(samples) => samples.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Feeds the samples into `fingerprinter`, like `Fingerprinter::feed`.
+    pub fn feed(self, fingerprinter: &mut Fingerprinter) -> Result<FeedStatus, Error> {
+        match self {
+            Samples::I16(samples) => fingerprinter.feed(samples),
+            Samples::I24(samples) => fingerprinter.feed(samples),
+            Samples::I32(samples) => fingerprinter.feed(samples),
+            Samples::F32(samples) => fingerprinter.feed(samples),
+            Samples::F64(samples) => fingerprinter.feed(samples),
+        }
+    }
+}
+
+/// A source of decoded audio which can drive a `Fingerprinter`, see `feed_decoder`.
+///
+/// `WavReader` implements it for WAV files and, with the `symphonia` feature, `SymphoniaDecoder`
+/// for most other formats.
+pub trait Decoder {
+    /// The error returned when decoding fails. It also carries the errors of the fingerprinter
+    /// the audio is fed into.
+    type Error: From<Error>;
+
+    fn sample_rate(&self) -> u32;
+
+    /// Number of channels interleaved in the blocks.
+    fn channels(&self) -> u16;
+
+    /// Decodes the next block of interleaved samples.
+    ///
+    /// # Returns
+    /// The samples, or `None` at the end of the audio.
+    fn next_block(&mut self) -> Result<Option<Samples<'_>>, Self::Error>;
+}
+
+/// Prepares `fingerprinter` for the format of `decoder` with `Fingerprinter::start`, keeping its
+/// settings, and feeds it the decoded audio until the end or until it is done.
+/// `Fingerprinter::finish` is left to the caller.
+pub fn feed_decoder<D: Decoder + ?Sized>(
+    decoder: &mut D,
+    fingerprinter: &mut Fingerprinter,
+) -> Result<FeedStatus, D::Error> {
+    fingerprinter.start(decoder.sample_rate(), decoder.channels())?;
+
+    let mut status = FeedStatus::NeedsMore;
+    while status == FeedStatus::NeedsMore {
+        status = match decoder.next_block()? {
+            Some(samples) => samples.feed(fingerprinter)?,
+            None => break,
+        };
+    }
+
+    Ok(status)
+}
+
+/// Decodes the whole input and fingerprints it with the default algorithm.
+///
+/// Only WAV is supported, unless the `symphonia` feature is enabled. Symphonia also decodes
+/// MP3, FLAC, Ogg Vorbis, AAC and ALAC in MP4, and Matroska. Opus needs a decoder passed to
+/// `SymphoniaDecoder::with_codecs`.
+pub fn fingerprint_reader<R: Read + Send + Sync + 'static>(
+    reader: R,
+) -> Result<OwnedFingerprint, DecoderError> {
+    #[cfg(feature = "symphonia")]
+    let reader = ReadOnlySource::new(reader);
+
+    fingerprint_input(reader, None)
+}
+
+/// Like `fingerprint_reader`, using the extension of the file as a hint of its format.
+pub fn fingerprint_file<P: AsRef<Path>>(path: P) -> Result<OwnedFingerprint, DecoderError> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|extension| extension.to_str());
+
+    fingerprint_input(File::open(path)?, extension)
+}
+
+#[cfg(not(feature = "symphonia"))]
+fn fingerprint_input<R: Read>(
+    reader: R,
+    _extension: Option<&str>,
+) -> Result<OwnedFingerprint, DecoderError> {
+    fingerprint(&mut WavReader::new(reader)?)
+}
+
+#[cfg(feature = "symphonia")]
+fn fingerprint_input<S: MediaSource + 'static>(
+    source: S,
+    extension: Option<&str>,
+) -> Result<OwnedFingerprint, DecoderError> {
+    fingerprint(&mut SymphoniaDecoder::new(Box::new(source), extension)?)
+}
+
+fn fingerprint<D: Decoder>(decoder: &mut D) -> Result<OwnedFingerprint, DecoderError>
+where
+    DecoderError: From<D::Error>,
+{
+    // The format is set by `feed_decoder`.
+    let mut fingerprinter = Fingerprinter::new(TARGET_SAMPLE_RATE, 1)?;
+    feed_decoder(decoder, &mut fingerprinter)?;
+    fingerprinter.finish()?;
+
+    Ok(fingerprinter.to_owned_fingerprint())
+}
+
+/// Reasons the audio of a file could not be fingerprinted.
+#[derive(Debug)]
+pub enum DecoderError {
+    Io(io::Error),
+
+    /// The input isn't a supported WAV file.
+    Wav(WavError),
+
+    /// Symphonia couldn't decode the input.
+    #[cfg(feature = "symphonia")]
+    Symphonia(SymphoniaError),
+
+    /// The fingerprinter rejected the audio.
+    Fingerprint(Error),
+}
+
+impl fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecoderError::Io(ref err) => write!(f, "unable to read audio: {}", err),
+            DecoderError::Wav(ref err) => err.fmt(f),
+            #[cfg(feature = "symphonia")]
+            DecoderError::Symphonia(ref err) => write!(f, "unable to decode audio: {}", err),
+            DecoderError::Fingerprint(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for DecoderError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            DecoderError::Io(ref err) => Some(err),
+            DecoderError::Wav(ref err) => Some(err),
+            #[cfg(feature = "symphonia")]
+            DecoderError::Symphonia(ref err) => Some(err),
+            DecoderError::Fingerprint(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for DecoderError {
+    fn from(err: io::Error) -> DecoderError {
+        DecoderError::Io(err)
+    }
+}
+
+impl From<WavError> for DecoderError {
+    fn from(err: WavError) -> DecoderError {
+        match err {
+            WavError::Io(err) => DecoderError::Io(err),
+            WavError::Fingerprint(err) => DecoderError::Fingerprint(err),
+            err => DecoderError::Wav(err),
+        }
+    }
+}
+
+#[cfg(feature = "symphonia")]
+impl From<SymphoniaError> for DecoderError {
+    fn from(err: SymphoniaError) -> DecoderError {
+        match err {
+            SymphoniaError::IoError(err) => DecoderError::Io(err),
+            err => DecoderError::Symphonia(err),
+        }
+    }
+}
+
+impl From<Error> for DecoderError {
+    fn from(err: Error) -> DecoderError {
+        DecoderError::Fingerprint(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        feed_decoder, fingerprint_file, fingerprint_reader, Decoder, DecoderError, Samples,
+    };
+    use error::Error;
+    use fingerprinter::{FeedStatus, Fingerprinter};
+    use std::io::{Cursor, Write};
+    use tempfile;
+    use tests;
+
+    /// Yields the samples in blocks of a fixed size.
+    struct BlockDecoder {
+        samples: Vec<f32>,
+        block_size: usize,
+        position: usize,
+    }
+
+    impl Decoder for BlockDecoder {
+        type Error = Error;
+
+        fn sample_rate(&self) -> u32 {
+            11025
+        }
+
+        fn channels(&self) -> u16 {
+            1
+        }
+
+        fn next_block(&mut self) -> Result<Option<Samples<'_>>, Error> {
+            if self.position == self.samples.len() {
+                return Ok(None);
+            }
+
+            let start = self.position;
+            self.position = (start + self.block_size).min(self.samples.len());
+            Ok(Some(Samples::F32(&self.samples[start..self.position])))
+        }
+    }
+
+    #[test]
+    fn test_feed_decoder() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let mut decoder = BlockDecoder {
+            samples: samples.iter().map(|&s| s as f32 / 32768.0).collect(),
+            block_size: 1000,
+            position: 0,
+        };
+
+        let mut fingerprinter = Fingerprinter::new(44100, 2)?;
+        assert_eq!(
+            FeedStatus::NeedsMore,
+            feed_decoder(&mut decoder, &mut fingerprinter)?
+        );
+        fingerprinter.finish()?;
+        assert_eq!(
+            tests::fingerprint_samples(&samples, 11025, 1)?,
+            fingerprinter.fingerprint().items()
+        );
+
+        // Stops once the fingerprinter is done.
+        decoder.position = 0;
+        fingerprinter.set_max_duration(Some(2.0))?;
+        assert_eq!(
+            FeedStatus::Done,
+            feed_decoder(&mut decoder, &mut fingerprinter)?
+        );
+        assert_eq!(23_000, decoder.position);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_reader() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+
+        let fingerprint = fingerprint_reader(Cursor::new(tests::wav_bytes(&samples, 11025, 1)))?;
+        assert_eq!(
+            tests::fingerprint_samples(&samples, 11025, 1)?,
+            fingerprint.items
+        );
+        assert_eq!(samples.len() as u64, fingerprint.sample_count);
+        assert_eq!(11025, fingerprint.sample_rate);
+
+        let mut file = tempfile::Builder::new().suffix(".wav").tempfile()?;
+        file.write_all(&tests::wav_bytes(&samples, 11025, 1))?;
+        assert_eq!(fingerprint, fingerprint_file(file.path())?);
+
+        assert!(matches!(
+            fingerprint_file(file.path().with_extension("missing")),
+            Err(DecoderError::Io(_))
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "symphonia"))]
+    #[test]
+    fn test_fingerprint_reader_errors() {
+        assert!(matches!(
+            fingerprint_reader(Cursor::new(b"ID3\x04".to_vec())),
+            Err(DecoderError::Wav(_))
+        ));
+    }
+}
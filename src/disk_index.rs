@@ -0,0 +1,1116 @@
+use algorithm::Algorithm;
+use fingerprinter::{CustomItems, Fingerprint};
+use index::{
+    rank, terms, top_candidates, verify, Index, SearchResult, CANDIDATES_PER_RESULT,
+    DEFAULT_MAX_BIT_ERROR_RATE,
+};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const MANIFEST_FILE: &str = "MANIFEST";
+const MANIFEST_HEADER: &str = "chromaprint-index 1";
+
+const SEGMENT_MAGIC: [u8; 4] = *b"CPIS";
+const SEGMENT_VERSION: u32 = 3;
+const SEGMENT_HEADER_SIZE: u64 = 32;
+const DOC_ENTRY_SIZE: u64 = 32;
+const TERM_ENTRY_SIZE: u64 = 16;
+
+/// Number of segments of similar size merged together by `commit`.
+const MERGE_FACTOR: usize = 4;
+
+/// A fingerprint index stored in a directory, which survives process restarts.
+///
+/// The directory holds immutable segment files and a `MANIFEST` listing the live segments along
+/// with the ids deleted from each of them. Fingerprints inserted since the last `commit` are kept
+/// in memory until `commit` writes them to a new segment, and deletions are only persisted by
+/// `commit` as well.
+///
+/// Segments are grouped in tiers by their number of live fingerprints, each tier holding
+/// `MERGE_FACTOR` times more fingerprints than the previous one. Whenever a tier fills up with
+/// `MERGE_FACTOR` segments, `commit` merges them into one, so the number of segments only grows
+/// logarithmically with the size of the index. `compact` merges all segments into one. Merging
+/// drops deleted fingerprints.
+///
+/// Every segment file is laid out as follows, with all integers little endian:
+///
+/// * A 32-byte header: the magic `CPIS`, the format version, the number of fingerprints and terms
+///   (all `u32`), and the offsets of the fingerprint and term tables (both `u64`).
+/// * The items of every fingerprint.
+/// * The ids of the fingerprints containing each term.
+/// * The fingerprint table, sorted by id: id, number of items, algorithm id, hop size, sample rate
+///   and words per item of custom items (all 0 for standard items) and offset of the items.
+/// * The term table, sorted by term: term, number of ids and offset of the ids.
+///
+/// Only the tables are loaded when a segment is opened. Postings and fingerprints are read from
+/// the file as needed by searches.
+///
+/// Only one `DiskIndex` may update a directory at a time. Nothing prevents several processes from
+/// opening the same directory, and concurrent commits would overwrite each other's manifest and
+/// segments.
+pub struct DiskIndex {
+    path: PathBuf,
+    max_bit_error_rate: f64,
+    next_segment: u64,
+    segments: Vec<Segment>,
+
+    /// Fingerprints inserted since the last commit.
+    pending: Index,
+}
+
+impl DiskIndex {
+    /// Creates an empty index in `path`, creating the directory if needed.
+    ///
+    /// # Errors
+    /// Fails if the directory already contains an index.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<DiskIndex> {
+        let path = path.as_ref();
+        fs::create_dir_all(path)?;
+        if path.join(MANIFEST_FILE).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("an index already exists in {}", path.display()),
+            ));
+        }
+
+        let index = DiskIndex {
+            path: path.to_path_buf(),
+            max_bit_error_rate: DEFAULT_MAX_BIT_ERROR_RATE,
+            next_segment: 0,
+            segments: Vec::new(),
+            pending: Index::new(),
+        };
+        index.write_manifest()?;
+        Ok(index)
+    }
+
+    /// Opens the index previously created in `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<DiskIndex> {
+        let path = path.as_ref();
+        let manifest = read_manifest(&path.join(MANIFEST_FILE))?;
+
+        let mut segments = Vec::with_capacity(manifest.segments.len());
+        for (number, deleted) in manifest.segments {
+            segments.push(Segment::open(&segment_path(path, number), number, deleted)?);
+        }
+
+        Ok(DiskIndex {
+            path: path.to_path_buf(),
+            max_bit_error_rate: DEFAULT_MAX_BIT_ERROR_RATE,
+            next_segment: manifest.next_segment,
+            segments,
+            pending: Index::new(),
+        })
+    }
+
+    /// Sets the highest bit error rate, between 0 and 1, a search result can have.
+    pub fn set_max_bit_error_rate(&mut self, max_bit_error_rate: f64) {
+        self.max_bit_error_rate = max_bit_error_rate;
+    }
+
+    /// Number of fingerprints in the index, including uncommitted ones.
+    pub fn len(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| segment.live_count())
+            .sum::<usize>()
+            + self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.pending.contains(id) || self.segments.iter().any(|segment| segment.contains(id))
+    }
+
+    /// Number of segment files the committed fingerprints are spread over.
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Adds a fingerprint to the index, replacing any fingerprint previously added with `id`.
+    pub fn insert(&mut self, id: u32, fingerprint: &Fingerprint) {
+        self.remove(id);
+        self.pending.insert(id, fingerprint);
+    }
+
+    /// Removes the fingerprint added with `id`.
+    ///
+    /// # Returns
+    /// Whether the index contained the fingerprint.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let mut removed = self.pending.remove(id);
+        for segment in &mut self.segments {
+            if segment.contains(id) {
+                segment.deleted.insert(id);
+                removed = true;
+            }
+        }
+
+        removed
+    }
+
+    /// Writes the fingerprints inserted since the last commit to a new segment and persists all
+    /// deletions. Segments without any live fingerprints are deleted, and full tiers of segments
+    /// are merged.
+    pub fn commit(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let number = self.next_segment;
+            let path = segment_path(&self.path, number);
+
+            let mut postings: Vec<(u32, Vec<u32>)> = self
+                .pending
+                .postings()
+                .map(|(term, ids)| (term, ids.to_vec()))
+                .collect();
+            postings.sort_unstable_by_key(|&(term, _)| term);
+            for (_, ids) in &mut postings {
+                ids.sort_unstable();
+            }
+
+            write_segment(
+                &path,
+                self.pending
+                    .iter()
+                    .map(|(id, algorithm, custom, fingerprint)| {
+                        Ok((id, algorithm, custom, fingerprint.to_vec()))
+                    }),
+                postings.into_iter().map(Ok),
+            )?;
+
+            self.segments
+                .push(Segment::open(&path, number, BTreeSet::new())?);
+            self.next_segment += 1;
+            self.pending = Index::new();
+        }
+
+        let (live, empty) = self
+            .segments
+            .drain(..)
+            .partition(|segment| segment.live_count() > 0);
+        self.segments = live;
+        self.write_manifest()?;
+        self.remove_segment_files(empty)?;
+
+        while let Some(tier) = self.full_tier() {
+            self.merge(&tier)?;
+        }
+
+        Ok(())
+    }
+
+    /// Commits pending changes and merges all segments into one without the deleted
+    /// fingerprints.
+    pub fn compact(&mut self) -> io::Result<()> {
+        self.commit()?;
+        if self.segments.len() <= 1
+            && self
+                .segments
+                .iter()
+                .all(|segment| segment.deleted.is_empty())
+        {
+            return Ok(());
+        }
+
+        let all: Vec<usize> = (0..self.segments.len()).collect();
+        self.merge(&all)
+    }
+
+    /// Finds up to `limit` fingerprints matching `query`, best match first, like `Index::search`.
+    pub fn search(&self, query: &Fingerprint, limit: usize) -> io::Result<Vec<SearchResult>> {
+        let terms = terms(query.items());
+        let mut hits = HashMap::new();
+        self.pending.count_hits(&terms, &mut hits);
+        for segment in &self.segments {
+            segment.count_hits(&terms, &mut hits)?;
+        }
+        hits.retain(|&id, _| self.settings(id) == Some((query.algorithm(), query.custom())));
+
+        let mut results = Vec::new();
+        for (id, hits) in top_candidates(hits, limit.saturating_mul(CANDIDATES_PER_RESULT)) {
+            let fingerprint = match self.pending.get(id) {
+                Some(fingerprint) => fingerprint.to_vec(),
+                None => match self
+                    .segments
+                    .iter()
+                    .find_map(|segment| segment.live_doc(id))
+                {
+                    Some((segment, doc)) => segment.read_fingerprint(doc)?,
+                    None => continue,
+                },
+            };
+
+            results.extend(verify(id, hits, &fingerprint, query.items()));
+        }
+
+        Ok(rank(results, self.max_bit_error_rate, limit))
+    }
+
+    /// Returns the algorithm and custom settings of the live fingerprint added with `id`.
+    fn settings(&self, id: u32) -> Option<(Algorithm, Option<CustomItems>)> {
+        match self.pending.algorithm(id) {
+            Some(algorithm) => Some((algorithm, self.pending.custom(id))),
+            None => self
+                .segments
+                .iter()
+                .find_map(|segment| segment.live_doc(id))
+                .map(|(_, doc)| (doc.algorithm, doc.custom)),
+        }
+    }
+
+    /// Returns the positions of the segments of the smallest tier holding at least
+    /// `MERGE_FACTOR` segments.
+    fn full_tier(&self) -> Option<Vec<usize>> {
+        let mut tiers: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        for (idx, segment) in self.segments.iter().enumerate() {
+            tiers
+                .entry(tier(segment.live_count()))
+                .or_default()
+                .push(idx);
+        }
+
+        tiers
+            .into_values()
+            .find(|segments| segments.len() >= MERGE_FACTOR)
+    }
+
+    /// Replaces the segments at the given positions with a single segment holding their live
+    /// fingerprints.
+    fn merge(&mut self, positions: &[usize]) -> io::Result<()> {
+        let number = self.next_segment;
+        let path = segment_path(&self.path, number);
+        {
+            let sources: Vec<&Segment> = positions.iter().map(|&idx| &self.segments[idx]).collect();
+            write_segment(
+                &path,
+                sources.iter().flat_map(|&segment| {
+                    segment.live_docs().map(move |doc| {
+                        segment
+                            .read_fingerprint(doc)
+                            .map(|fingerprint| (doc.id, doc.algorithm, doc.custom, fingerprint))
+                    })
+                }),
+                MergedPostings::new(&sources),
+            )?;
+        }
+
+        let merged = Segment::open(&path, number, BTreeSet::new())?;
+        self.next_segment += 1;
+
+        let mut old = Vec::with_capacity(positions.len());
+        for (idx, segment) in std::mem::take(&mut self.segments).into_iter().enumerate() {
+            if positions.contains(&idx) {
+                old.push(segment);
+            } else {
+                self.segments.push(segment);
+            }
+        }
+        self.segments.push(merged);
+
+        self.write_manifest()?;
+        self.remove_segment_files(old)
+    }
+
+    fn write_manifest(&self) -> io::Result<()> {
+        let path = self.path.join(MANIFEST_FILE);
+        let temp_path = self.path.join(format!("{}.tmp", MANIFEST_FILE));
+
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            writeln!(writer, "{}", MANIFEST_HEADER)?;
+            writeln!(writer, "next-segment {}", self.next_segment)?;
+            for segment in &self.segments {
+                write!(writer, "segment {}", segment.number)?;
+                for id in &segment.deleted {
+                    write!(writer, " {}", id)?;
+                }
+                writeln!(writer)?;
+            }
+
+            writer.into_inner()?.sync_all()?;
+        }
+
+        // Renaming is atomic, so a crash leaves either the old or the new manifest behind.
+        fs::rename(temp_path, path)?;
+        sync_directory(&self.path)
+    }
+
+    fn remove_segment_files(&self, segments: Vec<Segment>) -> io::Result<()> {
+        for segment in segments {
+            fs::remove_file(segment_path(&self.path, segment.number))?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Manifest {
+    next_segment: u64,
+
+    /// Number of each live segment and the ids deleted from it.
+    segments: Vec<(u64, BTreeSet<u32>)>,
+}
+
+fn read_manifest(path: &Path) -> io::Result<Manifest> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    if lines.next().transpose()?.as_deref() != Some(MANIFEST_HEADER) {
+        return Err(invalid_data("unsupported manifest"));
+    }
+
+    let mut manifest = Manifest {
+        next_segment: 0,
+        segments: Vec::new(),
+    };
+    for line in lines {
+        let line = line?;
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("next-segment") => {
+                manifest.next_segment = parse_field(fields.next())?;
+            }
+            Some("segment") => {
+                let number = parse_field(fields.next())?;
+                let deleted = fields
+                    .map(|field| parse_field(Some(field)))
+                    .collect::<io::Result<_>>()?;
+                manifest.segments.push((number, deleted));
+            }
+            None => {}
+            Some(_) => return Err(invalid_data("unknown manifest entry")),
+        }
+    }
+
+    Ok(manifest)
+}
+
+fn parse_field<T: std::str::FromStr>(field: Option<&str>) -> io::Result<T> {
+    field
+        .and_then(|field| field.parse().ok())
+        .ok_or_else(|| invalid_data("malformed manifest entry"))
+}
+
+/// Persists the entries of a directory, like a renamed manifest.
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+/// Directories can't be opened as files to sync them on other platforms.
+#[cfg(not(unix))]
+fn sync_directory(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn segment_path(directory: &Path, number: u64) -> PathBuf {
+    directory.join(format!("segment-{:08}.dat", number))
+}
+
+/// Returns the tier of a segment with `live_count` fingerprints, i.e. the integer part of its
+/// logarithm in base `MERGE_FACTOR`.
+fn tier(live_count: usize) -> u32 {
+    let mut tier = 0;
+    let mut size = live_count / MERGE_FACTOR;
+    while size > 0 {
+        size /= MERGE_FACTOR;
+        tier += 1;
+    }
+
+    tier
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DocEntry {
+    id: u32,
+    len: u32,
+    algorithm: Algorithm,
+    custom: Option<CustomItems>,
+    offset: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TermEntry {
+    term: u32,
+    len: u32,
+    offset: u64,
+}
+
+struct Segment {
+    number: u64,
+    file: Mutex<File>,
+    file_len: u64,
+
+    /// Sorted by id.
+    docs: Vec<DocEntry>,
+
+    /// Sorted by term.
+    terms: Vec<TermEntry>,
+
+    deleted: BTreeSet<u32>,
+}
+
+impl Segment {
+    fn open(path: &Path, number: u64, deleted: BTreeSet<u32>) -> io::Result<Segment> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != SEGMENT_MAGIC || read_u32(&mut reader)? != SEGMENT_VERSION {
+            return Err(invalid_data("unsupported segment file"));
+        }
+
+        let doc_count = read_u32(&mut reader)?;
+        let term_count = read_u32(&mut reader)?;
+        let doc_table_offset = read_u64(&mut reader)?;
+        let term_table_offset = read_u64(&mut reader)?;
+
+        // The counts are checked against the file length before allocating the tables.
+        check_bounds(
+            doc_table_offset,
+            doc_count as u64 * DOC_ENTRY_SIZE,
+            file_len,
+        )?;
+        check_bounds(
+            term_table_offset,
+            term_count as u64 * TERM_ENTRY_SIZE,
+            file_len,
+        )?;
+
+        reader.seek(SeekFrom::Start(doc_table_offset))?;
+        let docs = (0..doc_count)
+            .map(|_| {
+                Ok(DocEntry {
+                    id: read_u32(&mut reader)?,
+                    len: read_u32(&mut reader)?,
+                    algorithm: read_algorithm(&mut reader)?,
+                    custom: read_custom(&mut reader)?,
+                    offset: read_u64(&mut reader)?,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        reader.seek(SeekFrom::Start(term_table_offset))?;
+        let terms = (0..term_count)
+            .map(|_| {
+                Ok(TermEntry {
+                    term: read_u32(&mut reader)?,
+                    len: read_u32(&mut reader)?,
+                    offset: read_u64(&mut reader)?,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        if deleted
+            .iter()
+            .any(|id| docs.binary_search_by_key(id, |doc| doc.id).is_err())
+        {
+            return Err(invalid_data("deleted fingerprint missing from segment"));
+        }
+
+        Ok(Segment {
+            number,
+            file: Mutex::new(reader.into_inner()),
+            file_len,
+            docs,
+            terms,
+            deleted,
+        })
+    }
+
+    fn live_count(&self) -> usize {
+        self.docs.len() - self.deleted.len()
+    }
+
+    fn contains(&self, id: u32) -> bool {
+        self.live_doc(id).is_some()
+    }
+
+    fn live_doc(&self, id: u32) -> Option<(&Segment, &DocEntry)> {
+        if self.deleted.contains(&id) {
+            return None;
+        }
+
+        let idx = self.docs.binary_search_by_key(&id, |doc| doc.id).ok()?;
+        Some((self, &self.docs[idx]))
+    }
+
+    fn live_docs(&self) -> impl Iterator<Item = &DocEntry> {
+        self.docs
+            .iter()
+            .filter(move |doc| !self.deleted.contains(&doc.id))
+    }
+
+    fn count_hits(&self, terms: &[u32], hits: &mut HashMap<u32, usize>) -> io::Result<()> {
+        for term in terms {
+            let entry = match self.terms.binary_search_by_key(term, |entry| entry.term) {
+                Ok(idx) => self.terms[idx],
+                Err(_) => continue,
+            };
+
+            for id in self.read_u32s(entry.offset, entry.len)? {
+                if !self.deleted.contains(&id) {
+                    *hits.entry(id).or_default() += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_fingerprint(&self, doc: &DocEntry) -> io::Result<Vec<u32>> {
+        self.read_u32s(doc.offset, doc.len)
+    }
+
+    fn read_u32s(&self, offset: u64, len: u32) -> io::Result<Vec<u32>> {
+        // A corrupt length mustn't allocate more than the file holds.
+        check_bounds(offset, len as u64 * 4, self.file_len)?;
+
+        let mut bytes = vec![0; len as usize * 4];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut bytes)?;
+        }
+
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect())
+    }
+}
+
+/// Merges the posting lists of several segments, skipping deleted fingerprints.
+///
+/// The term tables of the segments are sorted, so the posting lists are produced in term order
+/// by a k-way merge, reading only the lists of the current term from the files.
+struct MergedPostings<'a> {
+    sources: &'a [&'a Segment],
+
+    /// Position in the term table of every source.
+    cursors: Vec<usize>,
+
+    /// Next term of every source which isn't exhausted, smallest first.
+    heap: BinaryHeap<Reverse<(u32, usize)>>,
+}
+
+impl<'a> MergedPostings<'a> {
+    fn new(sources: &'a [&'a Segment]) -> MergedPostings<'a> {
+        let heap = sources
+            .iter()
+            .enumerate()
+            .filter_map(|(source, segment)| Some(Reverse((segment.terms.first()?.term, source))))
+            .collect();
+
+        MergedPostings {
+            sources,
+            cursors: vec![0; sources.len()],
+            heap,
+        }
+    }
+}
+
+impl<'a> Iterator for MergedPostings<'a> {
+    type Item = io::Result<(u32, Vec<u32>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Reverse((term, _)) = *self.heap.peek()?;
+            let mut ids = Vec::new();
+            while let Some(&Reverse((next, source))) = self.heap.peek() {
+                if next != term {
+                    break;
+                }
+                self.heap.pop();
+
+                let segment = self.sources[source];
+                let entry = segment.terms[self.cursors[source]];
+                self.cursors[source] += 1;
+                if let Some(next) = segment.terms.get(self.cursors[source]) {
+                    self.heap.push(Reverse((next.term, source)));
+                }
+
+                match segment.read_u32s(entry.offset, entry.len) {
+                    Ok(posting) => ids.extend(
+                        posting
+                            .into_iter()
+                            .filter(|id| !segment.deleted.contains(id)),
+                    ),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+
+            // A term whose fingerprints were all deleted is dropped.
+            if !ids.is_empty() {
+                ids.sort_unstable();
+                return Some(Ok((term, ids)));
+            }
+        }
+    }
+}
+
+/// Writes a segment file with the given fingerprints, which must have distinct ids, and their
+/// posting lists, which must be sorted by term and hold sorted ids.
+///
+/// Fingerprints and posting lists are written to the file one at a time, only the fingerprint
+/// and term tables are kept in memory.
+fn write_segment<F, P>(path: &Path, fingerprints: F, postings: P) -> io::Result<()>
+where
+    F: IntoIterator<Item = io::Result<(u32, Algorithm, Option<CustomItems>, Vec<u32>)>>,
+    P: IntoIterator<Item = io::Result<(u32, Vec<u32>)>>,
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&[0; SEGMENT_HEADER_SIZE as usize])?;
+    let mut offset = SEGMENT_HEADER_SIZE;
+
+    let mut docs = Vec::new();
+    for fingerprint in fingerprints {
+        let (id, algorithm, custom, fingerprint) = fingerprint?;
+        for &item in &fingerprint {
+            write_u32(&mut writer, item)?;
+        }
+        docs.push(DocEntry {
+            id,
+            len: fingerprint.len() as u32,
+            algorithm,
+            custom,
+            offset,
+        });
+        offset += fingerprint.len() as u64 * 4;
+    }
+
+    let mut term_entries = Vec::new();
+    for posting in postings {
+        let (term, ids) = posting?;
+        for &id in &ids {
+            write_u32(&mut writer, id)?;
+        }
+        term_entries.push(TermEntry {
+            term,
+            len: ids.len() as u32,
+            offset,
+        });
+        offset += ids.len() as u64 * 4;
+    }
+
+    docs.sort_unstable_by_key(|doc| doc.id);
+    let doc_table_offset = offset;
+    for doc in &docs {
+        write_u32(&mut writer, doc.id)?;
+        write_u32(&mut writer, doc.len)?;
+        write_u32(&mut writer, doc.algorithm.id() as u32)?;
+        let (hop_size, sample_rate, words) = doc.custom.map_or((0, 0, 0), |custom| {
+            (
+                custom.hop_size as u32,
+                custom.sample_rate,
+                custom.words as u32,
+            )
+        });
+        write_u32(&mut writer, hop_size)?;
+        write_u32(&mut writer, sample_rate)?;
+        write_u32(&mut writer, words)?;
+        write_u64(&mut writer, doc.offset)?;
+    }
+
+    let term_table_offset = doc_table_offset + docs.len() as u64 * DOC_ENTRY_SIZE;
+    for entry in &term_entries {
+        write_u32(&mut writer, entry.term)?;
+        write_u32(&mut writer, entry.len)?;
+        write_u64(&mut writer, entry.offset)?;
+    }
+
+    writer.seek(SeekFrom::Start(0))?;
+    writer.write_all(&SEGMENT_MAGIC)?;
+    write_u32(&mut writer, SEGMENT_VERSION)?;
+    write_u32(&mut writer, docs.len() as u32)?;
+    write_u32(&mut writer, term_entries.len() as u32)?;
+    write_u64(&mut writer, doc_table_offset)?;
+    write_u64(&mut writer, term_table_offset)?;
+
+    writer.into_inner()?.sync_all()
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_algorithm<R: Read>(reader: &mut R) -> io::Result<Algorithm> {
+    let id = read_u32(reader)?;
+    if id > u8::MAX as u32 {
+        return Err(invalid_data("unknown algorithm"));
+    }
+
+    Algorithm::from_id(id as u8).ok_or_else(|| invalid_data("unknown algorithm"))
+}
+
+fn read_custom<R: Read>(reader: &mut R) -> io::Result<Option<CustomItems>> {
+    let hop_size = read_u32(reader)?;
+    let sample_rate = read_u32(reader)?;
+    let words = read_u32(reader)?;
+    match (hop_size, sample_rate, words) {
+        (0, 0, 0) => Ok(None),
+        (0, _, _) | (_, 0, _) | (_, _, 0) => Err(invalid_data("invalid custom items")),
+        _ => Ok(Some(CustomItems {
+            hop_size: hop_size as usize,
+            sample_rate,
+            words: words as usize,
+        })),
+    }
+}
+
+/// Checks that `size` bytes at `offset` are within a file of `file_len` bytes.
+fn check_bounds(offset: u64, size: u64, file_len: u64) -> io::Result<()> {
+    match offset.checked_add(size) {
+        Some(end) if end <= file_len => Ok(()),
+        _ => Err(invalid_data("entry out of the bounds of the segment file")),
+    }
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{segment_path, DiskIndex};
+    use algorithm::Algorithm;
+    use fingerprinter::{CustomItems, Fingerprint};
+    use std::error::Error;
+    use std::fs;
+    use std::io;
+    use tempfile;
+
+    /// Generates a fingerprint with uncorrelated items.
+    fn random_fingerprint(seed: u32, len: usize) -> Vec<u32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                state ^ (state >> 15)
+            })
+            .collect()
+    }
+
+    fn search(index: &DiskIndex, query: &[u32]) -> io::Result<Vec<(u32, i32)>> {
+        Ok(index
+            .search(&Fingerprint::new(query, Algorithm::default()), 5)?
+            .iter()
+            .map(|result| (result.id, result.offset))
+            .collect())
+    }
+
+    #[test]
+    fn test_reopen() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let fingerprints: Vec<Vec<u32>> = (0..10).map(|id| random_fingerprint(id, 200)).collect();
+
+        let mut index = DiskIndex::create(dir.path())?;
+        for (id, fingerprint) in fingerprints.iter().enumerate() {
+            index.insert(
+                id as u32,
+                &Fingerprint::new(fingerprint, Algorithm::default()),
+            );
+        }
+        assert_eq!(vec![(3, 50)], search(&index, &fingerprints[3][50..100])?);
+        index.commit()?;
+        drop(index);
+
+        let index = DiskIndex::open(dir.path())?;
+        assert_eq!(10, index.len());
+        assert_eq!(1, index.segment_count());
+        assert_eq!(vec![(3, 50)], search(&index, &fingerprints[3][50..100])?);
+        assert_eq!(vec![(9, 0)], search(&index, &fingerprints[9])?);
+        assert!(search(&index, &random_fingerprint(100, 50))?.is_empty());
+
+        let query = Fingerprint::new(&fingerprints[3], Algorithm::default());
+        assert_eq!(1, index.search(&query, usize::MAX)?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mutate() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let a = random_fingerprint(1, 100);
+        let b = random_fingerprint(2, 100);
+        let c = random_fingerprint(3, 100);
+
+        let mut index = DiskIndex::create(dir.path())?;
+        index.insert(1, &Fingerprint::new(&a, Algorithm::default()));
+        index.insert(2, &Fingerprint::new(&b, Algorithm::default()));
+        index.commit()?;
+
+        assert!(index.remove(1));
+        assert!(!index.remove(1));
+        index.insert(2, &Fingerprint::new(&c, Algorithm::default()));
+        assert_eq!(1, index.len());
+        assert!(search(&index, &a)?.is_empty());
+        assert!(search(&index, &b)?.is_empty());
+        assert_eq!(vec![(2, 0)], search(&index, &c)?);
+
+        // Uncommitted changes are lost.
+        let index = DiskIndex::open(dir.path())?;
+        assert_eq!(2, index.len());
+        assert_eq!(vec![(1, 0)], search(&index, &a)?);
+        drop(index);
+
+        let mut index = DiskIndex::open(dir.path())?;
+        index.remove(1);
+        index.insert(2, &Fingerprint::new(&c, Algorithm::default()));
+        index.commit()?;
+
+        let index = DiskIndex::open(dir.path())?;
+        assert_eq!(1, index.len());
+        assert!(index.contains(2));
+        assert!(!index.contains(1));
+        assert_eq!(vec![(2, 0)], search(&index, &c)?);
+        assert!(search(&index, &a)?.is_empty());
+        assert!(search(&index, &b)?.is_empty());
+
+        // The first segment had no live fingerprints left and was deleted.
+        assert_eq!(1, index.segment_count());
+        assert!(!segment_path(dir.path(), 0).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut index = DiskIndex::create(dir.path())?;
+        for id in 0..30 {
+            index.insert(
+                id,
+                &Fingerprint::new(&random_fingerprint(id, 100), Algorithm::default()),
+            );
+            if id % 10 == 9 {
+                index.commit()?;
+            }
+        }
+        assert_eq!(3, index.segment_count());
+
+        for id in (0..30).step_by(3) {
+            index.remove(id);
+        }
+        index.compact()?;
+        assert_eq!(1, index.segment_count());
+        // Only the manifest and the merged segment are left.
+        assert_eq!(2, fs::read_dir(dir.path())?.count());
+        drop(index);
+
+        let index = DiskIndex::open(dir.path())?;
+        assert_eq!(20, index.len());
+        for id in 0..30 {
+            let expected = if id % 3 == 0 { vec![] } else { vec![(id, 10)] };
+            assert_eq!(
+                expected,
+                search(&index, &random_fingerprint(id, 100)[10..60])?
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tiers() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut index = DiskIndex::create(dir.path())?;
+        let mut segment_counts = Vec::new();
+        for id in 0..16 {
+            index.insert(
+                id,
+                &Fingerprint::new(&random_fingerprint(id, 100), Algorithm::default()),
+            );
+            index.commit()?;
+            segment_counts.push(index.segment_count());
+        }
+
+        // Every 4 segments of 1 fingerprint are merged, and then the 4 segments of 4.
+        assert_eq!(
+            vec![1, 2, 3, 1, 2, 3, 4, 2, 3, 4, 5, 3, 4, 5, 6, 1],
+            segment_counts
+        );
+        assert_eq!(2, fs::read_dir(dir.path())?.count());
+
+        index.remove(5);
+        index.commit()?;
+        drop(index);
+
+        let index = DiskIndex::open(dir.path())?;
+        assert_eq!(15, index.len());
+        for id in 0..16 {
+            let expected = if id == 5 { vec![] } else { vec![(id, 10)] };
+            assert_eq!(
+                expected,
+                search(&index, &random_fingerprint(id, 100)[10..60])?
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            DiskIndex::open(dir.path()).err().unwrap().kind()
+        );
+
+        DiskIndex::create(dir.path())?;
+        assert_eq!(
+            io::ErrorKind::AlreadyExists,
+            DiskIndex::create(dir.path()).err().unwrap().kind()
+        );
+
+        // A deleted id the segment doesn't contain.
+        let mut index = DiskIndex::open(dir.path())?;
+        index.insert(
+            1,
+            &Fingerprint::new(&random_fingerprint(1, 100), Algorithm::default()),
+        );
+        index.commit()?;
+        let manifest = fs::read_to_string(dir.path().join("MANIFEST"))?;
+        fs::write(
+            dir.path().join("MANIFEST"),
+            manifest.replace("segment 0", "segment 0 1 2"),
+        )?;
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            DiskIndex::open(dir.path()).err().unwrap().kind()
+        );
+
+        fs::write(dir.path().join("MANIFEST"), "something else\n")?;
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            DiskIndex::open(dir.path()).err().unwrap().kind()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mixed_algorithms() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let a = random_fingerprint(1, 100);
+        let b = random_fingerprint(2, 100);
+
+        let mut index = DiskIndex::create(dir.path())?;
+        index.insert(1, &Fingerprint::new(&a, Algorithm::Test1));
+        index.insert(2, &Fingerprint::new(&a, Algorithm::Test2));
+        index.insert(3, &Fingerprint::new(&b, Algorithm::Test1));
+        assert_eq!(vec![(2, 0)], search(&index, &a)?);
+        index.commit()?;
+        index.insert(4, &Fingerprint::new(&b, Algorithm::Test2));
+        drop(index);
+
+        let mut index = DiskIndex::open(dir.path())?;
+        assert_eq!(vec![(2, 0)], search(&index, &a)?);
+        assert!(search(&index, &b)?.is_empty());
+
+        index.insert(4, &Fingerprint::new(&b, Algorithm::Test2));
+        let query = Fingerprint::new(&b[10..60], Algorithm::Test1);
+        let ids: Vec<u32> = index.search(&query, 5)?.iter().map(|r| r.id).collect();
+        assert_eq!(vec![3], ids);
+        assert_eq!(vec![(4, 10)], search(&index, &b[10..60])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_items() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let a = random_fingerprint(1, 100);
+        let custom = CustomItems {
+            hop_size: 2048,
+            sample_rate: 11025,
+            words: 1,
+        };
+        let other = CustomItems {
+            sample_rate: 22050,
+            ..custom
+        };
+
+        let mut index = DiskIndex::create(dir.path())?;
+        index.insert(1, &Fingerprint::new(&a, Algorithm::default()));
+        index.insert(
+            2,
+            &Fingerprint::with_custom(&a, Algorithm::default(), custom),
+        );
+        index.commit()?;
+        drop(index);
+
+        let index = DiskIndex::open(dir.path())?;
+        let search = |query: &Fingerprint| -> io::Result<Vec<u32>> {
+            Ok(index.search(query, 10)?.iter().map(|r| r.id).collect())
+        };
+        assert_eq!(
+            vec![1],
+            search(&Fingerprint::new(&a, Algorithm::default()))?
+        );
+        assert_eq!(
+            vec![2],
+            search(&Fingerprint::with_custom(&a, Algorithm::default(), custom))?
+        );
+        assert!(search(&Fingerprint::with_custom(&a, Algorithm::default(), other))?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_segment() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let fingerprint = random_fingerprint(1, 100);
+        let mut index = DiskIndex::create(dir.path())?;
+        index.insert(1, &Fingerprint::new(&fingerprint, Algorithm::default()));
+        index.commit()?;
+        drop(index);
+
+        // Claims the fingerprint has 2^32 - 1 items.
+        let path = segment_path(dir.path(), 0);
+        let mut bytes = fs::read(&path)?;
+        let mut offset = [0; 8];
+        offset.copy_from_slice(&bytes[16..24]);
+        let doc_table_offset = u64::from_le_bytes(offset) as usize;
+        bytes[(doc_table_offset + 4)..(doc_table_offset + 8)].copy_from_slice(&[0xff; 4]);
+        fs::write(&path, &bytes)?;
+
+        let index = DiskIndex::open(dir.path())?;
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            search(&index, &fingerprint).unwrap_err().kind()
+        );
+
+        // Claims the segment has 2^32 - 1 fingerprints.
+        bytes[8..12].copy_from_slice(&[0xff; 4]);
+        fs::write(&path, &bytes)?;
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            DiskIndex::open(dir.path()).err().unwrap().kind()
+        );
+
+        Ok(())
+    }
+}
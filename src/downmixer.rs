@@ -0,0 +1,118 @@
+use sample::{Pcm, Sample};
+
+/// Averages interleaved multi-channel samples into a single channel.
+pub struct Downmixer {
+    channels: usize,
+
+    /// Samples of a frame which was split across calls to `process`, on the scale of
+    /// `Sample::to_f32`.
+    partial_frame: Vec<f32>,
+}
+
+impl Downmixer {
+    pub fn new(channels: usize) -> Downmixer {
+        Downmixer {
+            channels,
+            partial_frame: Vec::with_capacity(channels),
+        }
+    }
+
+    /// Drops the samples of an incomplete frame.
+    pub fn reset(&mut self) {
+        self.partial_frame.clear();
+    }
+
+    /// Downmixes all complete frames in `data`, appending them to `output`. Samples of a trailing
+    /// incomplete frame are kept until the rest of the frame is passed to the next call.
+    ///
+    /// Mixing into `i16` is only exact for 16-bit input.
+    pub fn process<S: Sample, T: Pcm>(&mut self, mut data: &[S], output: &mut Vec<T>) {
+        output.reserve((self.partial_frame.len() + data.len()) / self.channels);
+
+        if !self.partial_frame.is_empty() {
+            let missing = self.channels - self.partial_frame.len();
+            if data.len() < missing {
+                self.partial_frame
+                    .extend(data.iter().map(|sample| sample.to_f32()));
+                return;
+            }
+
+            self.partial_frame
+                .extend(data[..missing].iter().map(|sample| sample.to_f32()));
+            output.push(T::mix(
+                self.partial_frame.iter().map(|&sample| T::from_f32(sample)),
+                self.channels,
+            ));
+            self.partial_frame.clear();
+            data = &data[missing..];
+        }
+
+        let mut frames = data.chunks_exact(self.channels);
+        output.extend(frames.by_ref().map(|frame| {
+            T::mix(
+                frame.iter().map(|sample| T::from_f32(sample.to_f32())),
+                self.channels,
+            )
+        }));
+        self.partial_frame
+            .extend(frames.remainder().iter().map(|sample| sample.to_f32()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Downmixer;
+
+    fn process(downmixer: &mut Downmixer, data: &[i16]) -> Vec<i16> {
+        let mut output = Vec::new();
+        downmixer.process(data, &mut output);
+        output
+    }
+
+    #[test]
+    fn test_stereo() {
+        let mut downmixer = Downmixer::new(2);
+
+        assert_eq!(
+            vec![1, -1, 32767, -32768],
+            process(
+                &mut downmixer,
+                &[0i16, 2, -1, -2, 32767, 32767, -32768, -32768]
+            )
+        );
+    }
+
+    #[test]
+    fn test_multi_channel() {
+        let mut downmixer = Downmixer::new(6);
+
+        assert_eq!(
+            vec![3, -1],
+            process(
+                &mut downmixer,
+                &[1i16, 2, 3, 4, 5, 6, -1, -1, -1, -1, -1, -1]
+            )
+        );
+    }
+
+    #[test]
+    fn test_split_frames() {
+        let mut downmixer = Downmixer::new(3);
+
+        assert!(process(&mut downmixer, &[3i16]).is_empty());
+        assert!(process(&mut downmixer, &[3i16]).is_empty());
+        assert_eq!(vec![3, 6], process(&mut downmixer, &[3i16, 6, 6, 6, 9]));
+        assert_eq!(vec![9], process(&mut downmixer, &[9i16, 9]));
+    }
+
+    #[test]
+    fn test_float() {
+        let mut downmixer = Downmixer::new(2);
+        let mut output: Vec<f32> = Vec::new();
+
+        // The average isn't truncated.
+        downmixer.process(&[0.5f32, 0.0], &mut output);
+        downmixer.process(&[1i32 << 16, 0], &mut output);
+        assert_eq!(vec![8192.0, 0.5], output);
+    }
+}
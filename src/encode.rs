@@ -1,4 +1,6 @@
 use base64::{CharacterSet, Config};
+use std::error::Error;
+use std::fmt;
 
 fn config() -> Config {
     Config::new(CharacterSet::UrlSafe, false)
@@ -7,3 +9,176 @@ fn config() -> Config {
 pub fn encode(fingerprint: &[u8]) -> String {
     base64::encode_config(fingerprint, config())
 }
+
+/// Reasons an encoded fingerprint could not be decoded. Positions are counted in characters
+/// from the start of the original input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// A character outside of the accepted alphabet was found.
+    InvalidCharacter { position: usize, character: char },
+
+    /// The number of base64 characters can't be produced by encoding whole bytes.
+    InvalidLength { length: usize },
+
+    /// The last character has bits set which don't belong to any encoded byte.
+    InvalidLastCharacter { position: usize, character: char },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::InvalidCharacter {
+                position,
+                character,
+            } => write!(
+                f,
+                "invalid character {:?} at position {}",
+                character, position
+            ),
+            DecodeError::InvalidLength { length } => {
+                write!(f, "invalid encoded fingerprint length {}", length)
+            }
+            DecodeError::InvalidLastCharacter {
+                position,
+                character,
+            } => write!(
+                f,
+                "invalid last character {:?} at position {}",
+                character, position
+            ),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Decodes a fingerprint encoded with the URL-safe alphabet and without padding, as produced by
+/// `encode`, `fpcalc` and the AcoustID API.
+///
+/// When `lenient` is set, surrounding whitespace, trailing padding and characters from the
+/// standard alphabet (`+` and `/`) are accepted too.
+pub fn decode(input: &str, lenient: bool) -> Result<Vec<u8>, DecodeError> {
+    let (skipped, body) = if lenient {
+        let trimmed = input.trim_start();
+        let skipped = input[..(input.len() - trimmed.len())].chars().count();
+
+        (skipped, trimmed.trim_end().trim_end_matches('='))
+    } else {
+        (0, input)
+    };
+
+    let mut normalized = Vec::with_capacity(body.len());
+    for (idx, character) in body.chars().enumerate() {
+        let normalized_character = match character {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '_' => character,
+            '+' if lenient => '-',
+            '/' if lenient => '_',
+            _ => {
+                return Err(DecodeError::InvalidCharacter {
+                    position: skipped + idx,
+                    character,
+                })
+            }
+        };
+
+        normalized.push(normalized_character as u8);
+    }
+
+    base64::decode_config(&normalized, config()).map_err(|err| match err {
+        base64::DecodeError::InvalidByte(offset, byte) => DecodeError::InvalidCharacter {
+            position: skipped + offset,
+            character: byte as char,
+        },
+        base64::DecodeError::InvalidLength => DecodeError::InvalidLength {
+            length: normalized.len(),
+        },
+        base64::DecodeError::InvalidLastSymbol(offset, byte) => DecodeError::InvalidLastCharacter {
+            position: skipped + offset,
+            character: byte as char,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, DecodeError};
+
+    const ENCODED: &str = "AQAAC0kkRVHCJEqU4IS6Hs8FH5eh_8jP4ztOHEoYQYwAgABBhog";
+
+    #[test]
+    fn test_round_trip() {
+        let decoded = decode(ENCODED, false).unwrap();
+        assert_eq!(ENCODED, encode(&decoded));
+    }
+
+    #[test]
+    fn test_strict_rejects_padding() {
+        assert_eq!(
+            Err(DecodeError::InvalidCharacter {
+                position: 3,
+                character: '='
+            }),
+            decode("AQE=", false)
+        );
+    }
+
+    #[test]
+    fn test_strict_rejects_standard_alphabet() {
+        assert_eq!(
+            Err(DecodeError::InvalidCharacter {
+                position: 28,
+                character: '/'
+            }),
+            decode(&ENCODED.replace('_', "/"), false)
+        );
+    }
+
+    #[test]
+    fn test_strict_rejects_whitespace() {
+        assert_eq!(
+            Err(DecodeError::InvalidCharacter {
+                position: 0,
+                character: ' '
+            }),
+            decode(" AQE", false)
+        );
+    }
+
+    #[test]
+    fn test_lenient() {
+        let expected = decode(ENCODED, false).unwrap();
+        let standard = format!(" \n{}=\t\n", ENCODED.replace('_', "/"));
+
+        assert_eq!(Ok(expected), decode(&standard, true));
+    }
+
+    #[test]
+    fn test_lenient_reports_original_position() {
+        assert_eq!(
+            Err(DecodeError::InvalidCharacter {
+                position: 4,
+                character: '*'
+            }),
+            decode("  AQ*E", true)
+        );
+    }
+
+    #[test]
+    fn test_invalid_length() {
+        assert_eq!(
+            Err(DecodeError::InvalidLength { length: 5 }),
+            decode("AQEAA", false)
+        );
+    }
+
+    #[test]
+    fn test_invalid_last_character() {
+        assert_eq!(
+            Err(DecodeError::InvalidLastCharacter {
+                position: 2,
+                character: 'F'
+            }),
+            decode("AQF", false)
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::error;
+use std::fmt;
+
+use audio_processor::{MAX_SAMPLE_RATE, MIN_SAMPLE_RATE};
+use encode::DecodeError;
+use fingerprint_compressor::MAX_COMPRESSED_LEN;
+use fingerprint_decompressor::DecompressError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The input sample rate is outside of the supported range.
+    InvalidSampleRate(u32),
+
+    /// A duration given to a `Fingerprinter` is out of range, for the given reason.
+    InvalidDuration(&'static str),
+
+    /// The input has no channels.
+    InvalidChannelCount(u16),
+
+    /// The settings of a `FingerprinterBuilder` can't work together, for the given reason.
+    InvalidConfig(&'static str),
+
+    /// The classifier at the given position, counted from 0, doesn't fit in the chroma image or
+    /// has thresholds out of order, for the given reason.
+    InvalidClassifier(usize, &'static str),
+
+    /// The line with the given number, counted from 1, of a text description of classifiers
+    /// doesn't hold 7 numbers.
+    MalformedClassifier(usize),
+
+    /// Audio was fed to, or the end signalled to, a fingerprinter which has already finished.
+    /// It has to be reset or started again first.
+    AlreadyFinished,
+
+    /// A fingerprint has more items than the 24-bit length of the compressed format can hold.
+    FingerprintTooLong(usize),
+
+    /// A fingerprint computed with custom settings was compressed, but the header can only name
+    /// a standard algorithm.
+    CustomFingerprint,
+
+    /// A fingerprint header names an algorithm which doesn't exist.
+    UnknownAlgorithm(u8),
+
+    /// A fingerprint isn't valid base64.
+    Decode(DecodeError),
+
+    /// A compressed fingerprint is malformed.
+    Decompress(DecompressError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidSampleRate(sample_rate) => write!(
+                f,
+                "unsupported sample rate {} Hz, expected more than {} Hz and at most {} Hz",
+                sample_rate, MIN_SAMPLE_RATE, MAX_SAMPLE_RATE
+            ),
+            Error::InvalidDuration(reason) => write!(f, "invalid duration: {}", reason),
+            Error::InvalidChannelCount(channels) => {
+                write!(f, "unsupported channel count {}", channels)
+            }
+            Error::InvalidConfig(reason) => {
+                write!(f, "invalid fingerprinter configuration: {}", reason)
+            }
+            Error::InvalidClassifier(idx, reason) => {
+                write!(f, "invalid classifier {}: {}", idx, reason)
+            }
+            Error::MalformedClassifier(line) => write!(
+                f,
+                "malformed classifier on line {}, expected the filter type, y, height, width and \
+                 3 thresholds",
+                line
+            ),
+            Error::AlreadyFinished => write!(f, "the fingerprinter has already finished"),
+            Error::FingerprintTooLong(len) => write!(
+                f,
+                "fingerprint of {} items is too long to compress, at most {} are supported",
+                len, MAX_COMPRESSED_LEN
+            ),
+            Error::CustomFingerprint => write!(
+                f,
+                "fingerprints computed with custom settings can't be compressed"
+            ),
+            Error::UnknownAlgorithm(id) => write!(f, "unknown fingerprint algorithm {}", id),
+            Error::Decode(ref err) => write!(f, "invalid encoded fingerprint: {}", err),
+            Error::Decompress(ref err) => write!(f, "invalid compressed fingerprint: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Decode(ref err) => Some(err),
+            Error::Decompress(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Error {
+        Error::Decode(err)
+    }
+}
+
+impl From<DecompressError> for Error {
+    fn from(err: DecompressError) -> Error {
+        Error::Decompress(err)
+    }
+}
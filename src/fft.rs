@@ -6,43 +6,44 @@ use rustfft::FftDirection;
 use slicer::FixedSlicer;
 use std::f32::consts::PI;
 
-const FRAME_SIZE: usize = 4096;
-const OVERLAP: usize = FRAME_SIZE - FRAME_SIZE / 3;
-
 pub struct Fft {
-    slicer: Option<FixedSlicer<i16>>,
+    slicer: FixedSlicer<f32>,
     fft: Radix4<f32>,
     hamming_window: Vec<f32>,
 }
 
 impl Fft {
-    pub fn new() -> Fft {
+    pub fn new(frame_size: usize, overlap: usize) -> Fft {
         Fft {
-            slicer: Some(FixedSlicer::new(FRAME_SIZE, FRAME_SIZE - OVERLAP)),
-            fft: Radix4::new(FRAME_SIZE, FftDirection::Forward),
-            hamming_window: prepare_hamming_window(FRAME_SIZE, 1.0 / ::std::i16::MAX as f32),
+            slicer: FixedSlicer::new(frame_size, frame_size - overlap),
+            fft: Radix4::new(frame_size, FftDirection::Forward),
+            hamming_window: prepare_hamming_window(frame_size, 1.0 / i16::MAX as f32),
         }
     }
 
-    pub fn consume<C: FnMut(Vec<f64>)>(&mut self, data: &[i16], mut consumer: C) {
-        let mut slicer = self.slicer.take().unwrap();
+    /// Drops any buffered samples, keeping the FFT plan and window.
+    pub fn reset(&mut self) {
+        self.slicer.reset();
+    }
 
-        slicer.process(data, |vec| {
+    /// Computes the spectrum of every frame of `data`, samples on a 16-bit scale.
+    pub fn consume<C: FnMut(Vec<f64>)>(&mut self, data: &[f32], mut consumer: C) {
+        let fft = &self.fft;
+        let hamming_window = &self.hamming_window;
+        self.slicer.process(data, |vec| {
             let mut converted: Vec<Complex<f32>> = vec
                 .into_iter()
                 .enumerate()
-                .map(|(idx, data)| self.hamming_window[idx] * (data as f32))
+                .map(|(idx, data)| hamming_window[idx] * data)
                 .map(|num| Complex::new(num, 0.0))
                 .collect();
 
             //let mut output: Vec<Complex<f32>> = vec![Complex::zero(); FRAME_SIZE];
-            self.fft.process(&mut converted);
+            fft.process(&mut converted);
 
             let folded = fold_output(&converted);
             consumer(folded);
         });
-
-        self.slicer = Some(slicer);
     }
 }
 
@@ -70,12 +71,15 @@ fn prepare_hamming_window(size: usize, scale: f32) -> Vec<f32> {
 
 #[cfg(test)]
 mod tests {
-    use super::{prepare_hamming_window, Fft, FRAME_SIZE};
+    use super::{prepare_hamming_window, Fft};
     use std::error::Error;
     use std::path::PathBuf;
     use test_data;
     use tests::load_audio_file;
 
+    const FRAME_SIZE: usize = 4096;
+    const OVERLAP: usize = FRAME_SIZE - FRAME_SIZE / 3;
+
     #[test]
     fn test_prepare_hamming_window() {
         let expected = vec![
@@ -100,7 +104,7 @@ mod tests {
     #[test]
     fn test_complete_hamming_window() {
         let expected = test_data::get_hamming_window();
-        let window = prepare_hamming_window(FRAME_SIZE, 1.0 / ::std::i16::MAX as f32);
+        let window = prepare_hamming_window(FRAME_SIZE, 1.0 / i16::MAX as f32);
 
         assert_eq!(expected.len(), window.len());
         for idx in 0..expected.len() {
@@ -111,11 +115,13 @@ mod tests {
     #[test]
     fn test_fft() -> Result<(), Box<dyn Error>> {
         let samples = load_audio_file(
-            &PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
                 .join("./test_data/test_stero_44100_resampled_11025.raw"),
         )?;
 
-        let mut fft = Fft::new();
+        let samples: Vec<f32> = samples.into_iter().map(|sample| sample as f32).collect();
+
+        let mut fft = Fft::new(FRAME_SIZE, OVERLAP);
         let mut frames = Vec::new();
         fft.consume(&samples, |frame| {
             frames.push(frame);
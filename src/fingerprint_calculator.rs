@@ -1,48 +1,108 @@
-use classifiers::Classifiers;
+use classifiers::{Classifiers, CLASSIFIERS_PER_WORD};
+use filter::Filter;
+use quantizer::Quantizer;
 use rolling_integral_image::RollingIntegralImage;
+use std::vec::Drain;
 
-const FILTER_WIDTH: usize = 16;
+/// Number of chroma rows each item of the standard algorithms is computed from.
+pub const FILTER_WIDTH: usize = 16;
+
+/// Number of rows the integral image keeps, which no filter can be wider than.
+pub const MAX_FILTER_WIDTH: usize = 256;
 
 pub struct FingerprintCalculator {
-    classifiers: Classifiers,
+    classifiers: Vec<(Filter, Quantizer)>,
+
+    /// Number of chroma rows each item is computed from.
+    filter_width: usize,
     image: RollingIntegralImage,
+
+    /// The words of the items, one item after the other.
     fingerprint: Vec<u32>,
+
+    /// Number of words dropped from the start of `fingerprint`.
+    dropped: usize,
 }
 
 impl FingerprintCalculator {
-    pub fn new(classifiers: Classifiers) -> FingerprintCalculator {
+    pub fn new(classifiers: &Classifiers) -> FingerprintCalculator {
         FingerprintCalculator {
-            classifiers,
-            image: RollingIntegralImage::new(256),
+            classifiers: classifiers
+                .iter()
+                .map(|classifier| {
+                    let [t0, t1, t2] = classifier.thresholds;
+                    (
+                        Filter::new(
+                            classifier.filter_type,
+                            classifier.y,
+                            classifier.height,
+                            classifier.width,
+                        ),
+                        Quantizer::new(t0, t1, t2),
+                    )
+                })
+                .collect(),
+            filter_width: classifiers.width(),
+            image: RollingIntegralImage::new(MAX_FILTER_WIDTH),
             fingerprint: Vec::new(),
+            dropped: 0,
         }
     }
 
-    fn calculate_subfingerprint(&self) -> u32 {
-        let mut bits = 0u32;
-        let offset = self.image.rows() - FILTER_WIDTH;
+    /// Number of words each item is made of.
+    pub fn words(&self) -> usize {
+        self.classifiers.len().div_ceil(CLASSIFIERS_PER_WORD)
+    }
 
-        for (filter, quantizer) in self.classifiers.iter() {
-            let temp = gray_code(quantizer.quantize(filter.apply(&self.image, offset)));
+    fn calculate_subfingerprint(&mut self) {
+        let offset = self.image.rows() - self.filter_width;
 
-            bits = (bits << 2) | (temp as u32);
-        }
+        for classifiers in self.classifiers.chunks(CLASSIFIERS_PER_WORD) {
+            let mut bits = 0u32;
+            for (filter, quantizer) in classifiers {
+                let temp = gray_code(quantizer.quantize(filter.apply(&self.image, offset)));
 
-        bits
+                bits = (bits << 2) | (temp as u32);
+            }
+
+            self.fingerprint.push(bits);
+        }
     }
 
     pub fn consume(&mut self, features: [f64; 12]) {
         self.image.add_row(features);
 
-        if self.image.rows() >= FILTER_WIDTH {
-            let subfingerprint = self.calculate_subfingerprint();
-            self.fingerprint.push(subfingerprint);
+        if self.image.rows() >= self.filter_width {
+            self.calculate_subfingerprint();
         }
     }
 
     pub fn fingerprint(&self) -> &[u32] {
         &self.fingerprint
     }
+
+    /// Starts a new fingerprint, keeping the allocated storage.
+    pub fn reset(&mut self) {
+        self.image.reset();
+        self.fingerprint.clear();
+        self.dropped = 0;
+    }
+
+    /// Drops the items computed so far, keeping the rows needed to compute the next ones.
+    pub fn clear_fingerprint(&mut self) {
+        self.drain();
+    }
+
+    /// Removes the items computed so far.
+    ///
+    /// # Returns
+    /// The index of the first removed word among all words computed, and the removed words.
+    pub fn drain(&mut self) -> (usize, Drain<'_, u32>) {
+        let first = self.dropped;
+        self.dropped += self.fingerprint.len();
+
+        (first, self.fingerprint.drain(..))
+    }
 }
 
 fn gray_code(idx: u8) -> u8 {
@@ -1,6 +1,14 @@
 use bit_writer::BitWriter;
+use error::Error;
+
+/// Most items a compressed fingerprint can have, as the header stores the length in 24 bits.
+pub const MAX_COMPRESSED_LEN: usize = 0xff_ffff;
+
+pub fn compress(fingerprint: &[u32], algorithm: u8) -> Result<Vec<u8>, Error> {
+    if fingerprint.len() > MAX_COMPRESSED_LEN {
+        return Err(Error::FingerprintTooLong(fingerprint.len()));
+    }
 
-pub fn compress(fingerprint: &[u32], algorithm: u8) -> Vec<u8> {
     let mut normal_bits = Vec::new();
     let mut exceptional_bits = Vec::new();
 
@@ -20,8 +28,8 @@ pub fn compress(fingerprint: &[u32], algorithm: u8) -> Vec<u8> {
     }
 
     let header_size = 4;
-    let normal_bits_size = (normal_bits.len() * 3 + 7) / 8;
-    let exceptional_bits_size = (exceptional_bits.len() * 5 + 7) / 8;
+    let normal_bits_size = (normal_bits.len() * 3).div_ceil(8);
+    let exceptional_bits_size = (exceptional_bits.len() * 5).div_ceil(8);
     let output_size = header_size + normal_bits_size + exceptional_bits_size;
 
     let mut output = vec![0u8; output_size];
@@ -35,7 +43,7 @@ pub fn compress(fingerprint: &[u32], algorithm: u8) -> Vec<u8> {
 
     output.truncate(header_size + exceptional_bits_output_size + normal_bits_output_size);
 
-    output
+    Ok(output)
 }
 
 const K_NORMAL_BITS: u8 = 3;
@@ -74,35 +82,48 @@ fn write_header(output: &mut [u8], size: usize, algorithm: u8) {
 
 #[cfg(test)]
 mod tests {
-    use super::compress;
+    use super::{compress, MAX_COMPRESSED_LEN};
+    use error::Error;
 
     #[test]
     fn one_item_one_bit() {
-        assert_eq!(compress(&[1], 0), [0, 0, 0, 1, 1]);
+        assert_eq!(Ok(vec![0, 0, 0, 1, 1]), compress(&[1], 0));
     }
 
     #[test]
     fn one_item_three_bits() {
-        assert_eq!(compress(&[7], 0), [0, 0, 0, 1, 73, 0]);
+        assert_eq!(Ok(vec![0, 0, 0, 1, 73, 0]), compress(&[7], 0));
     }
 
     #[test]
     fn one_item_one_bit_except() {
-        assert_eq!(compress(&[1 << 6], 0), [0, 0, 0, 1, 7, 0]);
+        assert_eq!(Ok(vec![0, 0, 0, 1, 7, 0]), compress(&[1 << 6], 0));
     }
 
     #[test]
     fn one_item_one_bit_except_2() {
-        assert_eq!(compress(&[1 << 8], 0), [0, 0, 0, 1, 7, 2]);
+        assert_eq!(Ok(vec![0, 0, 0, 1, 7, 2]), compress(&[1 << 8], 0));
     }
 
     #[test]
     fn two_items() {
-        assert_eq!(compress(&[1, 0], 0), [0, 0, 0, 2, 65, 0]);
+        assert_eq!(Ok(vec![0, 0, 0, 2, 65, 0]), compress(&[1, 0], 0));
     }
 
     #[test]
     fn two_items_no_change() {
-        assert_eq!(compress(&[1, 1], 0), [0, 0, 0, 2, 1, 0]);
+        assert_eq!(Ok(vec![0, 0, 0, 2, 1, 0]), compress(&[1, 1], 0));
+    }
+
+    #[test]
+    fn too_long() {
+        let fingerprint = vec![0; MAX_COMPRESSED_LEN + 1];
+        assert_eq!(
+            Err(Error::FingerprintTooLong(MAX_COMPRESSED_LEN + 1)),
+            compress(&fingerprint, 0)
+        );
+
+        let compressed = compress(&fingerprint[1..], 0).unwrap();
+        assert_eq!([0, 0xff, 0xff, 0xff], compressed[..4]);
     }
 }
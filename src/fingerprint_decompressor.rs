@@ -0,0 +1,237 @@
+use bit_reader::BitReader;
+use std::error::Error;
+use std::fmt;
+
+const HEADER_SIZE: usize = 4;
+const K_NORMAL_BITS: u8 = 3;
+const K_EXCEPTIONAL_BITS: u8 = 5;
+const MAX_NORMAL_VALUE: u8 = (1 << K_NORMAL_BITS) - 1;
+
+/// Reasons a compressed fingerprint could not be decompressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecompressError {
+    /// The input is shorter than the 4 byte header.
+    MissingHeader,
+
+    /// The input ended before all the subfingerprints announced by the header were found.
+    TruncatedNormalBits { expected: usize, found: usize },
+
+    /// The input ended before all the exceptional bits were read.
+    TruncatedExceptionalBits { expected: usize, found: usize },
+
+    /// A subfingerprint referenced a bit past the 32nd one.
+    InvalidBitPosition { index: usize },
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecompressError::MissingHeader => write!(f, "fingerprint is missing its header"),
+            DecompressError::TruncatedNormalBits { expected, found } => write!(
+                f,
+                "fingerprint is truncated, expected {} subfingerprints but found {}",
+                expected, found
+            ),
+            DecompressError::TruncatedExceptionalBits { expected, found } => write!(
+                f,
+                "fingerprint is truncated, expected {} exceptional bits but found {}",
+                expected, found
+            ),
+            DecompressError::InvalidBitPosition { index } => write!(
+                f,
+                "subfingerprint {} has a bit set outside of 32 bits",
+                index
+            ),
+        }
+    }
+}
+
+impl Error for DecompressError {}
+
+/// Decompresses a fingerprint produced by `fingerprint_compressor::compress`.
+///
+/// # Returns
+/// A tuple of the raw fingerprint and the algorithm id stored in the header.
+pub fn decompress(input: &[u8]) -> Result<(Vec<u32>, u8), DecompressError> {
+    if input.len() < HEADER_SIZE {
+        return Err(DecompressError::MissingHeader);
+    }
+
+    let (algorithm, size) = read_header(&input[..HEADER_SIZE]);
+    let body = &input[HEADER_SIZE..];
+
+    let mut bits = Vec::with_capacity(body.len() * 8 / K_NORMAL_BITS as usize);
+    let mut found = 0;
+    let mut exceptional_count = 0;
+    let mut normal_reader = BitReader::new(body);
+
+    while found < size {
+        let value =
+            normal_reader
+                .read(K_NORMAL_BITS)
+                .ok_or(DecompressError::TruncatedNormalBits {
+                    expected: size,
+                    found,
+                })?;
+
+        if value == 0 {
+            found += 1;
+        } else if value == MAX_NORMAL_VALUE {
+            exceptional_count += 1;
+        }
+
+        bits.push(value);
+    }
+
+    let mut exceptional_reader = BitReader::new(&body[normal_reader.consumed()..]);
+    let exceptional_bits = bits.iter_mut().filter(|bit| **bit == MAX_NORMAL_VALUE);
+
+    for (exceptional_found, bit) in exceptional_bits.enumerate() {
+        let value = exceptional_reader.read(K_EXCEPTIONAL_BITS).ok_or(
+            DecompressError::TruncatedExceptionalBits {
+                expected: exceptional_count,
+                found: exceptional_found,
+            },
+        )?;
+
+        *bit += value;
+    }
+
+    Ok((unpack_bits(&bits, size)?, algorithm))
+}
+
+/// Turns the bit position deltas back into subfingerprints and undoes the XOR delta encoding.
+fn unpack_bits(bits: &[u8], size: usize) -> Result<Vec<u32>, DecompressError> {
+    let mut output = Vec::with_capacity(size);
+    let mut value = 0u32;
+    let mut last_bit = 0u32;
+
+    for &bit in bits {
+        if bit == 0 {
+            let subfingerprint = match output.last() {
+                Some(previous) => value ^ previous,
+                None => value,
+            };
+            output.push(subfingerprint);
+
+            value = 0;
+            last_bit = 0;
+            continue;
+        }
+
+        last_bit += bit as u32;
+        if last_bit > 32 {
+            return Err(DecompressError::InvalidBitPosition {
+                index: output.len(),
+            });
+        }
+
+        value |= 1 << (last_bit - 1);
+    }
+
+    Ok(output)
+}
+
+fn read_header(input: &[u8]) -> (u8, usize) {
+    let size = ((input[1] as usize) << 16) | ((input[2] as usize) << 8) | (input[3] as usize);
+
+    (input[0], size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, DecompressError};
+    use fingerprint_compressor::compress;
+
+    fn assert_round_trip(fingerprint: &[u32], algorithm: u8) {
+        let compressed = compress(fingerprint, algorithm).unwrap();
+        assert_eq!(
+            Ok((fingerprint.to_vec(), algorithm)),
+            decompress(&compressed)
+        );
+    }
+
+    #[test]
+    fn one_item_one_bit() {
+        assert_eq!(Ok((vec![1], 0)), decompress(&[0, 0, 0, 1, 1]));
+    }
+
+    #[test]
+    fn one_item_three_bits() {
+        assert_eq!(Ok((vec![7], 0)), decompress(&[0, 0, 0, 1, 73, 0]));
+    }
+
+    #[test]
+    fn one_item_one_bit_except() {
+        assert_eq!(Ok((vec![1 << 6], 0)), decompress(&[0, 0, 0, 1, 7, 0]));
+    }
+
+    #[test]
+    fn one_item_one_bit_except_2() {
+        assert_eq!(Ok((vec![1 << 8], 0)), decompress(&[0, 0, 0, 1, 7, 2]));
+    }
+
+    #[test]
+    fn two_items() {
+        assert_eq!(Ok((vec![1, 0], 0)), decompress(&[0, 0, 0, 2, 65, 0]));
+    }
+
+    #[test]
+    fn two_items_no_change() {
+        assert_eq!(Ok((vec![1, 1], 0)), decompress(&[0, 0, 0, 2, 1, 0]));
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_round_trip(&[], 1);
+        assert_round_trip(&[0], 1);
+        assert_round_trip(&[1 << 31, u32::MAX, 0, 0xdead_beef], 4);
+        assert_round_trip(
+            &[
+                0x0f0f_0f0f,
+                0xf0f0_f0f0,
+                0x8000_0001,
+                0x1234_5678,
+                0x1234_5678,
+                0,
+            ],
+            2,
+        );
+    }
+
+    #[test]
+    fn missing_header() {
+        assert_eq!(Err(DecompressError::MissingHeader), decompress(&[1, 0, 0]));
+    }
+
+    #[test]
+    fn truncated_normal_bits() {
+        assert_eq!(
+            Err(DecompressError::TruncatedNormalBits {
+                expected: 3,
+                found: 1
+            }),
+            decompress(&[0, 0, 0, 3, 65])
+        );
+    }
+
+    #[test]
+    fn truncated_exceptional_bits() {
+        assert_eq!(
+            Err(DecompressError::TruncatedExceptionalBits {
+                expected: 1,
+                found: 0
+            }),
+            decompress(&[0, 0, 0, 1, 7])
+        );
+    }
+
+    #[test]
+    fn invalid_bit_position() {
+        // The first set bit lands on position 7 + 31.
+        assert_eq!(
+            Err(DecompressError::InvalidBitPosition { index: 0 }),
+            decompress(&[0, 0, 0, 1, 63, 0, 255, 3])
+        );
+    }
+}
@@ -0,0 +1,445 @@
+use compare::{bit_errors, overlap};
+use fingerprinter::Fingerprint;
+
+/// Number of top bits of an item used to find the offsets at which two fingerprints align.
+const HASH_BITS: u32 = 20;
+
+/// Number of the most promising alignments which are split into segments. Every alignment is
+/// smoothed over the whole overlap, so examining every peak of the histogram would take time
+/// quadratic in the length of the fingerprints.
+const MAX_ALIGNMENTS: usize = 10;
+
+/// Segments with more differing bits per item than this on average are not considered matching.
+const DEFAULT_MATCH_THRESHOLD: f64 = 10.0;
+
+/// Width of the gaussian filter used to smooth the bit errors before looking for segment edges.
+const SMOOTHING_SIGMA: f64 = 8.0;
+const SMOOTHING_PASSES: usize = 3;
+
+/// Minimum change in the smoothed bit errors per item for a segment edge.
+const EDGE_GRADIENT: f64 = 0.15;
+
+/// Consecutive segments whose scores differ less than this are merged.
+const MERGE_SCORE_DIFFERENCE: f64 = 0.7;
+
+/// A region which matches in two fingerprints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    /// Start of the region in the first fingerprint, in seconds.
+    pub pos1: f64,
+
+    /// Start of the region in the second fingerprint, in seconds.
+    pub pos2: f64,
+
+    /// Length of the region, in seconds.
+    pub duration: f64,
+
+    /// Average number of differing bits per item in the region, between 0 (identical) and 32.
+    /// Items made of several words count the differing bits per word.
+    pub score: f64,
+}
+
+/// Finds the regions two fingerprints have in common, like Chromaprint's `FingerprintMatcher`.
+///
+/// Candidate alignments are found by building a histogram of the offsets between items with the
+/// same 20-bit hash in both fingerprints. The overlap at each dominant offset is then split into
+/// segments at points where the bit errors change sharply, and the segments with few enough bit
+/// errors are reported.
+pub struct FingerprintMatcher {
+    match_threshold: f64,
+}
+
+/// A segment with positions and duration in items.
+#[derive(Debug, Clone, Copy)]
+struct ItemSegment {
+    pos1: usize,
+    pos2: usize,
+    duration: usize,
+    score: f64,
+}
+
+impl ItemSegment {
+    fn merged(&self, other: &ItemSegment) -> ItemSegment {
+        let duration = self.duration + other.duration;
+
+        ItemSegment {
+            pos1: self.pos1,
+            pos2: self.pos2,
+            duration,
+            score: (self.score * self.duration as f64 + other.score * other.duration as f64)
+                / duration as f64,
+        }
+    }
+
+    fn overlaps(&self, other: &ItemSegment) -> bool {
+        fn ranges_overlap(a: usize, b: usize, duration_a: usize, duration_b: usize) -> bool {
+            a < b + duration_b && b < a + duration_a
+        }
+
+        ranges_overlap(self.pos1, other.pos1, self.duration, other.duration)
+            || ranges_overlap(self.pos2, other.pos2, self.duration, other.duration)
+    }
+}
+
+impl Default for FingerprintMatcher {
+    fn default() -> FingerprintMatcher {
+        FingerprintMatcher::new()
+    }
+}
+
+impl FingerprintMatcher {
+    pub fn new() -> FingerprintMatcher {
+        FingerprintMatcher {
+            match_threshold: DEFAULT_MATCH_THRESHOLD,
+        }
+    }
+
+    /// Sets the highest average number of differing bits per item a matching segment can have.
+    pub fn with_match_threshold(match_threshold: f64) -> FingerprintMatcher {
+        FingerprintMatcher { match_threshold }
+    }
+
+    /// Finds the matching segments of two fingerprints, ordered by their position in `a`.
+    ///
+    /// Times are derived from the item duration of `a`, and items made of several words are
+    /// aligned by their first word.
+    pub fn find_segments(&self, a: &Fingerprint, b: &Fingerprint) -> Vec<Segment> {
+        let item_duration = a.item_duration();
+        let words = a.words();
+        let mut segments: Vec<ItemSegment> = Vec::new();
+
+        for offset in find_alignments(a.items(), b.items(), words) {
+            for segment in self.segments_at_offset(a.items(), b.items(), words, offset) {
+                if !segments.iter().any(|existing| existing.overlaps(&segment)) {
+                    segments.push(segment);
+                }
+            }
+        }
+
+        segments.sort_by_key(|segment| segment.pos1);
+        segments
+            .into_iter()
+            .map(|segment| Segment {
+                pos1: segment.pos1 as f64 * item_duration,
+                pos2: segment.pos2 as f64 * item_duration,
+                duration: segment.duration as f64 * item_duration,
+                score: segment.score,
+            })
+            .collect()
+    }
+
+    fn segments_at_offset(
+        &self,
+        a: &[u32],
+        b: &[u32],
+        words: usize,
+        offset: i32,
+    ) -> Vec<ItemSegment> {
+        let offset1 = offset.max(0) as usize;
+        let offset2 = (-offset).max(0) as usize;
+        let (a, b) = overlap(a, b, offset * words as i32);
+
+        let errors: Vec<f64> = a
+            .chunks_exact(words)
+            .zip(b.chunks_exact(words))
+            .map(|(a, b)| bit_errors(a, b) as f64 / words as f64)
+            .collect();
+        let gradient: Vec<f64> =
+            gradient(&gaussian_filter(&errors, SMOOTHING_SIGMA, SMOOTHING_PASSES))
+                .into_iter()
+                .map(f64::abs)
+                .collect();
+
+        let size = errors.len();
+        let mut edges = Vec::new();
+        for idx in 1..size.saturating_sub(1) {
+            let value = gradient[idx];
+            let is_peak = value >= gradient[idx - 1] && value >= gradient[idx + 1];
+            if value > EDGE_GRADIENT && is_peak && edges.last().is_none_or(|&last| last + 1 < idx) {
+                edges.push(idx);
+            }
+        }
+        edges.push(size);
+
+        let mut segments: Vec<ItemSegment> = Vec::new();
+        let mut begin = 0;
+        for end in edges {
+            let duration = end - begin;
+            let score = errors[begin..end].iter().sum::<f64>() / duration as f64;
+
+            if score < self.match_threshold {
+                let segment = ItemSegment {
+                    pos1: offset1 + begin,
+                    pos2: offset2 + begin,
+                    duration,
+                    score,
+                };
+
+                match segments.last_mut() {
+                    Some(last)
+                        if last.pos1 + last.duration == segment.pos1
+                            && (last.score - score).abs() < MERGE_SCORE_DIFFERENCE =>
+                    {
+                        *last = last.merged(&segment)
+                    }
+                    _ => segments.push(segment),
+                }
+            }
+
+            begin = end;
+        }
+
+        segments
+    }
+}
+
+/// Finds the offsets of `b` relative to `a` at which many items of `words` words have the same
+/// hash in their first word, most promising first. At most `MAX_ALIGNMENTS` are returned.
+fn find_alignments(a: &[u32], b: &[u32], words: usize) -> Vec<i32> {
+    // Sorting puts items with the same hash next to each other, with the ones from `a` first.
+    let mut hashes: Vec<(u32, bool, usize)> = a
+        .iter()
+        .step_by(words)
+        .enumerate()
+        .map(|(idx, item)| (item >> (32 - HASH_BITS), false, idx))
+        .chain(
+            b.iter()
+                .step_by(words)
+                .enumerate()
+                .map(|(idx, item)| (item >> (32 - HASH_BITS), true, idx)),
+        )
+        .collect();
+    hashes.sort_unstable();
+
+    let (a_len, b_len) = (a.len().div_ceil(words), b.len().div_ceil(words));
+    let mut histogram = vec![0u32; a_len + b_len];
+    for (idx, &(hash, from_b, idx1)) in hashes.iter().enumerate() {
+        if from_b {
+            continue;
+        }
+
+        for &(_, _, idx2) in hashes[(idx + 1)..]
+            .iter()
+            .take_while(|other| other.0 == hash)
+            .filter(|other| other.1)
+        {
+            histogram[idx1 + b_len - idx2] += 1;
+        }
+    }
+
+    let mut peaks = Vec::new();
+    for (idx, &count) in histogram.iter().enumerate() {
+        let is_peak_left = idx == 0 || histogram[idx - 1] <= count;
+        let is_peak_right = idx + 1 == histogram.len() || histogram[idx + 1] <= count;
+
+        if count > 1 && is_peak_left && is_peak_right {
+            peaks.push((count, idx));
+        }
+    }
+    peaks.sort_unstable_by(|a, b| b.cmp(a));
+
+    peaks
+        .into_iter()
+        .take(MAX_ALIGNMENTS)
+        .map(|(_, idx)| idx as i32 - b_len as i32)
+        .collect()
+}
+
+/// Approximates a gaussian filter by repeatedly applying box filters.
+fn gaussian_filter(input: &[f64], sigma: f64, passes: usize) -> Vec<f64> {
+    let n = passes as f64;
+    let ideal_width = (12.0 * sigma * sigma / n + 1.0).sqrt().floor() as usize;
+    let lower_width = if ideal_width.is_multiple_of(2) {
+        ideal_width - 1
+    } else {
+        ideal_width
+    };
+    let upper_width = lower_width + 2;
+    let wl = lower_width as f64;
+    let lower_passes = ((12.0 * sigma * sigma - n * wl * wl - 4.0 * n * wl - 3.0 * n)
+        / (-4.0 * wl - 4.0))
+        .round() as usize;
+
+    let mut output = input.to_vec();
+    for pass in 0..passes {
+        let width = if pass < lower_passes {
+            lower_width
+        } else {
+            upper_width
+        };
+
+        output = box_filter(&output, width);
+    }
+
+    output
+}
+
+/// Averages every value with its neighbours, reflecting the input at its edges.
+fn box_filter(input: &[f64], width: usize) -> Vec<f64> {
+    let size = input.len() as isize;
+    let left = (width / 2) as isize;
+
+    (0..size)
+        .map(|idx| {
+            let sum: f64 = (0..width as isize)
+                .map(|pos| input[reflect(idx - left + pos, size)])
+                .sum();
+            sum / width as f64
+        })
+        .collect()
+}
+
+fn reflect(mut idx: isize, size: isize) -> usize {
+    loop {
+        if idx < 0 {
+            idx = -idx - 1;
+        } else if idx >= size {
+            idx = 2 * size - idx - 1;
+        } else {
+            return idx as usize;
+        }
+    }
+}
+
+/// Computes the gradient using central differences in the interior and one sided differences at
+/// the edges.
+fn gradient(input: &[f64]) -> Vec<f64> {
+    let size = input.len();
+    if size < 2 {
+        return vec![0.0; size];
+    }
+
+    (0..size)
+        .map(|idx| {
+            if idx == 0 {
+                input[1] - input[0]
+            } else if idx == size - 1 {
+                input[size - 1] - input[size - 2]
+            } else {
+                (input[idx + 1] - input[idx - 1]) / 2.0
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        box_filter, find_alignments, gaussian_filter, gradient, FingerprintMatcher, MAX_ALIGNMENTS,
+    };
+    use algorithm::Algorithm;
+    use fingerprinter::Fingerprint;
+    use std::error::Error;
+    use std::path::PathBuf;
+    use tests;
+
+    const SAMPLE_RATE: u32 = 11025;
+
+    #[test]
+    fn test_box_filter() {
+        assert_eq!(vec![1.0, 2.0, 3.0], box_filter(&[1.0, 2.0, 3.0], 1));
+        assert_eq!(
+            vec![4.0 / 3.0, 2.0, 8.0 / 3.0],
+            box_filter(&[1.0, 2.0, 3.0], 3)
+        );
+    }
+
+    #[test]
+    fn test_gaussian_filter_keeps_constant() {
+        let input = vec![5.0; 40];
+        for value in gaussian_filter(&input, 8.0, 3) {
+            assert_ulps_eq!(5.0, value, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_gradient() {
+        assert_eq!(vec![1.0, 1.5, 2.0], gradient(&[1.0, 2.0, 4.0]));
+        assert_eq!(vec![0.0], gradient(&[1.0]));
+    }
+
+    #[test]
+    fn test_find_alignments() {
+        let a: Vec<u32> = (0..50).map(|idx| idx << 12).collect();
+        let b = &a[10..];
+
+        assert_eq!(Some(&10), find_alignments(&a, b, 1).first());
+        assert_eq!(Some(&-10), find_alignments(b, &a, 1).first());
+
+        // Repeating every 10 items gives a peak at every multiple of 10.
+        let repeating: Vec<u32> = (0..200).map(|idx| (idx % 10) << 12).collect();
+        let alignments = find_alignments(&repeating, &repeating, 1);
+        assert_eq!(MAX_ALIGNMENTS, alignments.len());
+        assert_eq!(0, alignments[0]);
+    }
+
+    #[test]
+    fn test_identical() -> Result<(), Box<dyn Error>> {
+        let raw =
+            tests::fingerprint_samples(&tests::generate_chords(SAMPLE_RATE, 20), SAMPLE_RATE, 1)?;
+        let a = Fingerprint::new(&raw, Algorithm::default());
+
+        let segments = FingerprintMatcher::new().find_segments(&a, &a);
+        assert_eq!(1, segments.len());
+        assert_eq!(0.0, segments[0].pos1);
+        assert_eq!(0.0, segments[0].pos2);
+        assert_eq!(0.0, segments[0].score);
+        assert_ulps_eq!(
+            raw.len() as f64 * Algorithm::default().item_duration(),
+            segments[0].duration
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit() -> Result<(), Box<dyn Error>> {
+        let samples = tests::generate_chords(SAMPLE_RATE, 40);
+        let rate = SAMPLE_RATE as usize;
+
+        // Cuts the seconds 15 to 20 out of the recording.
+        let mut edit = samples[..(15 * rate)].to_vec();
+        edit.extend_from_slice(&samples[(20 * rate)..]);
+
+        let raw_a = tests::fingerprint_samples(&samples, SAMPLE_RATE, 1)?;
+        let raw_b = tests::fingerprint_samples(&edit, SAMPLE_RATE, 1)?;
+        let a = Fingerprint::new(&raw_a, Algorithm::default());
+        let b = Fingerprint::new(&raw_b, Algorithm::default());
+
+        let segments = FingerprintMatcher::new().find_segments(&a, &b);
+        assert_eq!(2, segments.len());
+
+        let first = segments[0];
+        assert_eq!(0.0, first.pos1);
+        assert_eq!(0.0, first.pos2);
+        assert!((first.duration - 15.0).abs() < 2.0, "{:?}", first);
+
+        let second = segments[1];
+        assert!((second.pos1 - 20.0).abs() < 2.0, "{:?}", second);
+        assert!((second.pos2 - 15.0).abs() < 2.0, "{:?}", second);
+        let end = raw_a.len() as f64 * Algorithm::default().item_duration();
+        assert!(
+            (second.pos1 + second.duration - end).abs() < 0.5,
+            "{:?}",
+            second
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unrelated() -> Result<(), Box<dyn Error>> {
+        let samples = tests::load_audio_file(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw"),
+        )?;
+        let raw_a =
+            tests::fingerprint_samples(&tests::generate_chords(SAMPLE_RATE, 20), SAMPLE_RATE, 1)?;
+        let raw_b = tests::fingerprint_samples(&samples, SAMPLE_RATE, 1)?;
+        let a = Fingerprint::new(&raw_a, Algorithm::default());
+        let b = Fingerprint::new(&raw_b, Algorithm::default());
+
+        assert!(FingerprintMatcher::new().find_segments(&a, &b).is_empty());
+
+        Ok(())
+    }
+}
@@ -1,95 +1,694 @@
-use audio_processor::AudioProcessor;
-use chroma::Chroma;
-use chroma_filter::{ChromaFilter, FILTER_COEFFICIENTS};
-use chroma_normalize::normalize_vector;
-use classifiers;
-use encode;
-use fft::Fft;
+use algorithm::Algorithm;
+use builder::Config;
+use chroma_extractor::ChromaExtractor;
+use encode::{self, DecodeError};
+use error::Error;
 use fingerprint_calculator::FingerprintCalculator;
 use fingerprint_compressor;
+use fingerprint_decompressor::{self, DecompressError};
+use sample::Sample;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use simhash;
+use std::convert::TryFrom;
 
-pub const TARGET_SAMPLE_RATE: u16 = 11025;
+pub const TARGET_SAMPLE_RATE: u32 = 11025;
 pub const MIN_FREQ: u32 = 28;
 pub const MAX_FREQ: u32 = 3520;
-pub const FRAME_SIZE: usize = 4096;
+
+/// Whether a `Fingerprinter` accepts more audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedStatus {
+    /// The fingerprinter needs more audio.
+    NeedsMore,
+
+    /// The maximum duration has been reached. Any further samples are ignored.
+    Done,
+}
+
+/// The fingerprint of a part of the input, produced when a `Fingerprinter` splits its input into
+/// chunks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// Start of the audio covered by the fingerprint, in seconds from the start of the input.
+    pub start: f64,
+
+    /// Duration of the audio covered by the fingerprint, in seconds.
+    pub duration: f64,
+
+    pub fingerprint: Vec<u32>,
+    pub algorithm: Algorithm,
+    pub custom: Option<CustomItems>,
+}
+
+impl Chunk {
+    pub fn as_fingerprint(&self) -> Fingerprint<'_> {
+        Fingerprint {
+            items: &self.fingerprint,
+            algorithm: self.algorithm,
+            custom: self.custom,
+        }
+    }
+}
+
+/// Describes the items of a fingerprint computed with settings changed with a
+/// `FingerprinterBuilder`, which differ from those of its algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CustomItems {
+    /// Number of samples, at `sample_rate`, between the starts of two consecutive items.
+    pub hop_size: usize,
+
+    /// The sample rate audio was resampled to.
+    pub sample_rate: u32,
+
+    /// Number of 32-bit words each item is made of, more than 1 with more than 16 classifiers.
+    pub words: usize,
+}
+
+impl CustomItems {
+    /// Duration in seconds of the audio between the starts of two consecutive items.
+    pub fn item_duration(&self) -> f64 {
+        self.hop_size as f64 / self.sample_rate as f64
+    }
+}
+
+/// A single item of a fingerprint, or a word of one if items are made of several words, returned
+/// by `Fingerprinter::drain`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Subfingerprint {
+    /// Position of the item among all items computed from the input.
+    pub index: usize,
+
+    /// Start of the audio the item is computed from, in seconds from the start of the input.
+    pub timestamp: f64,
+
+    /// Position of the word in the item, always 0 unless items are made of several words.
+    pub word: usize,
+    pub value: u32,
+}
+
+/// Progress through the current chunk when the input is split into chunks.
+struct Chunking {
+    /// Number of interleaved samples in each chunk.
+    chunk_samples: u64,
+
+    /// Whether the pipeline carries on between chunks instead of starting over.
+    overlap: bool,
+
+    /// Interleaved samples added to the current chunk on top of `chunk_samples`.
+    extra_samples: u64,
+    fed_samples: u64,
+    start: f64,
+}
 
 pub struct Fingerprinter {
-    audio_processor: Option<AudioProcessor>,
-    fft: Option<Fft>,
-    chroma: Chroma,
-    chroma_filter: ChromaFilter,
+    config: Config,
+    sample_rate: u32,
+    channels: u16,
+
+    max_duration: Option<f64>,
+
+    /// Number of interleaved samples after which input is ignored.
+    max_samples: Option<u64>,
+    fed_samples: u64,
+
+    chunk_duration: Option<f64>,
+    overlap: bool,
+    chunking: Option<Chunking>,
+    chunks: Vec<Chunk>,
+
+    /// Whether `finish` has been called since the last reset.
+    finished: bool,
+
+    /// Computes the normalized chroma vectors the fingerprint is computed from.
+    extractor: ChromaExtractor,
     fingerprint_calculator: FingerprintCalculator,
 }
 
 impl Fingerprinter {
-    pub fn new(sample_rate: u16) -> Fingerprinter {
-        Fingerprinter {
-            audio_processor: Some(AudioProcessor::new(TARGET_SAMPLE_RATE, sample_rate)),
-            fft: Some(Fft::new()),
-            chroma: Chroma::new(
-                MIN_FREQ,
-                MAX_FREQ,
-                FRAME_SIZE as u32,
-                TARGET_SAMPLE_RATE as u32,
-            ),
-            chroma_filter: ChromaFilter::new(&FILTER_COEFFICIENTS),
-            fingerprint_calculator: FingerprintCalculator::new(
-                classifiers::get_default_classifier(),
-            ),
-        }
-    }
-
-    pub fn feed(&mut self, raw_pcm: &[i16]) {
-        let mut audio_processor = self.audio_processor.take().unwrap();
-        let mut fft = self.fft.take().unwrap();
-
-        audio_processor.feed(raw_pcm, |samples| {
-            self.handle_resampled(samples, &mut fft);
-        });
+    /// Creates a fingerprinter using the default algorithm.
+    ///
+    /// # Arguments
+    /// * `sample_rate` - The sample rate of the input.
+    /// * `channels` - The number of interleaved channels in the input. These are averaged into a
+    ///   single channel before fingerprinting.
+    ///
+    /// # Errors
+    /// `Error::InvalidSampleRate` if the sample rate is 1000 Hz or lower, or above 768 kHz, and
+    /// `Error::InvalidChannelCount` if `channels` is zero.
+    pub fn new(sample_rate: u32, channels: u16) -> Result<Fingerprinter, Error> {
+        Fingerprinter::with_algorithm(sample_rate, channels, Algorithm::default())
+    }
+
+    /// Creates a fingerprinter using one of the standard algorithms. `FingerprinterBuilder`
+    /// creates fingerprinters with other settings.
+    pub fn with_algorithm(
+        sample_rate: u32,
+        channels: u16,
+        algorithm: Algorithm,
+    ) -> Result<Fingerprinter, Error> {
+        Fingerprinter::with_config(sample_rate, channels, Config::new(algorithm))
+    }
+
+    /// Creates a fingerprinter with settings which have been validated.
+    pub(crate) fn with_config(
+        sample_rate: u32,
+        channels: u16,
+        config: Config,
+    ) -> Result<Fingerprinter, Error> {
+        Ok(Fingerprinter {
+            sample_rate,
+            channels,
+            max_duration: None,
+            max_samples: None,
+            fed_samples: 0,
+            chunk_duration: None,
+            overlap: false,
+            chunking: None,
+            chunks: Vec::new(),
+            finished: false,
+            extractor: ChromaExtractor::with_config(sample_rate, channels, &config)?,
+            fingerprint_calculator: FingerprintCalculator::new(&config.classifiers),
+            config,
+        })
+    }
+
+    /// Enables or disables skipping the leading silence of the input, overriding the default of
+    /// the algorithm. Audio is silent until the average absolute amplitude of 55 consecutive
+    /// samples exceeds `threshold`, on a 16-bit scale.
+    ///
+    /// Like in Chromaprint, silence is detected after resampling. This must be called before
+    /// feeding any samples.
+    pub fn set_silence_threshold(&mut self, threshold: Option<i32>) {
+        self.extractor.set_silence_threshold(threshold);
+    }
+
+    /// Number of leading silent samples, at the 11025 Hz target sample rate, which were skipped.
+    /// The fingerprint starts this many samples into the input.
+    pub fn removed_samples(&self) -> usize {
+        self.extractor.removed_samples()
+    }
+
+    /// Duration in seconds of the leading silence which was skipped.
+    pub fn removed_duration(&self) -> f64 {
+        self.extractor.removed_duration()
+    }
+
+    /// Limits the fingerprint to the first `max_duration` seconds of the input, like the
+    /// `-length` option of `fpcalc`. `None` fingerprints the whole input, and so does a duration
+    /// too long to count in samples, like infinity.
+    ///
+    /// # Errors
+    /// `Error::InvalidDuration` if `max_duration` is negative or not a number, in which case the
+    /// previous limit is kept.
+    pub fn set_max_duration(&mut self, max_duration: Option<f64>) -> Result<(), Error> {
+        if max_duration.is_some_and(|max_duration| max_duration.is_nan() || max_duration < 0.0) {
+            return Err(Error::InvalidDuration(
+                "the maximum duration must be a number of seconds, at least 0",
+            ));
+        }
+
+        self.max_duration = max_duration;
+        self.update_max_samples();
+        Ok(())
+    }
 
-        self.fft = Some(fft);
-        self.audio_processor = Some(audio_processor);
+    fn update_max_samples(&mut self) {
+        self.max_samples = self
+            .max_duration
+            .map(|max_duration| self.duration_samples(max_duration));
     }
 
-    pub fn finish(&mut self) {
-        let mut fft = self.fft.take().unwrap();
+    /// Number of interleaved samples making up `duration` seconds of input, saturating at
+    /// `u64::MAX`.
+    fn duration_samples(&self, duration: f64) -> u64 {
+        // Casting a float to an integer saturates.
+        ((duration * self.sample_rate as f64).round() as u64).saturating_mul(self.channels as u64)
+    }
 
-        if let Some(last_samples) = self.audio_processor.as_mut().unwrap().flush() {
-            self.handle_resampled(last_samples, &mut fft)
+    /// Splits the input into chunks of `chunk_duration` seconds and fingerprints each of them,
+    /// like the `-chunk` and `-overlap` options of `fpcalc`. `None` fingerprints the input as a
+    /// whole. Completed chunks are returned by `take_chunks`.
+    ///
+    /// Without `overlap` the pipeline is reset at the end of every chunk, like `fpcalc` does, so
+    /// every chunk is fingerprinted from scratch and only the precomputed tables are reused. With
+    /// `overlap` the pipeline carries on from one chunk to the next, so the fingerprint of each
+    /// chunk also covers the `Algorithm::delay_duration` seconds of audio before it. To make up
+    /// for that, the first chunk is extended by the same amount. A chunk duration too long to
+    /// count in samples, like infinity, makes a single chunk of the whole input. This must be
+    /// called before feeding any samples.
+    ///
+    /// # Errors
+    /// `Error::InvalidDuration` if `chunk_duration` isn't a positive number, in which case the
+    /// previous settings are kept.
+    pub fn set_chunk_duration(
+        &mut self,
+        chunk_duration: Option<f64>,
+        overlap: bool,
+    ) -> Result<(), Error> {
+        if chunk_duration
+            .is_some_and(|chunk_duration| chunk_duration.is_nan() || chunk_duration <= 0.0)
+        {
+            return Err(Error::InvalidDuration(
+                "the chunk duration must be a positive number of seconds",
+            ));
         }
 
-        self.fft = Some(fft);
+        self.chunk_duration = chunk_duration;
+        self.overlap = overlap;
+        self.restart_chunks();
+        Ok(())
+    }
+
+    fn restart_chunks(&mut self) {
+        self.chunking = self.chunk_duration.map(|chunk_duration| Chunking {
+            chunk_samples: self
+                .duration_samples(chunk_duration)
+                .max(self.channels as u64),
+            overlap: self.overlap,
+            extra_samples: if self.overlap {
+                self.duration_samples(self.config.delay_duration())
+            } else {
+                0
+            },
+            fed_samples: 0,
+            start: 0.0,
+        });
+    }
+
+    /// Returns the chunks completed since the last call.
+    pub fn take_chunks(&mut self) -> Vec<Chunk> {
+        std::mem::take(&mut self.chunks)
     }
 
-    fn handle_resampled(&mut self, samples: Vec<i16>, fft: &mut Fft) {
-        fft.consume(&samples, |frame| {
-            let features = self.chroma.handle_frame(&frame);
-            if let Some(filtered) = self.chroma_filter.handle_features(features) {
-                let normalized_features = normalize_vector(filtered);
-                self.fingerprint_calculator.consume(normalized_features);
+    /// Feeds interleaved samples into the fingerprinter.
+    ///
+    /// # Returns
+    /// `FeedStatus::Done` once the maximum duration has been reached, after which the caller can
+    /// stop decoding and call `finish`. Only the samples up to the maximum duration are used.
+    ///
+    /// # Errors
+    /// `Error::AlreadyFinished` if `finish` was called since the fingerprinter was created, reset
+    /// or started.
+    pub fn feed<S: Sample>(&mut self, mut raw_pcm: &[S]) -> Result<FeedStatus, Error> {
+        if self.finished {
+            return Err(Error::AlreadyFinished);
+        }
+
+        if let Some(max_samples) = self.max_samples {
+            let remaining = max_samples.saturating_sub(self.fed_samples);
+            if (raw_pcm.len() as u64) >= remaining {
+                raw_pcm = &raw_pcm[..remaining as usize];
+            }
+        }
+        self.fed_samples += raw_pcm.len() as u64;
+
+        while let Some(ref mut chunking) = self.chunking {
+            let remaining = chunking
+                .chunk_samples
+                .saturating_add(chunking.extra_samples)
+                - chunking.fed_samples;
+            if (raw_pcm.len() as u64) < remaining {
+                chunking.fed_samples += raw_pcm.len() as u64;
+                break;
             }
+
+            chunking.fed_samples += remaining;
+            let (chunk_end, rest) = raw_pcm.split_at(remaining as usize);
+            self.process(chunk_end);
+            self.complete_chunk();
+            raw_pcm = rest;
+        }
+        self.process(raw_pcm);
+
+        Ok(self.status())
+    }
+
+    fn process<S: Sample>(&mut self, raw_pcm: &[S]) {
+        self.extractor.process(raw_pcm);
+        self.consume_frames();
+    }
+
+    /// Whether the maximum duration has been reached.
+    pub fn status(&self) -> FeedStatus {
+        match self.max_samples {
+            Some(max_samples) if self.fed_samples >= max_samples => FeedStatus::Done,
+            _ => FeedStatus::NeedsMore,
+        }
+    }
+
+    /// Processes the audio still buffered and completes the fingerprint. Afterwards, nothing can
+    /// be fed until the fingerprinter is reset or started again.
+    ///
+    /// # Errors
+    /// `Error::AlreadyFinished` if `finish` was already called.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if self.finished {
+            return Err(Error::AlreadyFinished);
+        }
+        self.finished = true;
+        self.flush();
+
+        if self
+            .chunking
+            .as_ref()
+            .is_some_and(|chunking| chunking.fed_samples > 0)
+        {
+            self.complete_chunk();
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        self.extractor.flush();
+        self.consume_frames();
+    }
+
+    fn consume_frames(&mut self) {
+        for frame in self.extractor.drain() {
+            self.fingerprint_calculator.consume(frame.chroma);
+        }
+    }
+
+    /// Emits the fingerprint of the current chunk and prepares for the next one.
+    fn complete_chunk(&mut self) {
+        self.flush();
+
+        let algorithm = self.config.algorithm;
+        let custom = self.config.custom_items();
+        let delay_duration = self.config.delay_duration();
+        let samples_per_second = self.sample_rate as f64 * self.channels as f64;
+        let chunking = self.chunking.as_mut().unwrap();
+
+        // Apart from the first one, overlapping chunks also cover the audio of the delay before
+        // them.
+        let overlap = if chunking.overlap && chunking.extra_samples == 0 {
+            delay_duration
+        } else {
+            0.0
+        };
+        let duration = chunking.fed_samples as f64 / samples_per_second + overlap;
+        self.chunks.push(Chunk {
+            start: chunking.start - overlap,
+            duration,
+            fingerprint: self.fingerprint_calculator.fingerprint().to_vec(),
+            algorithm,
+            custom,
         });
+
+        chunking.start += duration - overlap;
+        chunking.extra_samples = 0;
+        chunking.fed_samples = 0;
+
+        if chunking.overlap {
+            self.fingerprint_calculator.clear_fingerprint();
+        } else {
+            self.reset_pipeline();
+        }
+    }
+
+    /// Discards all audio fed so far to start over with a new input of the same format. The
+    /// settings and the precomputed tables are kept, which is cheaper than creating a new
+    /// fingerprinter.
+    pub fn reset(&mut self) {
+        self.reset_pipeline();
+        self.fed_samples = 0;
+        self.chunks.clear();
+        self.finished = false;
+
+        // Recomputes the limits for the current input format and restarts the chunks.
+        self.update_max_samples();
+        self.restart_chunks();
+    }
+
+    /// Like `reset`, but for an input with a possibly different format. Resampling tables are
+    /// cached, so going back to a sample rate used before is cheap.
+    ///
+    /// # Errors
+    /// `Error::InvalidSampleRate` or `Error::InvalidChannelCount` under the same conditions as
+    /// `new`, in which case the fingerprinter is left unchanged.
+    pub fn start(&mut self, sample_rate: u32, channels: u16) -> Result<(), Error> {
+        self.extractor.start(sample_rate, channels)?;
+
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        self.reset();
+        Ok(())
     }
 
-    pub fn fingerprint(&self) -> Fingerprint {
-        Fingerprint(self.fingerprint_calculator.fingerprint())
+    /// Starts a new fingerprint from scratch, as if no samples had been fed.
+    fn reset_pipeline(&mut self) {
+        self.extractor.reset();
+        self.fingerprint_calculator.reset();
+    }
+
+    /// Removes the items computed so far from the fingerprint and returns them along with their
+    /// position in the input. Draining after every call to `feed` keeps memory use constant
+    /// however long the input is, which suits live streams.
+    ///
+    /// Items drained while the input is split into chunks are missing from the chunks.
+    pub fn drain(&mut self) -> impl Iterator<Item = Subfingerprint> + '_ {
+        let item_duration = self.config.item_duration();
+        let silence = self.removed_duration();
+        let words = self.fingerprint_calculator.words();
+        let (first, items) = self.fingerprint_calculator.drain();
+
+        items.enumerate().map(move |(idx, value)| {
+            let index = (first + idx) / words;
+            Subfingerprint {
+                index,
+                timestamp: silence + index as f64 * item_duration,
+                word: (first + idx) % words,
+                value,
+            }
+        })
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint<'_> {
+        Fingerprint {
+            items: self.fingerprint_calculator.fingerprint(),
+            algorithm: self.config.algorithm,
+            custom: self.config.custom_items(),
+        }
+    }
+
+    /// Copies the fingerprint along with the duration of the input fed so far.
+    pub fn to_owned_fingerprint(&self) -> OwnedFingerprint {
+        OwnedFingerprint {
+            sample_count: self.fed_samples / self.channels as u64,
+            sample_rate: self.sample_rate,
+            ..OwnedFingerprint::from(self.fingerprint())
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.config.algorithm
     }
 }
 
-pub struct Fingerprint<'a>(pub &'a [u32]);
+/// Raw subfingerprints along with the algorithm used to compute them, and how their items differ
+/// from those of the algorithm if they were computed with custom settings.
+///
+/// Items made of several words are stored one after the other. `compare` and
+/// `FingerprintMatcher` compare them whole, while indexes and images treat each word as an item.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fingerprint<'a> {
+    items: &'a [u32],
+    algorithm: Algorithm,
+    custom: Option<CustomItems>,
+}
 
 impl<'a> Fingerprint<'a> {
-    pub fn compress(&self) -> CompressedFingerprint {
-        CompressedFingerprint(fingerprint_compressor::compress(self.0, 1))
+    /// Wraps items computed with the standard settings of `algorithm`.
+    pub fn new(items: &'a [u32], algorithm: Algorithm) -> Fingerprint<'a> {
+        Fingerprint {
+            items,
+            algorithm,
+            custom: None,
+        }
+    }
+
+    /// Wraps items computed by a fingerprinter from a `FingerprinterBuilder` based on `algorithm`,
+    /// with the custom settings it reports in `FingerprinterBuilder::custom_items`.
+    pub fn with_custom(
+        items: &'a [u32],
+        algorithm: Algorithm,
+        custom: CustomItems,
+    ) -> Fingerprint<'a> {
+        Fingerprint {
+            items,
+            algorithm,
+            custom: Some(custom),
+        }
+    }
+
+    pub fn items(&self) -> &'a [u32] {
+        self.items
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// How the items differ from those of the algorithm, or `None` if they were computed with its
+    /// standard settings.
+    pub fn custom(&self) -> Option<CustomItems> {
+        self.custom
+    }
+
+    /// # Errors
+    /// `Error::FingerprintTooLong` if the fingerprint has more than 2^24 - 1 items, the most the
+    /// compressed header can hold, and `Error::CustomFingerprint` if it was computed with custom
+    /// settings, which the header can't describe.
+    pub fn compress(&self) -> Result<CompressedFingerprint, Error> {
+        if self.custom.is_some() {
+            return Err(Error::CustomFingerprint);
+        }
+
+        fingerprint_compressor::compress(self.items, self.algorithm.id()).map(CompressedFingerprint)
+    }
+
+    /// Number of 32-bit words each item is made of.
+    pub fn words(&self) -> usize {
+        self.custom.map_or(1, |custom| custom.words)
+    }
+
+    /// Number of items, rather than words.
+    pub fn len(&self) -> usize {
+        self.items.len() / self.words()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Duration in seconds of the audio between the starts of two consecutive items.
+    pub fn item_duration(&self) -> f64 {
+        match self.custom {
+            Some(custom) => custom.item_duration(),
+            None => self.algorithm.item_duration(),
+        }
+    }
+
+    /// Hashes the whole fingerprint into 32 bits, like `chromaprint_hash_fingerprint`. Similar
+    /// fingerprints have hashes with a small `compare::hamming_distance`.
+    pub fn simhash(&self) -> u32 {
+        simhash::simhash(self.items)
+    }
+}
+
+/// A fingerprint which owns its items, so it can outlive the `Fingerprinter`, be sent to other
+/// threads and be stored. With the `serde` feature it can be serialized too.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OwnedFingerprint {
+    pub items: Vec<u32>,
+    pub algorithm: Algorithm,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub custom: Option<CustomItems>,
+
+    /// Number of input samples per channel the fingerprint was computed from, or 0 if unknown.
+    pub sample_count: u64,
+
+    /// Sample rate of the input, or 0 if unknown.
+    pub sample_rate: u32,
+}
+
+impl OwnedFingerprint {
+    /// Decompresses a fingerprint. The input duration is unknown afterwards.
+    ///
+    /// # Errors
+    /// `Error::Decompress` if the fingerprint is malformed, or `Error::UnknownAlgorithm` if its
+    /// header names an algorithm which doesn't exist.
+    pub fn from_compressed(compressed: &CompressedFingerprint) -> Result<OwnedFingerprint, Error> {
+        let (items, algorithm) = compressed.decompress()?;
+
+        Ok(OwnedFingerprint {
+            items,
+            algorithm: Algorithm::try_from(algorithm)?,
+            custom: None,
+            sample_count: 0,
+            sample_rate: 0,
+        })
+    }
+
+    /// Decodes and decompresses a fingerprint in the base64 form produced by `encode`.
+    pub fn decode(encoded: &str) -> Result<OwnedFingerprint, Error> {
+        OwnedFingerprint::from_compressed(&CompressedFingerprint::decode(encoded)?)
+    }
+
+    pub fn as_fingerprint(&self) -> Fingerprint<'_> {
+        Fingerprint {
+            items: &self.items,
+            algorithm: self.algorithm,
+            custom: self.custom,
+        }
+    }
+
+    /// # Errors
+    /// The errors of `Fingerprint::compress`.
+    pub fn compress(&self) -> Result<CompressedFingerprint, Error> {
+        self.as_fingerprint().compress()
+    }
+
+    /// Compresses and encodes the fingerprint to the base64 form used by AcoustID.
+    pub fn encode(&self) -> Result<String, Error> {
+        self.compress().map(|compressed| compressed.encode())
+    }
+
+    /// Duration in seconds of the input the fingerprint was computed from, if known.
+    pub fn duration(&self) -> Option<f64> {
+        if self.sample_rate == 0 {
+            return None;
+        }
+
+        Some(self.sample_count as f64 / self.sample_rate as f64)
     }
 }
 
+impl<'a> From<Fingerprint<'a>> for OwnedFingerprint {
+    fn from(fingerprint: Fingerprint<'a>) -> OwnedFingerprint {
+        OwnedFingerprint {
+            items: fingerprint.items().to_vec(),
+            algorithm: fingerprint.algorithm(),
+            custom: fingerprint.custom(),
+            sample_count: 0,
+            sample_rate: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CompressedFingerprint(pub Vec<u8>);
 
 impl CompressedFingerprint {
+    /// Decodes a fingerprint in the URL-safe, unpadded base64 form produced by `encode`.
+    pub fn decode(encoded: &str) -> Result<CompressedFingerprint, DecodeError> {
+        encode::decode(encoded, false).map(CompressedFingerprint)
+    }
+
+    /// Decodes a fingerprint like `decode`, but also accepts surrounding whitespace, padding and
+    /// the standard base64 alphabet.
+    pub fn decode_lenient(encoded: &str) -> Result<CompressedFingerprint, DecodeError> {
+        encode::decode(encoded, true).map(CompressedFingerprint)
+    }
+
     pub fn encode(&self) -> String {
         encode::encode(&self.0)
     }
+
+    /// Decompresses the fingerprint.
+    ///
+    /// # Returns
+    /// A tuple of the raw fingerprint and the algorithm id stored in the header.
+    pub fn decompress(&self) -> Result<(Vec<u32>, u8), DecompressError> {
+        fingerprint_decompressor::decompress(&self.0)
+    }
 }
 
 #[cfg(test)]
@@ -98,19 +697,23 @@ mod tests {
     use std::path::PathBuf;
     use tests;
 
-    use super::Fingerprinter;
+    use super::{CompressedFingerprint, FeedStatus, Fingerprint, Fingerprinter, OwnedFingerprint};
+    use algorithm::Algorithm;
+    use compare;
+    use error;
+    use sample::I24;
 
     #[test]
     fn test_fingerprinter() -> Result<(), Box<dyn Error>> {
         let samples = tests::load_audio_file(
-            &PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw"),
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw"),
         )?;
 
-        let mut fingerprinter = Fingerprinter::new(44100);
-        fingerprinter.feed(&samples);
-        fingerprinter.finish();
+        let mut fingerprinter = Fingerprinter::new(44100, 1)?;
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
 
-        let fingerprint = fingerprinter.fingerprint().compress().encode();
+        let fingerprint = fingerprinter.fingerprint().compress()?.encode();
 
         // Doesn't exactly match the fingerprint from the C library due to small variances in the
         // FFT library. The fingerprint doesn't need to be an exact match to work with AcoustID.
@@ -121,4 +724,631 @@ mod tests {
 
         Ok(())
     }
+
+    /// Matches `Test2SilenceFp` and `Test2SilenceRawFp` from the C library's API tests.
+    #[test]
+    fn test_fingerprinter_silence_reference() -> Result<(), Box<dyn Error>> {
+        let mut fingerprinter = Fingerprinter::with_algorithm(44100, 1, Algorithm::Test2)?;
+        for _ in 0..130 {
+            fingerprinter.feed(&[0; 1024])?;
+        }
+        fingerprinter.finish()?;
+
+        let fingerprint = fingerprinter.fingerprint();
+        assert_eq!(&[627_964_279; 3], fingerprint.items());
+        assert_eq!("AQAAA0mUaEkSRZEGAA", fingerprint.compress()?.encode());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_fingerprint() -> Result<(), Box<dyn Error>> {
+        let samples = tests::load_audio_file(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw"),
+        )?;
+
+        let mut fingerprinter = Fingerprinter::new(44100, 1)?;
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
+
+        let fingerprint = fingerprinter.fingerprint();
+        let (raw, algorithm) = fingerprint.compress()?.decompress()?;
+
+        assert_eq!(fingerprint.items(), &raw[..]);
+        assert_eq!(fingerprint.algorithm().id(), algorithm);
+
+        let encoded = fingerprint.compress()?.encode();
+        let (decoded, _) = CompressedFingerprint::decode(&encoded)?.decompress()?;
+        assert_eq!(fingerprint.items(), &decoded[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_owned_fingerprint() -> Result<(), Box<dyn Error>> {
+        let samples = tests::load_audio_file(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw"),
+        )?;
+
+        let owned = {
+            let mut fingerprinter = Fingerprinter::new(44100, 1)?;
+            fingerprinter.feed(&samples)?;
+            fingerprinter.finish()?;
+            fingerprinter.to_owned_fingerprint()
+        };
+        assert_eq!(Algorithm::Test2, owned.algorithm);
+        assert_eq!(Some(samples.len() as f64 / 44100.0), owned.duration());
+
+        let encoded = owned.encode()?;
+        assert_eq!(
+            "AQAAC0kkRVHCJEqU4IS6Hs8FH5eh_8jP4ztOHEoYQYwAgABBhog",
+            encoded
+        );
+
+        let decoded = OwnedFingerprint::decode(&encoded)?;
+        assert_eq!(owned.items, decoded.items);
+        assert_eq!(owned.algorithm, decoded.algorithm);
+        assert_eq!(None, decoded.duration());
+        assert_eq!(
+            decoded,
+            OwnedFingerprint::from_compressed(&owned.compress()?)?
+        );
+        assert_eq!(decoded, OwnedFingerprint::from(owned.as_fingerprint()));
+
+        let handle = std::thread::spawn(move || owned.items.len());
+        assert_eq!(decoded.items.len(), handle.join().unwrap());
+
+        let mut unknown_algorithm = decoded.compress()?;
+        unknown_algorithm.0[0] = 5;
+        assert_eq!(
+            Some(error::Error::UnknownAlgorithm(5)),
+            OwnedFingerprint::from_compressed(&unknown_algorithm).err()
+        );
+        assert!(matches!(
+            OwnedFingerprint::decode("!"),
+            Err(error::Error::Decode(_))
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_owned_fingerprint_serde() -> Result<(), Box<dyn Error>> {
+        let fingerprint = OwnedFingerprint {
+            items: vec![1, 2, 3],
+            algorithm: Algorithm::Test4,
+            custom: None,
+            sample_count: 44100,
+            sample_rate: 22050,
+        };
+
+        let json = serde_json::to_string(&fingerprint)?;
+        assert_eq!(
+            r#"{"items":[1,2,3],"algorithm":"Test4","sample_count":44100,"sample_rate":22050}"#,
+            json
+        );
+        assert_eq!(fingerprint, serde_json::from_str(&json)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_algorithms() -> Result<(), Box<dyn Error>> {
+        let samples = tests::load_audio_file(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw"),
+        )?;
+
+        let mut default_fingerprinter = Fingerprinter::new(44100, 1)?;
+        default_fingerprinter.feed(&samples)?;
+        default_fingerprinter.finish()?;
+        let default_fingerprint = default_fingerprinter.fingerprint();
+
+        for &algorithm in &[
+            Algorithm::Test1,
+            Algorithm::Test2,
+            Algorithm::Test3,
+            Algorithm::Test4,
+            Algorithm::Test5,
+        ] {
+            let mut fingerprinter = Fingerprinter::with_algorithm(44100, 1, algorithm)?;
+            fingerprinter.feed(&samples)?;
+            fingerprinter.finish()?;
+
+            let fingerprint = fingerprinter.fingerprint();
+            assert!(!fingerprint.items().is_empty());
+            assert_eq!(algorithm.id(), fingerprint.compress()?.0[0]);
+
+            match algorithm {
+                Algorithm::Test1 | Algorithm::Test3 | Algorithm::Test5 => {
+                    assert_ne!(default_fingerprint.items(), fingerprint.items())
+                }
+                Algorithm::Test2 => assert_eq!(default_fingerprint.items(), fingerprint.items()),
+                // The recording starts quietly enough for a few samples to be skipped.
+                Algorithm::Test4 => {
+                    assert_eq!(730, fingerprinter.removed_samples());
+                    assert_eq!(
+                        &[
+                            4008827735, 4007696709, 4016089365, 4011895093, 4013025589, 3996252469,
+                            4000841527, 1861743926, 1857540150, 1861730358,
+                        ],
+                        fingerprint.items()
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_silence() -> Result<(), Box<dyn Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let mut padded = vec![0i16; 11025 * 3];
+        padded.extend_from_slice(&samples);
+
+        let mut expected = Fingerprinter::with_algorithm(11025, 1, Algorithm::Test4)?;
+        expected.feed(&samples)?;
+        expected.finish()?;
+
+        // The resampler smears the onset by a sample, so the cut may not land on exactly the
+        // same sample of the chords.
+        let assert_trimmed = |fingerprinter: &Fingerprinter| {
+            let silence = fingerprinter.removed_samples() - expected.removed_samples();
+            assert!((11025 * 3 - 1..=11025 * 3 + 1).contains(&silence));
+            assert!(fingerprinter.removed_duration() > 2.99);
+
+            let expected = expected.fingerprint();
+            let fingerprint = fingerprinter.fingerprint();
+            assert_eq!(expected.items().len(), fingerprint.items().len());
+            assert!(compare::bit_error_rate(&expected, &fingerprint, 0).unwrap() < 0.02);
+        };
+
+        let mut fingerprinter = Fingerprinter::with_algorithm(11025, 1, Algorithm::Test4)?;
+        fingerprinter.feed(&padded)?;
+        fingerprinter.finish()?;
+        assert_trimmed(&fingerprinter);
+
+        // The default algorithm keeps the silence unless told otherwise.
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        fingerprinter.feed(&padded)?;
+        fingerprinter.finish()?;
+        assert_eq!(0, fingerprinter.removed_samples());
+        assert_ne!(
+            expected.fingerprint().items().len(),
+            fingerprinter.fingerprint().items().len()
+        );
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        fingerprinter.set_silence_threshold(Some(50));
+        fingerprinter.feed(&padded)?;
+        fingerprinter.finish()?;
+        assert_trimmed(&fingerprinter);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_max_duration() -> Result<(), Box<dyn Error>> {
+        let samples = tests::generate_chords(11025, 10);
+
+        let mut expected = Fingerprinter::new(11025, 1)?;
+        expected.feed(&samples[..(11025 * 6)])?;
+        expected.finish()?;
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        fingerprinter.set_max_duration(Some(6.0))?;
+        let mut chunks = samples.chunks(1000);
+        let mut fed_chunks = 0;
+        for chunk in chunks.by_ref() {
+            fed_chunks += 1;
+            if fingerprinter.feed(chunk)? == FeedStatus::Done {
+                break;
+            }
+        }
+        assert_eq!(67, fed_chunks);
+        assert_eq!(FeedStatus::Done, fingerprinter.status());
+        assert_eq!(
+            FeedStatus::Done,
+            fingerprinter.feed(chunks.next().unwrap())?
+        );
+        fingerprinter.finish()?;
+        assert_eq!(
+            expected.fingerprint().items(),
+            fingerprinter.fingerprint().items()
+        );
+
+        // The limit counts frames, not interleaved samples.
+        let stereo: Vec<i16> = samples.iter().flat_map(|&s| vec![s, s]).collect();
+        let mut fingerprinter = Fingerprinter::new(11025, 2)?;
+        fingerprinter.set_max_duration(Some(6.0))?;
+        assert_eq!(FeedStatus::Done, fingerprinter.feed(&stereo)?);
+        fingerprinter.finish()?;
+        assert_eq!(
+            expected.fingerprint().items(),
+            fingerprinter.fingerprint().items()
+        );
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        assert_eq!(FeedStatus::NeedsMore, fingerprinter.feed(&samples)?);
+
+        // Durations too long to count in samples don't limit anything.
+        for &max_duration in &[f64::INFINITY, 1e300] {
+            let mut fingerprinter = Fingerprinter::new(11025, 2)?;
+            fingerprinter.set_max_duration(Some(max_duration))?;
+            assert_eq!(FeedStatus::NeedsMore, fingerprinter.feed(&stereo)?);
+        }
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        fingerprinter.set_max_duration(Some(6.0))?;
+        for &max_duration in &[-1.0, f64::NAN, f64::NEG_INFINITY] {
+            assert!(matches!(
+                fingerprinter.set_max_duration(Some(max_duration)),
+                Err(error::Error::InvalidDuration(_))
+            ));
+        }
+        assert_eq!(FeedStatus::Done, fingerprinter.feed(&samples)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_chunks() -> Result<(), Box<dyn Error>> {
+        let samples = tests::generate_chords(11025, 25);
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        fingerprinter.set_chunk_duration(Some(10.0), false)?;
+        for chunk in samples.chunks(4097) {
+            fingerprinter.feed(chunk)?;
+        }
+        let mut chunks = fingerprinter.take_chunks();
+        assert_eq!(2, chunks.len());
+        fingerprinter.finish()?;
+        chunks.extend(fingerprinter.take_chunks());
+
+        assert_eq!(3, chunks.len());
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let start = idx * 11025 * 10;
+            let end = (start + 11025 * 10).min(samples.len());
+
+            let mut expected = Fingerprinter::new(11025, 1)?;
+            expected.feed(&samples[start..end])?;
+            expected.finish()?;
+
+            assert_ulps_eq!(idx as f64 * 10.0, chunk.start);
+            assert_ulps_eq!((end - start) as f64 / 11025.0, chunk.duration);
+            assert_eq!(
+                expected.fingerprint().items(),
+                chunk.as_fingerprint().items()
+            );
+        }
+
+        // A chunk too long to count in samples covers the whole input.
+        let mut expected = Fingerprinter::new(11025, 1)?;
+        expected.feed(&samples)?;
+        expected.finish()?;
+        for &overlap in &[false, true] {
+            let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+            fingerprinter.set_chunk_duration(Some(f64::INFINITY), overlap)?;
+            fingerprinter.feed(&samples)?;
+            fingerprinter.finish()?;
+            let chunks = fingerprinter.take_chunks();
+            assert_eq!(1, chunks.len());
+            assert_eq!(
+                expected.fingerprint().items(),
+                chunks[0].as_fingerprint().items()
+            );
+        }
+
+        for &chunk_duration in &[0.0, -1.0, f64::NAN] {
+            assert!(matches!(
+                fingerprinter.set_chunk_duration(Some(chunk_duration), false),
+                Err(error::Error::InvalidDuration(_))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_overlapping_chunks() -> Result<(), Box<dyn Error>> {
+        let samples = tests::generate_chords(11025, 25);
+        let delay = Algorithm::default().delay_duration();
+
+        let mut expected = Fingerprinter::new(11025, 1)?;
+        expected.feed(&samples)?;
+        expected.finish()?;
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        fingerprinter.set_chunk_duration(Some(10.0), true)?;
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
+        let chunks = fingerprinter.take_chunks();
+
+        assert_eq!(3, chunks.len());
+        assert_ulps_eq!(0.0, chunks[0].start);
+        assert_ulps_eq!(10.0, chunks[1].start, epsilon = 1e-9);
+        assert_ulps_eq!(20.0, chunks[2].start, epsilon = 1e-9);
+        assert_relative_eq!(10.0 + delay, chunks[0].duration, epsilon = 1e-3);
+        assert_relative_eq!(10.0 + delay, chunks[1].duration, epsilon = 1e-3);
+        assert_relative_eq!(
+            samples.len() as f64 / 11025.0 - 20.0,
+            chunks[2].duration,
+            epsilon = 1e-3
+        );
+
+        // The pipeline carries on between chunks, so together they make up the whole fingerprint
+        // apart from where the resampler was flushed.
+        let joined: Vec<u32> = chunks
+            .iter()
+            .flat_map(|chunk| chunk.fingerprint.iter().cloned())
+            .collect();
+        let expected = expected.fingerprint();
+        let joined = Fingerprint::new(&joined, Algorithm::default());
+        assert!((expected.items().len() as i64 - joined.items().len() as i64).abs() <= 1);
+        assert!(compare::bit_error_rate(&expected, &joined, 0).unwrap() < 0.02);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_drain() -> Result<(), Box<dyn Error>> {
+        let samples = tests::generate_chords(11025, 20);
+
+        let mut expected = Fingerprinter::new(11025, 1)?;
+        expected.feed(&samples)?;
+        expected.finish()?;
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        let mut items = Vec::new();
+        for chunk in samples.chunks(5000) {
+            fingerprinter.feed(chunk)?;
+            items.extend(fingerprinter.drain());
+            assert!(fingerprinter.fingerprint().items().is_empty());
+        }
+        fingerprinter.finish()?;
+        items.extend(fingerprinter.drain());
+
+        let values: Vec<u32> = items.iter().map(|item| item.value).collect();
+        assert_eq!(expected.fingerprint().items(), &values[..]);
+        for (index, item) in items.iter().enumerate() {
+            assert_eq!(index, item.index);
+            assert_ulps_eq!(index as f64 * 1365.0 / 11025.0, item.timestamp);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_reset() -> Result<(), Box<dyn Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let stereo: Vec<i16> = samples.iter().flat_map(|&s| vec![s, s]).collect();
+
+        let fingerprint = |sample_rate, channels, samples: &[i16]| -> Result<_, Box<dyn Error>> {
+            let mut fingerprinter = Fingerprinter::new(sample_rate, channels)?;
+            fingerprinter.feed(samples)?;
+            fingerprinter.finish()?;
+            Ok(fingerprinter.fingerprint().items().to_vec())
+        };
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        fingerprinter.feed(&samples[..50_000])?;
+        fingerprinter.reset();
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
+        assert_eq!(
+            fingerprint(11025, 1, &samples)?,
+            fingerprinter.fingerprint().items()
+        );
+
+        // Ends with an incomplete stereo frame.
+        fingerprinter.reset();
+        fingerprinter.feed(&stereo[..50_001])?;
+        fingerprinter.start(22050, 2)?;
+        fingerprinter.feed(&stereo)?;
+        fingerprinter.finish()?;
+        assert_eq!(
+            fingerprint(22050, 2, &stereo)?,
+            fingerprinter.fingerprint().items()
+        );
+
+        // Going back to a cached resampler.
+        fingerprinter.start(11025, 1)?;
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
+        assert_eq!(
+            fingerprint(11025, 1, &samples)?,
+            fingerprinter.fingerprint().items()
+        );
+
+        assert_eq!(
+            Some(error::Error::InvalidSampleRate(0)),
+            fingerprinter.start(0, 1).err()
+        );
+
+        // Limits follow the new input format.
+        fingerprinter.set_max_duration(Some(2.0))?;
+        fingerprinter.start(22050, 2)?;
+        assert_eq!(
+            FeedStatus::NeedsMore,
+            fingerprinter.feed(&stereo[..(22050 * 4 - 2)])?
+        );
+        assert_eq!(FeedStatus::Done, fingerprinter.feed(&stereo[..2])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_stereo() -> Result<(), Box<dyn Error>> {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw");
+        // The file only holds two seconds of stereo audio, which is too short to fingerprint.
+        let samples = tests::load_audio_file(&path)?.repeat(3);
+        let mono_samples = tests::load_stero_audio_file(&path)?.repeat(3);
+
+        let mut mono_fingerprinter = Fingerprinter::new(44100, 1)?;
+        mono_fingerprinter.feed(&mono_samples)?;
+        mono_fingerprinter.finish()?;
+
+        let mut stereo_fingerprinter = Fingerprinter::new(44100, 2)?;
+        stereo_fingerprinter.feed(&samples)?;
+        stereo_fingerprinter.finish()?;
+
+        // Odd sized chunks split frames across calls to feed.
+        let mut chunked_fingerprinter = Fingerprinter::new(44100, 2)?;
+        for chunk in samples.chunks(4097) {
+            chunked_fingerprinter.feed(chunk)?;
+        }
+        chunked_fingerprinter.finish()?;
+
+        assert!(!mono_fingerprinter.fingerprint().items().is_empty());
+        assert_eq!(
+            mono_fingerprinter.fingerprint().items(),
+            stereo_fingerprinter.fingerprint().items()
+        );
+        assert_eq!(
+            mono_fingerprinter.fingerprint().items(),
+            chunked_fingerprinter.fingerprint().items()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_high_sample_rates() -> Result<(), Box<dyn Error>> {
+        let fingerprint_chords = |sample_rate| -> Result<Vec<u32>, Box<dyn Error>> {
+            let mut fingerprinter = Fingerprinter::new(sample_rate, 1)?;
+            fingerprinter.feed(&tests::generate_chords(sample_rate, 10))?;
+            fingerprinter.finish()?;
+
+            Ok(fingerprinter.fingerprint().items().to_vec())
+        };
+
+        let expected = fingerprint_chords(44100)?;
+
+        for &sample_rate in &[96000, 192000] {
+            let fingerprint = fingerprint_chords(sample_rate)?;
+            assert_eq!(expected.len(), fingerprint.len());
+
+            let bit_errors: u32 = expected
+                .iter()
+                .zip(fingerprint.iter())
+                .map(|(a, b)| (a ^ b).count_ones())
+                .sum();
+            let bit_error_rate = bit_errors as f64 / (expected.len() * 32) as f64;
+            assert!(
+                bit_error_rate < 0.05,
+                "{} Hz: {}",
+                sample_rate,
+                bit_error_rate
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_sample_rates() {
+        for &sample_rate in &[0, 1000, 768_001, u32::MAX] {
+            assert_eq!(
+                Some(error::Error::InvalidSampleRate(sample_rate)),
+                Fingerprinter::new(sample_rate, 1).err()
+            );
+        }
+
+        assert!(Fingerprinter::new(1001, 1).is_ok());
+        assert!(Fingerprinter::new(768_000, 1).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_channel_count() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            Some(error::Error::InvalidChannelCount(0)),
+            Fingerprinter::new(11025, 0).err()
+        );
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        assert_eq!(
+            Some(error::Error::InvalidChannelCount(0)),
+            fingerprinter.start(22050, 0).err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_finished() -> Result<(), Box<dyn Error>> {
+        let samples = tests::generate_chords(11025, 5);
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
+        let expected = fingerprinter.fingerprint().items().to_vec();
+
+        assert_eq!(
+            Some(error::Error::AlreadyFinished),
+            fingerprinter.feed(&samples).err()
+        );
+        assert_eq!(
+            Some(error::Error::AlreadyFinished),
+            fingerprinter.finish().err()
+        );
+        assert_eq!(expected, fingerprinter.fingerprint().items());
+
+        fingerprinter.reset();
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
+        assert_eq!(expected, fingerprinter.fingerprint().items());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprinter_sample_formats() -> Result<(), Box<dyn Error>> {
+        let samples = tests::load_audio_file(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw"),
+        )?;
+
+        let mut expected = Fingerprinter::new(44100, 1)?;
+        expected.feed(&samples)?;
+        expected.finish()?;
+
+        let mut float = Fingerprinter::new(44100, 1)?;
+        let float_samples: Vec<f32> = samples.iter().map(|&s| s as f32 / 32768.0).collect();
+        float.feed(&float_samples)?;
+        float.finish()?;
+
+        let mut double = Fingerprinter::new(44100, 1)?;
+        let double_samples: Vec<f64> = samples.iter().map(|&s| s as f64 / 32768.0).collect();
+        double.feed(&double_samples)?;
+        double.finish()?;
+
+        let mut int = Fingerprinter::new(44100, 1)?;
+        let int_samples: Vec<i32> = samples.iter().map(|&s| (s as i32) << 16).collect();
+        int.feed(&int_samples)?;
+        int.finish()?;
+
+        let mut packed = Fingerprinter::new(44100, 1)?;
+        let packed_samples: Vec<I24> = samples
+            .iter()
+            .map(|&s| I24::from_i32((s as i32) << 8))
+            .collect();
+        packed.feed(&packed_samples)?;
+        packed.finish()?;
+
+        assert_eq!(expected.fingerprint().items(), float.fingerprint().items());
+        assert_eq!(expected.fingerprint().items(), double.fingerprint().items());
+        assert_eq!(expected.fingerprint().items(), int.fingerprint().items());
+        assert_eq!(expected.fingerprint().items(), packed.fingerprint().items());
+
+        // Switching to floating point part way through keeps the buffered 16-bit samples.
+        let mut mixed = Fingerprinter::new(44100, 1)?;
+        let half = samples.len() / 2 + 1;
+        mixed.feed(&samples[..half])?;
+        mixed.feed(&float_samples[half..])?;
+        mixed.finish()?;
+        assert_eq!(expected.fingerprint().items(), mixed.fingerprint().items());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,423 @@
+//! Lookup of fingerprints in a local catalogue.
+//!
+//! Like acoustid-index, the top 20 bits of every item are used as terms of an inverted index.
+//! Fingerprints sharing many terms with the query are candidates, which are then aligned with the
+//! query and scored by their bit error rate. Fingerprints are only compared with queries computed
+//! with the same algorithm and the same custom settings, if any. Items made of several words are
+//! indexed word by word, so offsets are counted in words.
+//!
+//! `Index` lives in memory, while `DiskIndex` stores the same structure in files which can be
+//! reopened and updated incrementally.
+
+use algorithm::Algorithm;
+use compare::raw_bit_error_rate;
+use fingerprinter::{CustomItems, Fingerprint};
+use std::collections::HashMap;
+
+pub use disk_index::DiskIndex;
+
+/// Number of top bits of an item used as a term of the index.
+const HASH_BITS: u32 = 20;
+
+/// Results with a higher bit error rate than this are discarded.
+pub(crate) const DEFAULT_MAX_BIT_ERROR_RATE: f64 = 0.25;
+
+/// Number of candidates verified for every requested result.
+pub(crate) const CANDIDATES_PER_RESULT: usize = 4;
+
+/// Number of the most voted offsets at which a candidate is scored.
+const CANDIDATE_OFFSETS: usize = 3;
+
+/// A fingerprint found by `Index::search`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchResult {
+    /// The id the fingerprint was inserted with.
+    pub id: u32,
+
+    /// Number of distinct terms of the query found in the fingerprint.
+    pub hits: usize,
+
+    /// Item of the indexed fingerprint aligned with the first item of the query. Negative if the
+    /// query starts before the indexed fingerprint.
+    pub offset: i32,
+
+    /// Ratio of differing bits between the query and the indexed fingerprint at `offset`.
+    pub bit_error_rate: f64,
+}
+
+/// An in-memory inverted index of fingerprints.
+pub struct Index {
+    max_bit_error_rate: f64,
+
+    /// Ids of the fingerprints containing each term.
+    postings: HashMap<u32, Vec<u32>>,
+
+    fingerprints: HashMap<u32, Vec<u32>>,
+    algorithms: HashMap<u32, Algorithm>,
+
+    /// Settings of the fingerprints computed by a fingerprinter from a `FingerprinterBuilder`.
+    customs: HashMap<u32, CustomItems>,
+}
+
+impl Default for Index {
+    fn default() -> Index {
+        Index::new()
+    }
+}
+
+impl Index {
+    pub fn new() -> Index {
+        Index::with_max_bit_error_rate(DEFAULT_MAX_BIT_ERROR_RATE)
+    }
+
+    /// Sets the highest bit error rate, between 0 and 1, a search result can have.
+    pub fn with_max_bit_error_rate(max_bit_error_rate: f64) -> Index {
+        Index {
+            max_bit_error_rate,
+            postings: HashMap::new(),
+            fingerprints: HashMap::new(),
+            algorithms: HashMap::new(),
+            customs: HashMap::new(),
+        }
+    }
+
+    /// Number of fingerprints in the index.
+    pub fn len(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty()
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.fingerprints.contains_key(&id)
+    }
+
+    /// Adds a fingerprint to the index, replacing any fingerprint previously added with `id`.
+    pub fn insert(&mut self, id: u32, fingerprint: &Fingerprint) {
+        self.remove(id);
+
+        for term in terms(fingerprint.items()) {
+            self.postings.entry(term).or_default().push(id);
+        }
+        self.fingerprints.insert(id, fingerprint.items().to_vec());
+        self.algorithms.insert(id, fingerprint.algorithm());
+        if let Some(custom) = fingerprint.custom() {
+            self.customs.insert(id, custom);
+        }
+    }
+
+    /// Removes the fingerprint added with `id`.
+    ///
+    /// # Returns
+    /// Whether the index contained the fingerprint.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let fingerprint = match self.fingerprints.remove(&id) {
+            Some(fingerprint) => fingerprint,
+            None => return false,
+        };
+        self.algorithms.remove(&id);
+        self.customs.remove(&id);
+
+        for term in terms(&fingerprint) {
+            if let Some(ids) = self.postings.get_mut(&term) {
+                ids.retain(|&other| other != id);
+                if ids.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Finds up to `limit` fingerprints matching `query`, best match first.
+    ///
+    /// The fingerprints sharing the most terms with the query are aligned with it and the ones
+    /// with a low enough bit error rate are returned, ordered by bit error rate. Fingerprints
+    /// computed with another algorithm or other custom settings than the query are skipped.
+    pub fn search(&self, query: &Fingerprint, limit: usize) -> Vec<SearchResult> {
+        let mut hits = HashMap::new();
+        self.count_hits(&terms(query.items()), &mut hits);
+        hits.retain(|&id, _| {
+            self.algorithms[&id] == query.algorithm() && self.custom(id) == query.custom()
+        });
+
+        let results = top_candidates(hits, limit.saturating_mul(CANDIDATES_PER_RESULT))
+            .into_iter()
+            .filter_map(|(id, hits)| verify(id, hits, &self.fingerprints[&id], query.items()))
+            .collect();
+        rank(results, self.max_bit_error_rate, limit)
+    }
+
+    /// Adds the number of `terms` each fingerprint contains to `hits`.
+    pub(crate) fn count_hits(&self, terms: &[u32], hits: &mut HashMap<u32, usize>) {
+        for term in terms {
+            for &id in self.postings.get(term).into_iter().flatten() {
+                *hits.entry(id).or_default() += 1;
+            }
+        }
+    }
+
+    pub(crate) fn get(&self, id: u32) -> Option<&[u32]> {
+        self.fingerprints
+            .get(&id)
+            .map(|fingerprint| &fingerprint[..])
+    }
+
+    pub(crate) fn algorithm(&self, id: u32) -> Option<Algorithm> {
+        self.algorithms.get(&id).cloned()
+    }
+
+    pub(crate) fn custom(&self, id: u32) -> Option<CustomItems> {
+        self.customs.get(&id).cloned()
+    }
+
+    pub(crate) fn iter(
+        &self,
+    ) -> impl Iterator<Item = (u32, Algorithm, Option<CustomItems>, &[u32])> {
+        self.fingerprints.iter().map(move |(&id, fingerprint)| {
+            (id, self.algorithms[&id], self.custom(id), &fingerprint[..])
+        })
+    }
+
+    /// Returns the ids of the fingerprints containing each term, in no particular order.
+    pub(crate) fn postings(&self) -> impl Iterator<Item = (u32, &[u32])> {
+        self.postings.iter().map(|(&term, ids)| (term, &ids[..]))
+    }
+}
+
+/// Returns the distinct terms of a fingerprint in ascending order.
+pub(crate) fn terms(fingerprint: &[u32]) -> Vec<u32> {
+    let mut terms: Vec<u32> = fingerprint.iter().map(|&item| term(item)).collect();
+    terms.sort_unstable();
+    terms.dedup();
+    terms
+}
+
+fn term(item: u32) -> u32 {
+    item >> (32 - HASH_BITS)
+}
+
+/// Returns the `count` candidates with the most hits, ties broken by id.
+pub(crate) fn top_candidates(hits: HashMap<u32, usize>, count: usize) -> Vec<(u32, usize)> {
+    let mut candidates: Vec<(u32, usize)> = hits.into_iter().collect();
+    candidates.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    candidates.truncate(count);
+    candidates
+}
+
+/// Aligns a candidate with the query and scores it.
+pub(crate) fn verify(
+    id: u32,
+    hits: usize,
+    fingerprint: &[u32],
+    query: &[u32],
+) -> Option<SearchResult> {
+    let (offset, bit_error_rate) = align(fingerprint, query)?;
+    Some(SearchResult {
+        id,
+        hits,
+        offset,
+        bit_error_rate,
+    })
+}
+
+/// Drops the results above `max_bit_error_rate` and returns the best `limit` ones.
+pub(crate) fn rank(
+    mut results: Vec<SearchResult>,
+    max_bit_error_rate: f64,
+    limit: usize,
+) -> Vec<SearchResult> {
+    results.retain(|result| result.bit_error_rate <= max_bit_error_rate);
+    results.sort_by(|a, b| {
+        a.bit_error_rate
+            .total_cmp(&b.bit_error_rate)
+            .then(b.hits.cmp(&a.hits))
+    });
+    results.truncate(limit);
+    results
+}
+
+/// Aligns `query` with `fingerprint` by voting with the offsets of items having the same term.
+///
+/// # Returns
+/// The offset of the query in the fingerprint with the lowest bit error rate among the most voted
+/// offsets, or `None` if no items share a term.
+pub(crate) fn align(fingerprint: &[u32], query: &[u32]) -> Option<(i32, f64)> {
+    let mut positions: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (pos, &item) in fingerprint.iter().enumerate() {
+        positions.entry(term(item)).or_default().push(pos);
+    }
+
+    let mut votes: HashMap<i32, usize> = HashMap::new();
+    for (query_pos, &item) in query.iter().enumerate() {
+        for &pos in positions.get(&term(item)).into_iter().flatten() {
+            *votes.entry(pos as i32 - query_pos as i32).or_default() += 1;
+        }
+    }
+
+    let mut offsets: Vec<(i32, usize)> = votes.into_iter().collect();
+    offsets.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    offsets
+        .iter()
+        .take(CANDIDATE_OFFSETS)
+        .filter_map(|&(offset, _)| {
+            raw_bit_error_rate(fingerprint, query, offset).map(|rate| (offset, rate))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{align, terms, Index};
+    use algorithm::Algorithm;
+    use fingerprinter::{CustomItems, Fingerprint};
+    use std::error::Error;
+    use test_audio::{fingerprint_samples, load_samples, ITEM_SAMPLES};
+    use tests;
+
+    #[test]
+    fn test_terms() {
+        assert_eq!(
+            vec![0, 1, 0xfffff],
+            terms(&[
+                0x0000_1fff,
+                0xffff_ffff,
+                0x0000_0000,
+                0x0000_1000,
+                0xffff_f000
+            ])
+        );
+    }
+
+    #[test]
+    fn test_align() {
+        let fingerprint: Vec<u32> = (0..20u32).map(|i| i.wrapping_mul(0x9e37_79b9)).collect();
+
+        assert_eq!(Some((5, 0.0)), align(&fingerprint, &fingerprint[5..12]));
+        assert_eq!(Some((-3, 0.0)), align(&fingerprint[3..], &fingerprint));
+        assert_eq!(None, align(&fingerprint, &[0x1234_5678]));
+    }
+
+    #[test]
+    fn test_search() -> Result<(), Box<dyn Error>> {
+        let samples = load_samples()?;
+        let mut reversed = samples.clone();
+        reversed.reverse();
+
+        let raw_audio = fingerprint_samples(&samples, 11025, 1)?;
+        let raw_reversed = fingerprint_samples(&reversed, 11025, 1)?;
+        let raw_chords = fingerprint_samples(&tests::generate_chords(11025, 16), 11025, 1)?;
+        let raw_query =
+            fingerprint_samples(&samples[(ITEM_SAMPLES * 20)..(ITEM_SAMPLES * 80)], 11025, 1)?;
+
+        let mut index = Index::new();
+        index.insert(1, &Fingerprint::new(&raw_audio, Algorithm::default()));
+        index.insert(2, &Fingerprint::new(&raw_reversed, Algorithm::default()));
+        index.insert(3, &Fingerprint::new(&raw_chords, Algorithm::default()));
+        assert_eq!(3, index.len());
+
+        let query = Fingerprint::new(&raw_query, Algorithm::default());
+        let results = index.search(&query, 10);
+        assert_eq!(1, results.len());
+        assert_eq!(1, results[0].id);
+        assert_eq!(20, results[0].offset);
+        assert!(results[0].bit_error_rate < 0.05);
+        assert!(results[0].hits > raw_query.len() / 2);
+
+        let results = index.search(&Fingerprint::new(&raw_chords, Algorithm::default()), 10);
+        assert_eq!(3, results[0].id);
+        assert_eq!(0, results[0].offset);
+        assert_eq!(0.0, results[0].bit_error_rate);
+
+        assert!(index.search(&query, 0).is_empty());
+        assert_eq!(1, index.search(&query, usize::MAX).len());
+        assert!(Index::new().search(&query, 10).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let a: Vec<u32> = (0..50u32).map(|i| i.wrapping_mul(0x9e37_79b9)).collect();
+        let b: Vec<u32> = (0..50u32).map(|i| i.wrapping_mul(0x85eb_ca6b)).collect();
+        let query = Fingerprint::new(&a[10..30], Algorithm::default());
+
+        let mut index = Index::default();
+        index.insert(7, &Fingerprint::new(&a, Algorithm::default()));
+        assert!(index.contains(7));
+        assert_eq!(7, index.search(&query, 1)[0].id);
+
+        index.insert(7, &Fingerprint::new(&b, Algorithm::default()));
+        assert_eq!(1, index.len());
+        assert!(index.search(&query, 1).is_empty());
+
+        assert!(index.remove(7));
+        assert!(!index.remove(7));
+        assert!(index.is_empty());
+        assert!(index.postings.is_empty());
+        assert!(index.algorithms.is_empty());
+        assert!(index.customs.is_empty());
+    }
+
+    #[test]
+    fn test_mixed_algorithms() -> Result<(), Box<dyn Error>> {
+        let samples = load_samples()?;
+        let raw_audio = fingerprint_samples(&samples, 11025, 1)?;
+        let raw_query =
+            fingerprint_samples(&samples[(ITEM_SAMPLES * 20)..(ITEM_SAMPLES * 80)], 11025, 1)?;
+
+        let mut index = Index::new();
+        index.insert(1, &Fingerprint::new(&raw_audio, Algorithm::Test1));
+        index.insert(2, &Fingerprint::new(&raw_audio, Algorithm::Test2));
+
+        let results = index.search(&Fingerprint::new(&raw_query, Algorithm::Test2), 10);
+        assert_eq!(vec![2], results.iter().map(|r| r.id).collect::<Vec<_>>());
+
+        let results = index.search(&Fingerprint::new(&raw_query, Algorithm::Test1), 10);
+        assert_eq!(vec![1], results.iter().map(|r| r.id).collect::<Vec<_>>());
+
+        assert!(index
+            .search(&Fingerprint::new(&raw_query, Algorithm::Test3), 10)
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_items() {
+        let items: Vec<u32> = (0..50u32).map(|i| i.wrapping_mul(0x9e37_79b9)).collect();
+        let custom = CustomItems {
+            hop_size: 2048,
+            sample_rate: 11025,
+            words: 1,
+        };
+        let other = CustomItems {
+            hop_size: 1024,
+            ..custom
+        };
+
+        let mut index = Index::new();
+        index.insert(1, &Fingerprint::new(&items, Algorithm::Test2));
+        index.insert(
+            2,
+            &Fingerprint::with_custom(&items, Algorithm::Test2, custom),
+        );
+
+        let search = |query: &Fingerprint| -> Vec<u32> {
+            index.search(query, 10).iter().map(|r| r.id).collect()
+        };
+        assert_eq!(vec![1], search(&Fingerprint::new(&items, Algorithm::Test2)));
+        assert_eq!(
+            vec![2],
+            search(&Fingerprint::with_custom(&items, Algorithm::Test2, custom))
+        );
+        assert!(search(&Fingerprint::with_custom(&items, Algorithm::Test2, other)).is_empty());
+
+        assert!(index.remove(2));
+        assert!(index.customs.is_empty());
+    }
+}
@@ -1,35 +1,88 @@
 #![allow(clippy::needless_range_loop)]
 
 extern crate base64;
+#[cfg(feature = "render")]
+extern crate png;
 extern crate rustfft;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "symphonia")]
+extern crate symphonia;
 
+mod algorithm;
 mod audio_processor;
+mod bit_reader;
 mod bit_writer;
+mod builder;
 mod chroma;
+mod chroma_extractor;
 mod chroma_filter;
 mod chroma_normalize;
 mod classifiers;
 mod combined_buffer;
+mod decoder;
+mod disk_index;
+mod downmixer;
 mod encode;
+mod error;
 mod fft;
 mod filter;
 mod fingerprint_calculator;
 mod fingerprint_compressor;
+mod fingerprint_decompressor;
+mod fingerprint_matcher;
 mod quantizer;
 mod resampler;
 mod rolling_integral_image;
+mod sample;
+mod silence_remover;
+mod simhash;
 mod slicer;
+#[cfg(feature = "symphonia")]
+mod symphonia_decoder;
 
 #[cfg(test)]
 #[macro_use]
 extern crate approx;
 
+#[cfg(test)]
+extern crate tempfile;
+
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
 #[cfg(test)]
 mod tests;
 
+#[cfg(test)]
+mod test_audio;
+
 #[cfg(test)]
 mod test_data;
 
 mod fingerprinter;
 
-pub use fingerprinter::{Fingerprint, Fingerprinter};
+pub mod compare;
+pub mod index;
+#[cfg(feature = "render")]
+pub mod render;
+pub mod wav;
+
+pub use algorithm::Algorithm;
+pub use builder::{FingerprinterBuilder, MAX_FRAME_SIZE};
+pub use chroma_extractor::{ChromaExtractor, ChromaFrame, ChromaStage};
+pub use classifiers::{Classifier, Classifiers};
+pub use decoder::{
+    feed_decoder, fingerprint_file, fingerprint_reader, Decoder, DecoderError, Samples,
+};
+pub use encode::DecodeError;
+pub use error::Error;
+pub use fingerprint_decompressor::DecompressError;
+pub use fingerprint_matcher::{FingerprintMatcher, Segment};
+pub use fingerprinter::{
+    Chunk, CompressedFingerprint, CustomItems, FeedStatus, Fingerprint, Fingerprinter,
+    OwnedFingerprint, Subfingerprint,
+};
+pub use sample::{Sample, I24};
+#[cfg(feature = "symphonia")]
+pub use symphonia_decoder::SymphoniaDecoder;
@@ -0,0 +1,432 @@
+//! Rendering of fingerprints and chromagrams to images, to inspect them when debugging matching.
+//!
+//! Fingerprints are drawn one item per row, from the most significant bit on the left to the
+//! least significant one on the right, with set bits in white. Chromagrams are drawn one frame per
+//! row, from the pitch class A on the left, as a heatmap going from black through red and yellow
+//! to white. Images can be written as PNG, binary PPM or binary PGM.
+
+use chroma_extractor::ChromaFrame;
+use compare::overlap;
+use fingerprinter::Fingerprint;
+use png;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const BACKGROUND: [u8; 3] = [0x40, 0x40, 0x40];
+const SET_BIT: [u8; 3] = [0xff, 0xff, 0xff];
+const CLEAR_BIT: [u8; 3] = [0, 0, 0];
+const DIFFERENT_BIT: [u8; 3] = [0xff, 0x30, 0x30];
+
+/// Width of the gap between the panels of a diff image.
+const GAP: usize = 2;
+
+/// Encodings images can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+
+    /// Binary Netpbm colour image (`P6`).
+    Ppm,
+
+    /// Binary Netpbm greyscale image (`P5`). Colours are converted to their luma.
+    Pgm,
+}
+
+impl ImageFormat {
+    /// Picks the format matching a file extension, ignoring case.
+    pub fn from_extension(extension: &str) -> Option<ImageFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" | "pnm" => Some(ImageFormat::Ppm),
+            "pgm" => Some(ImageFormat::Pgm),
+            _ => None,
+        }
+    }
+}
+
+/// Reasons an image could not be written.
+#[derive(Debug)]
+pub enum RenderError {
+    Io(io::Error),
+
+    /// The PNG encoder failed.
+    Png(png::EncodingError),
+
+    /// The file extension doesn't match any `ImageFormat`.
+    UnknownFormat,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderError::Io(ref err) => write!(f, "unable to write image: {}", err),
+            RenderError::Png(ref err) => write!(f, "unable to encode PNG image: {}", err),
+            RenderError::UnknownFormat => {
+                write!(f, "unknown image format, expected .png, .ppm or .pgm")
+            }
+        }
+    }
+}
+
+impl error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            RenderError::Io(ref err) => Some(err),
+            RenderError::Png(ref err) => Some(err),
+            RenderError::UnknownFormat => None,
+        }
+    }
+}
+
+impl From<io::Error> for RenderError {
+    fn from(err: io::Error) -> RenderError {
+        RenderError::Io(err)
+    }
+}
+
+impl From<png::EncodingError> for RenderError {
+    fn from(err: png::EncodingError) -> RenderError {
+        match err {
+            png::EncodingError::IoError(err) => RenderError::Io(err),
+            err => RenderError::Png(err),
+        }
+    }
+}
+
+/// An RGB image with 8 bits per channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+
+    /// Rows from top to bottom, of pixels from left to right.
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![BACKGROUND; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The colour of the pixel in column `x` of row `y`.
+    ///
+    /// # Panics
+    /// If the pixel is outside of the image.
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel outside of the image"
+        );
+        self.pixels[y * self.width + x]
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Enlarges the image, turning each pixel into a square of `factor` by `factor` pixels. The
+    /// images are otherwise only a few pixels wide.
+    pub fn scale(&self, factor: usize) -> Image {
+        let mut scaled = Image::new(self.width * factor, self.height * factor);
+        for y in 0..scaled.height {
+            for x in 0..scaled.width {
+                scaled.set_pixel(x, y, self.pixel(x / factor, y / factor));
+            }
+        }
+        scaled
+    }
+
+    /// Encodes the image into `writer`.
+    pub fn write<W: Write>(&self, mut writer: W, format: ImageFormat) -> Result<(), RenderError> {
+        match format {
+            ImageFormat::Png => {
+                let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                let mut writer = encoder.write_header()?;
+                writer.write_image_data(&self.rgb())?;
+                writer.finish()?;
+            }
+            ImageFormat::Ppm => {
+                write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+                writer.write_all(&self.rgb())?;
+            }
+            ImageFormat::Pgm => {
+                write!(writer, "P5\n{} {}\n255\n", self.width, self.height)?;
+                writer.write_all(&self.luma())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the image to a file, in the format given by the extension of `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), RenderError> {
+        let path = path.as_ref();
+        let format = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(ImageFormat::from_extension)
+            .ok_or(RenderError::UnknownFormat)?;
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, format)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn rgb(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_vec())
+            .collect()
+    }
+
+    fn luma(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .map(|&[r, g, b]| {
+                ((299 * r as u32 + 587 * g as u32 + 114 * b as u32 + 500) / 1000) as u8
+            })
+            .collect()
+    }
+
+    /// Draws `items` in a panel of 32 columns starting at column `x`. Missing items are left as
+    /// background.
+    fn draw_items<I: IntoIterator<Item = Option<u32>>>(
+        &mut self,
+        x: usize,
+        items: I,
+        color: [u8; 3],
+    ) {
+        for (y, item) in items.into_iter().enumerate() {
+            if let Some(item) = item {
+                for bit in 0..32 {
+                    let set = item & (1 << (31 - bit)) != 0;
+                    self.set_pixel(x + bit, y, if set { color } else { CLEAR_BIT });
+                }
+            }
+        }
+    }
+}
+
+/// Draws a fingerprint as an image 32 pixels wide with a row per item.
+pub fn render_fingerprint(fingerprint: &Fingerprint) -> Image {
+    let items = fingerprint.items();
+
+    let mut image = Image::new(32, items.len());
+    image.draw_items(0, items.iter().map(|&item| Some(item)), SET_BIT);
+    image
+}
+
+/// Draws chroma vectors as a heatmap 12 pixels wide with a row per frame. The colours are scaled
+/// to the largest value, so that raw vectors can be drawn as well as normalized ones.
+pub fn render_chromagram(frames: &[ChromaFrame]) -> Image {
+    let max = frames
+        .iter()
+        .flat_map(|frame| frame.chroma.iter())
+        .fold(0.0f64, |max, &value| max.max(value));
+
+    let mut image = Image::new(12, frames.len());
+    for (y, frame) in frames.iter().enumerate() {
+        for (x, &value) in frame.chroma.iter().enumerate() {
+            let level = if max > 0.0 { value / max } else { 0.0 };
+            image.set_pixel(x, y, heat(level));
+        }
+    }
+    image
+}
+
+/// Draws two fingerprints side by side, followed by their XOR, with the differing bits in red.
+/// The fingerprints are aligned like in `compare::bit_error_rate`, and the rows where only one of
+/// them has an item are left grey in the other panels.
+pub fn render_diff(a: &Fingerprint, b: &Fingerprint, offset: i32) -> Image {
+    let (a, b) = (a.items(), b.items());
+    // Rows are numbered from the first item of whichever fingerprint starts first.
+    let (a_start, b_start) = if offset >= 0 {
+        (0, offset as usize)
+    } else {
+        (offset.unsigned_abs() as usize, 0)
+    };
+    let height = (a_start + a.len()).max(b_start + b.len());
+    let item = |items: &[u32], start: usize, y: usize| {
+        y.checked_sub(start).and_then(|idx| items.get(idx)).cloned()
+    };
+
+    let mut image = Image::new(32 * 3 + GAP * 2, height);
+    image.draw_items(0, (0..height).map(|y| item(a, a_start, y)), SET_BIT);
+    image.draw_items(32 + GAP, (0..height).map(|y| item(b, b_start, y)), SET_BIT);
+
+    let (overlap_a, overlap_b) = overlap(a, b, offset);
+    let diff = overlap_a.iter().zip(overlap_b).map(|(a, b)| Some(a ^ b));
+    let first = a_start.max(b_start);
+    image.draw_items(
+        (32 + GAP) * 2,
+        (0..first).map(|_| None).chain(diff),
+        DIFFERENT_BIT,
+    );
+    image
+}
+
+/// Maps a level between 0 and 1 to a colour going from black through red and yellow to white.
+fn heat(level: f64) -> [u8; 3] {
+    let channel = |start: f64| ((level * 3.0 - start).clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(0.0), channel(1.0), channel(2.0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        render_chromagram, render_diff, render_fingerprint, ImageFormat, RenderError, BACKGROUND,
+        CLEAR_BIT, DIFFERENT_BIT, SET_BIT,
+    };
+    use algorithm::Algorithm;
+    use chroma_extractor::{ChromaExtractor, ChromaFrame, ChromaStage};
+    use fingerprinter::Fingerprint;
+    use std::fs;
+    use tempfile;
+    use tests;
+
+    #[test]
+    fn test_render_fingerprint() {
+        let items = [0x8000_0001, 0xffff_0000];
+        let image = render_fingerprint(&Fingerprint::new(&items, Algorithm::default()));
+
+        assert_eq!((32, 2), (image.width(), image.height()));
+        assert_eq!(SET_BIT, image.pixel(0, 0));
+        assert_eq!(CLEAR_BIT, image.pixel(1, 0));
+        assert_eq!(SET_BIT, image.pixel(31, 0));
+        assert_eq!(SET_BIT, image.pixel(15, 1));
+        assert_eq!(CLEAR_BIT, image.pixel(16, 1));
+
+        let scaled = image.scale(3);
+        assert_eq!((96, 6), (scaled.width(), scaled.height()));
+        assert_eq!(SET_BIT, scaled.pixel(2, 2));
+        assert_eq!(CLEAR_BIT, scaled.pixel(3, 2));
+    }
+
+    #[test]
+    fn test_render_chromagram() -> Result<(), Box<dyn std::error::Error>> {
+        let mut extractor = ChromaExtractor::new(11025, 1)?;
+        extractor.set_stage(ChromaStage::Raw);
+        extractor.feed(&tests::generate_chords(11025, 5))?;
+        extractor.finish()?;
+        let frames: Vec<ChromaFrame> = extractor.drain().collect();
+
+        let image = render_chromagram(&frames);
+        assert_eq!((12, frames.len()), (image.width(), image.height()));
+
+        // The largest value is white.
+        let (y, x) = (0..frames.len())
+            .flat_map(|y| (0..12).map(move |x| (y, x)))
+            .max_by(|&(y1, x1), &(y2, x2)| frames[y1].chroma[x1].total_cmp(&frames[y2].chroma[x2]))
+            .unwrap();
+        assert_eq!([255, 255, 255], image.pixel(x, y));
+
+        let empty = render_chromagram(&[ChromaFrame {
+            index: 0,
+            timestamp: 0.0,
+            chroma: [0.0; 12],
+        }]);
+        assert_eq!([0, 0, 0], empty.pixel(0, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_diff() {
+        let a = [1, 2, 3];
+        let b = [2, 7];
+        let algorithm = Algorithm::default();
+
+        let image = render_diff(
+            &Fingerprint::new(&a, algorithm),
+            &Fingerprint::new(&b, algorithm),
+            1,
+        );
+        assert_eq!((100, 3), (image.width(), image.height()));
+        let diff_x = 68;
+
+        // Row 0 only has an item of `a`.
+        assert_eq!(BACKGROUND, image.pixel(34, 0));
+        assert_eq!(BACKGROUND, image.pixel(diff_x, 0));
+        assert_eq!(BACKGROUND, image.pixel(32, 0));
+
+        // 2 ^ 2 and 3 ^ 7.
+        assert!((0..32).all(|x| image.pixel(diff_x + x, 1) == CLEAR_BIT));
+        assert_eq!(DIFFERENT_BIT, image.pixel(diff_x + 29, 2));
+        assert_eq!(CLEAR_BIT, image.pixel(diff_x + 30, 2));
+        assert_eq!(SET_BIT, image.pixel(34 + 29, 2));
+
+        // 1 ^ 7 in row 1.
+        let image = render_diff(
+            &Fingerprint::new(&a, algorithm),
+            &Fingerprint::new(&b, algorithm),
+            -1,
+        );
+        assert_eq!(4, image.height());
+        assert_eq!(BACKGROUND, image.pixel(31, 0));
+        assert_eq!(BACKGROUND, image.pixel(diff_x + 31, 0));
+        assert_eq!(SET_BIT, image.pixel(31, 1));
+        assert_eq!(CLEAR_BIT, image.pixel(diff_x + 31, 1));
+        assert_eq!(DIFFERENT_BIT, image.pixel(diff_x + 30, 1));
+    }
+
+    #[test]
+    fn test_write() -> Result<(), Box<dyn std::error::Error>> {
+        let items = [0xf000_000f];
+        let image = render_fingerprint(&Fingerprint::new(&items, Algorithm::default()));
+
+        let mut ppm = Vec::new();
+        image.write(&mut ppm, ImageFormat::Ppm)?;
+        assert!(ppm.starts_with(b"P6\n32 1\n255\n\xff\xff\xff"));
+        assert_eq!(12 + 32 * 3, ppm.len());
+
+        let mut pgm = Vec::new();
+        image.write(&mut pgm, ImageFormat::Pgm)?;
+        assert_eq!(&b"P5\n32 1\n255\n\xff\xff\xff\xff\0"[..], &pgm[..17]);
+        assert_eq!(12 + 32, pgm.len());
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("fingerprint.PNG");
+        image.scale(2).save(&path)?;
+        assert!(fs::read(&path)?.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        assert!(matches!(
+            image.save(dir.path().join("fingerprint.bmp")),
+            Err(RenderError::UnknownFormat)
+        ));
+        assert_eq!(Some(ImageFormat::Ppm), ImageFormat::from_extension("pnm"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty() -> Result<(), Box<dyn std::error::Error>> {
+        let image = render_fingerprint(&Fingerprint::new(&[], Algorithm::default()));
+        assert_eq!((32, 0), (image.width(), image.height()));
+
+        let mut ppm = Vec::new();
+        image.write(&mut ppm, ImageFormat::Ppm)?;
+        assert_eq!(b"P6\n32 0\n255\n".to_vec(), ppm);
+
+        assert_eq!(0, render_chromagram(&[]).height());
+
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
+use error::Error;
+use sample::Pcm;
 use std::f64::consts::PI;
 
-const FILTER_SHIFT: i32 = 15;
+/// Number of fractional bits of the filter coefficients.
+pub const FILTER_SHIFT: i32 = 15;
 
 pub struct Resampler {
     phase_shift: i32,
@@ -18,13 +21,25 @@ pub struct Resampler {
 
 impl Resampler {
     pub fn new(
-        out_rate: i32,
-        in_rate: i32,
+        out_rate: u32,
+        in_rate: u32,
         filter_size: i32,
         phase_shift: i32,
         linear: bool,
         cutoff: f64,
-    ) -> Resampler {
+    ) -> Result<Resampler, Error> {
+        // A zero rate would make the resampling factor infinite or the filter empty.
+        if out_rate == 0 {
+            return Err(Error::InvalidSampleRate(out_rate));
+        }
+        if in_rate == 0 {
+            return Err(Error::InvalidSampleRate(in_rate));
+        }
+
+        // Rates are bounded by the callers, so these fit comfortably even after being multiplied
+        // by the phase count.
+        let out_rate = out_rate as i32;
+        let in_rate = in_rate as i32;
         let factor = ((out_rate as f64) * cutoff / (in_rate as f64)).min(1.0);
         let phase_count = 1 << phase_shift;
         let filter_length = ((filter_size as f64 / factor).ceil() as i32).max(1);
@@ -47,7 +62,7 @@ impl Resampler {
 
         let dst_incr = in_rate * phase_count;
 
-        Resampler {
+        Ok(Resampler {
             phase_shift,
             phase_mask: phase_count - 1,
             linear,
@@ -56,19 +71,28 @@ impl Resampler {
             src_incr: out_rate,
             ideal_dst_incr: dst_incr,
             dst_incr,
-            index: -(phase_count as i32) * (((filter_length as i32) - 1) / 2),
+            index: -phase_count * ((filter_length - 1) / 2),
             compensation_distance: 0,
             frac: 0,
-        }
+        })
+    }
+
+    /// Forgets the position in the input, keeping the filter bank.
+    pub fn reset(&mut self) {
+        self.dst_incr = self.ideal_dst_incr;
+        self.index = -(self.phase_mask + 1) * ((self.filter_length - 1) / 2);
+        self.compensation_distance = 0;
+        self.frac = 0;
     }
 
-    /// Resamples the contents of `src` and writes the output to `dst`.
+    /// Resamples the contents of `src` and writes the output to `dst`. 16-bit samples are
+    /// filtered with the integer arithmetic of Chromaprint and floating point samples without
+    /// rounding.
     ///
     /// # Returns
     /// A tuple of the number of bytes consumed from `src` and the index of the
     /// last valid byte in `dst`.
-    pub fn resample(&mut self, src: &[i16], dst: &mut [i16]) -> (usize, usize) {
-        let consumed;
+    pub fn resample<T: Pcm>(&mut self, src: &[T], dst: &mut [T]) -> (usize, usize) {
         let mut last_dst_idx: i32 = 0;
 
         let mut index = self.index;
@@ -103,41 +127,35 @@ impl Resampler {
                 let filter_offset = (self.filter_length * (index & self.phase_mask)) as usize;
 
                 let sample_index = index >> self.phase_shift;
-                let mut val: i32 = 0;
+                let mut val = T::Sum::default();
 
                 if sample_index < 0 {
-                    for i in 0..(self.filter_length as usize) {
-                        val += (src[(sample_index + 1).abs() as usize % src.len()]
-                            * filter[filter_offset + i]) as i32;
+                    for i in 0..self.filter_length {
+                        val += src[(sample_index + i).unsigned_abs() as usize % src.len()]
+                            .weigh(filter[(filter_offset as i32 + i) as usize]);
                     }
                 } else if sample_index + self.filter_length > src.len() as i32 {
                     break;
                 } else if self.linear {
-                    let mut v2: i32 = 0;
+                    let mut v2 = T::Sum::default();
 
                     for i in 0..self.filter_length {
-                        val += (src[(sample_index + i) as usize] as i32)
-                            * (filter[(filter_offset as i32 + i) as usize] as i32);
-                        v2 += (src[sample_index as usize] as i32)
-                            * (filter[(filter_offset as i32 + i + self.filter_length) as usize]
-                                as i32);
+                        val += src[(sample_index + i) as usize]
+                            .weigh(filter[(filter_offset as i32 + i) as usize]);
+                        v2 += src[sample_index as usize].weigh(
+                            filter[(filter_offset as i32 + i + self.filter_length) as usize],
+                        );
                     }
 
-                    val +=
-                        ((v2 as i64 - val as i64) * (frac as i64) / (self.src_incr as i64)) as i32;
+                    val = T::interpolate(val, v2, frac, self.src_incr);
                 } else {
                     for i in 0..self.filter_length {
-                        val += (src[(sample_index + i) as usize] as i32)
-                            * (filter[(filter_offset as i32 + i) as usize] as i32);
+                        val += src[(sample_index + i) as usize]
+                            .weigh(filter[(filter_offset as i32 + i) as usize]);
                     }
                 }
 
-                val = (val + (1 << (FILTER_SHIFT - 1))) >> FILTER_SHIFT;
-                dst[dst_index] = if i32::saturating_add(val, 32768) == ::std::i32::MAX {
-                    (val >> 31) ^ 32767
-                } else {
-                    val
-                } as i16;
+                dst[dst_index] = T::from_sum(val);
 
                 frac += dst_incr_frac;
                 index += dst_incr;
@@ -156,7 +174,7 @@ impl Resampler {
             }
         }
 
-        consumed = index.max(0) >> self.phase_shift;
+        let consumed = index.max(0) >> self.phase_shift;
         if index >= 0 {
             index &= self.phase_mask;
         }
@@ -222,8 +240,8 @@ fn make_filter_bank(
         for i in 0..tap_count {
             filter[phase * tap_count + i] = clip(
                 (tab[i] * scale / norm).round() as i32,
-                i16::min_value() as i32,
-                i16::max_value() as i32,
+                i16::MIN as i32,
+                i16::MAX as i32,
             ) as i16;
         }
     }
@@ -265,8 +283,8 @@ mod tests {
     use std::path::PathBuf;
     use tests::{load_audio_file, load_stero_audio_file};
 
-    const TARGET_SAMPLE_RATE: i32 = 11025;
-    const INPUT_SAMPLE_RATE: i32 = 44100;
+    const TARGET_SAMPLE_RATE: u32 = 11025;
+    const INPUT_SAMPLE_RATE: u32 = 44100;
     const RESAMPLE_FILTER_LENGTH: i32 = 16;
     const RESAMPLE_PHASE_SHIFT: i32 = 8;
     const RESAMPLE_LINEAR: bool = false;
@@ -302,7 +320,7 @@ mod tests {
             filter_bank[(filter_length - 1) as usize];
 
         let expected = load_audio_file(
-            &PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/filter_bank_values.raw"),
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/filter_bank_values.raw"),
         )?;
         assert_eq!(expected, filter_bank);
 
@@ -322,7 +340,7 @@ mod tests {
             RESAMPLE_PHASE_SHIFT,
             RESAMPLE_LINEAR,
             RESAMPLE_SAMPLE_CUTOFF,
-        );
+        )?;
         let mut output = vec![0; samples.len()];
         let (_src_consumed, last_dst_idx) = resampler.resample(&samples, &mut output);
         output.truncate(last_dst_idx + 1);
@@ -335,4 +353,86 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_resample_float() -> Result<(), Box<dyn Error>> {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw");
+        let samples = load_stero_audio_file(&path)?;
+        let float_samples: Vec<f32> = samples.iter().map(|&sample| sample as f32).collect();
+
+        let new = || {
+            Resampler::new(
+                TARGET_SAMPLE_RATE,
+                INPUT_SAMPLE_RATE,
+                RESAMPLE_FILTER_LENGTH,
+                RESAMPLE_PHASE_SHIFT,
+                RESAMPLE_LINEAR,
+                RESAMPLE_SAMPLE_CUTOFF,
+            )
+        };
+        let mut output = vec![0; samples.len()];
+        let (consumed, last_dst_idx) = new()?.resample(&samples, &mut output);
+        let mut float_output = vec![0.0; samples.len()];
+        assert_eq!(
+            (consumed, last_dst_idx),
+            new()?.resample(&float_samples, &mut float_output)
+        );
+
+        // The same filter, without rounding to 16 bits.
+        assert!(float_output.iter().any(|sample| sample.fract() != 0.0));
+        for (&sample, &float_sample) in output.iter().zip(&float_output).take(last_dst_idx + 1) {
+            assert!((sample as f32 - float_sample).abs() <= 0.5);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resample_start() -> Result<(), Box<dyn Error>> {
+        // The first output samples mirror the input around its start, which used to read the
+        // same sample for every tap and overflow when multiplying it in 16 bits.
+        let samples = vec![10000i16; 4096];
+
+        let mut resampler = Resampler::new(
+            TARGET_SAMPLE_RATE,
+            INPUT_SAMPLE_RATE,
+            RESAMPLE_FILTER_LENGTH,
+            RESAMPLE_PHASE_SHIFT,
+            RESAMPLE_LINEAR,
+            RESAMPLE_SAMPLE_CUTOFF,
+        )?;
+        let mut output = vec![0; samples.len()];
+        let (_src_consumed, last_dst_idx) = resampler.resample(&samples, &mut output);
+
+        assert!(last_dst_idx > 0);
+        for &sample in &output[..=last_dst_idx] {
+            assert!((sample - 10000).abs() <= 10, "{}", sample);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zero_rate() {
+        let new = |out_rate, in_rate| {
+            Resampler::new(
+                out_rate,
+                in_rate,
+                RESAMPLE_FILTER_LENGTH,
+                RESAMPLE_PHASE_SHIFT,
+                RESAMPLE_LINEAR,
+                RESAMPLE_SAMPLE_CUTOFF,
+            )
+        };
+
+        assert_eq!(
+            Some(::error::Error::InvalidSampleRate(0)),
+            new(TARGET_SAMPLE_RATE, 0).err()
+        );
+        assert_eq!(
+            Some(::error::Error::InvalidSampleRate(0)),
+            new(0, INPUT_SAMPLE_RATE).err()
+        );
+    }
 }
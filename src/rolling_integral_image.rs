@@ -14,6 +14,12 @@ impl RollingIntegralImage {
         }
     }
 
+    /// Removes all rows. The storage is kept and overwritten by later rows.
+    pub fn reset(&mut self) {
+        self.rows_count = 0;
+        self.empty = true;
+    }
+
     pub fn rows(&self) -> usize {
         self.rows_count
     }
@@ -0,0 +1,249 @@
+use resampler::FILTER_SHIFT;
+use std::ops::AddAssign;
+
+/// A PCM sample which can be fed into a `Fingerprinter`.
+///
+/// 16-bit samples go through the pipeline as they are, with the same integer arithmetic as
+/// Chromaprint. Any other samples are carried in floating point on the same scale, so the
+/// precision of wider input isn't lost before the FFT. Integer samples are treated as full scale
+/// for their width and floating point samples are expected to be in `[-1.0, 1.0]`. Floating point
+/// values outside of the range are clipped and NaN is treated as silence.
+pub trait Sample: Copy {
+    /// Converts the sample to floating point on a 16-bit scale, where `i16::MAX` is full scale.
+    fn to_f32(self) -> f32;
+
+    /// Converts the sample to 16-bit, rounding to the nearest value and clipping.
+    fn to_i16(self) -> i16 {
+        // Casting saturates.
+        self.to_f32().round() as i16
+    }
+
+    /// Returns the samples if they are 16-bit, to be processed without conversion.
+    fn as_i16_slice(_samples: &[Self]) -> Option<&[i16]> {
+        None
+    }
+}
+
+impl Sample for i16 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn to_i16(self) -> i16 {
+        self
+    }
+
+    fn as_i16_slice(samples: &[i16]) -> Option<&[i16]> {
+        Some(samples)
+    }
+}
+
+impl Sample for i32 {
+    fn to_f32(self) -> f32 {
+        (self as f64 / 65536.0) as f32
+    }
+
+    fn to_i16(self) -> i16 {
+        round_shift(self as i64, 16)
+    }
+}
+
+impl Sample for f32 {
+    fn to_f32(self) -> f32 {
+        (self as f64).to_f32()
+    }
+}
+
+impl Sample for f64 {
+    fn to_f32(self) -> f32 {
+        if self.is_nan() {
+            return 0.0;
+        }
+        (self * 32768.0).clamp(i16::MIN as f64, i16::MAX as f64) as f32
+    }
+}
+
+/// A packed, little endian, signed 24-bit sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I24([u8; 3]);
+
+impl I24 {
+    pub fn from_le_bytes(bytes: [u8; 3]) -> I24 {
+        I24(bytes)
+    }
+
+    /// Creates a sample from the lower 24 bits of `value`.
+    pub fn from_i32(value: i32) -> I24 {
+        let bytes = value.to_le_bytes();
+        I24([bytes[0], bytes[1], bytes[2]])
+    }
+
+    pub fn to_i32(self) -> i32 {
+        // Places the sample in the upper bytes so the shift extends the sign.
+        i32::from_le_bytes([0, self.0[0], self.0[1], self.0[2]]) >> 8
+    }
+}
+
+impl Sample for I24 {
+    fn to_f32(self) -> f32 {
+        self.to_i32() as f32 / 256.0
+    }
+
+    fn to_i16(self) -> i16 {
+        round_shift(self.to_i32() as i64, 8)
+    }
+}
+
+/// A type samples are carried in before they are resampled: `i16` for 16-bit input and `f32` for
+/// anything else.
+pub trait Pcm: Sample + Default {
+    /// Sum of samples weighted by the resampler filter.
+    type Sum: Copy + Default + AddAssign;
+
+    /// Converts a sample which was stored as floating point, like a partial frame held by the
+    /// downmixer.
+    fn from_f32(sample: f32) -> Self;
+
+    /// Averages the samples of a frame into a single channel.
+    fn mix<I: Iterator<Item = Self>>(frame: I, channels: usize) -> Self;
+
+    /// Multiplies the sample by a resampler filter coefficient with `FILTER_SHIFT` fractional
+    /// bits.
+    fn weigh(self, coefficient: i16) -> Self::Sum;
+
+    /// Interpolates linearly from `a` to `b` by `frac / incr`.
+    fn interpolate(a: Self::Sum, b: Self::Sum, frac: i32, incr: i32) -> Self::Sum;
+
+    /// Scales a sum of weighted samples back down to a sample.
+    fn from_sum(sum: Self::Sum) -> Self;
+}
+
+impl Pcm for i16 {
+    type Sum = i32;
+
+    fn from_f32(sample: f32) -> i16 {
+        sample as i16
+    }
+
+    /// Truncates the average towards zero, like Chromaprint.
+    fn mix<I: Iterator<Item = i16>>(frame: I, channels: usize) -> i16 {
+        let sum: i32 = frame.map(|sample| sample as i32).sum();
+        (sum / channels as i32) as i16
+    }
+
+    fn weigh(self, coefficient: i16) -> i32 {
+        self as i32 * coefficient as i32
+    }
+
+    fn interpolate(a: i32, b: i32, frac: i32, incr: i32) -> i32 {
+        a + ((b as i64 - a as i64) * (frac as i64) / (incr as i64)) as i32
+    }
+
+    fn from_sum(sum: i32) -> i16 {
+        let val = (sum + (1 << (FILTER_SHIFT - 1))) >> FILTER_SHIFT;
+        if i32::saturating_add(val, 32768) == i32::MAX {
+            ((val >> 31) ^ 32767) as i16
+        } else {
+            val as i16
+        }
+    }
+}
+
+impl Pcm for f32 {
+    type Sum = f32;
+
+    fn from_f32(sample: f32) -> f32 {
+        sample
+    }
+
+    fn mix<I: Iterator<Item = f32>>(frame: I, channels: usize) -> f32 {
+        frame.sum::<f32>() / channels as f32
+    }
+
+    fn weigh(self, coefficient: i16) -> f32 {
+        self * coefficient as f32
+    }
+
+    fn interpolate(a: f32, b: f32, frac: i32, incr: i32) -> f32 {
+        a + (b - a) * frac as f32 / incr as f32
+    }
+
+    fn from_sum(sum: f32) -> f32 {
+        sum / (1 << FILTER_SHIFT) as f32
+    }
+}
+
+/// Shifts `value` right by `bits`, rounding to the nearest value and clipping to 16-bit.
+fn round_shift(value: i64, bits: u32) -> i16 {
+    let rounded = (value + (1 << (bits - 1))) >> bits;
+    rounded.clamp(i16::MIN as i64, i16::MAX as i64) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pcm, Sample, I24};
+
+    #[test]
+    fn test_i16() {
+        let samples = [0i16, 1, -1, i16::MAX, i16::MIN];
+
+        assert_eq!(Some(&samples[..]), i16::as_i16_slice(&samples));
+        assert_eq!(None, f32::as_i16_slice(&[0.0f32]));
+        assert_eq!(-32768.0, i16::MIN.to_f32());
+    }
+
+    #[test]
+    fn test_i32() {
+        assert_eq!(0, 0i32.to_i16());
+        assert_eq!(1, (1i32 << 16).to_i16());
+        assert_eq!(1, (1i32 << 15).to_i16());
+        assert_eq!(0, ((1i32 << 15) - 1).to_i16());
+        assert_eq!(-1, (-1i32 << 16).to_i16());
+        assert_eq!(i16::MAX, i32::MAX.to_i16());
+        assert_eq!(i16::MIN, i32::MIN.to_i16());
+
+        // The bits below 16-bit precision are kept.
+        assert_eq!(0.5, (1i32 << 15).to_f32());
+        assert_eq!(-1.25, (-5i32 << 14).to_f32());
+    }
+
+    #[test]
+    fn test_i24() {
+        assert_eq!(-1, I24::from_le_bytes([0xff, 0xff, 0xff]).to_i32());
+        assert_eq!(0x7f_ffff, I24::from_le_bytes([0xff, 0xff, 0x7f]).to_i32());
+        assert_eq!(-0x80_0000, I24::from_i32(-0x80_0000).to_i32());
+
+        assert_eq!(1, I24::from_i32(1 << 8).to_i16());
+        assert_eq!(-300, I24::from_i32(-300 << 8).to_i16());
+        assert_eq!(i16::MAX, I24::from_i32(0x7f_ffff).to_i16());
+        assert_eq!(i16::MIN, I24::from_i32(-0x80_0000).to_i16());
+        assert_eq!(-300.5, I24::from_i32(-300 * 256 - 128).to_f32());
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(0, 0.0f32.to_i16());
+        assert_eq!(16384, 0.5f32.to_i16());
+        assert_eq!(-16384, (-0.5f64).to_i16());
+        assert_eq!(i16::MAX, 1.0f64.to_i16());
+        assert_eq!(i16::MIN, (-1.0f32).to_i16());
+        assert_eq!(i16::MAX, 2.0f32.to_i16());
+        assert_eq!(0, f64::NAN.to_i16());
+
+        assert_eq!(0.25, (0.25f32 / 32768.0).to_f32());
+        assert_eq!(-32768.0, f64::NEG_INFINITY.to_f32());
+        assert_eq!(0.0, f32::NAN.to_f32());
+    }
+
+    #[test]
+    fn test_pcm() {
+        // Filtering in 16 bits rounds like Chromaprint.
+        assert_eq!(3, i16::from_sum(3 << 15));
+        assert_eq!(2, i16::from_sum((5 << 14) - 1));
+        assert_eq!(-3, i16::from_sum(-3 << 15));
+        assert_eq!(1.25, f32::from_sum(5.0 * (1 << 13) as f32));
+
+        assert_eq!(-1, i16::mix([-1i16, -2].iter().cloned(), 2));
+        assert_eq!(-1.5, f32::mix([-1.0f32, -2.0].iter().cloned(), 2));
+    }
+}
@@ -0,0 +1,126 @@
+/// Number of samples the absolute amplitude is averaged over, like in Chromaprint.
+const SILENCE_WINDOW: usize = 55;
+
+/// Drops the leading silence of a stream of samples, like Chromaprint's `SilenceRemover`.
+///
+/// Samples are dropped until the moving average of their absolute amplitude exceeds the
+/// threshold. Everything from that sample on is passed through, including later silence.
+pub struct SilenceRemover {
+    threshold: i32,
+
+    /// Whether the end of the leading silence hasn't been found yet.
+    removing: bool,
+
+    window: [i32; SILENCE_WINDOW],
+    window_offset: usize,
+    window_count: usize,
+    window_sum: i32,
+
+    removed: usize,
+}
+
+impl SilenceRemover {
+    pub fn new(threshold: i32) -> SilenceRemover {
+        SilenceRemover {
+            threshold,
+            removing: true,
+            window: [0; SILENCE_WINDOW],
+            window_offset: 0,
+            window_count: 0,
+            window_sum: 0,
+            removed: 0,
+        }
+    }
+
+    /// Starts looking for leading silence again, as if no samples had been processed.
+    pub fn reset(&mut self) {
+        *self = SilenceRemover::new(self.threshold);
+    }
+
+    /// Returns the part of `samples`, on a 16-bit scale, which follows the leading silence.
+    pub fn process<'a>(&mut self, samples: &'a [f32]) -> &'a [f32] {
+        if !self.removing {
+            return samples;
+        }
+
+        for (idx, &sample) in samples.iter().enumerate() {
+            // Truncated like the 16-bit samples of Chromaprint.
+            if self.add_to_average(sample.abs() as i32) > self.threshold {
+                self.removing = false;
+                self.removed += idx;
+                return &samples[idx..];
+            }
+        }
+
+        self.removed += samples.len();
+        &[]
+    }
+
+    /// Number of samples dropped so far.
+    pub fn removed(&self) -> usize {
+        self.removed
+    }
+
+    /// Adds a value to the moving average and returns the new average, truncated like the
+    /// integer division in Chromaprint.
+    fn add_to_average(&mut self, value: i32) -> i32 {
+        self.window_sum += value - self.window[self.window_offset];
+        self.window[self.window_offset] = value;
+        self.window_offset = (self.window_offset + 1) % SILENCE_WINDOW;
+        self.window_count = (self.window_count + 1).min(SILENCE_WINDOW);
+
+        self.window_sum / self.window_count as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SilenceRemover, SILENCE_WINDOW};
+
+    #[test]
+    fn test_leading_silence() {
+        let mut samples = vec![0.0f32; 1000];
+        samples.extend_from_slice(&[5000.0, -5000.0, 0.0, 0.0, 5000.0]);
+
+        let mut remover = SilenceRemover::new(50);
+        assert_eq!(&samples[1000..], remover.process(&samples));
+        assert_eq!(1000, remover.removed());
+
+        // Silence after the start is kept.
+        assert_eq!(&[0.0, 0.0, 0.0][..], remover.process(&[0.0, 0.0, 0.0]));
+        assert_eq!(1000, remover.removed());
+    }
+
+    #[test]
+    fn test_moving_average() {
+        // Quiet samples only end the silence once the window average is above the threshold.
+        let samples = vec![100.0f32; SILENCE_WINDOW];
+        let mut remover = SilenceRemover::new(99);
+        assert_eq!(&samples[..], remover.process(&samples));
+
+        let mut samples = vec![0.0f32; SILENCE_WINDOW];
+        samples.extend(vec![100.0f32; SILENCE_WINDOW]);
+        let mut remover = SilenceRemover::new(49);
+        // The average first exceeds 49 when 28 of the last 55 samples are 100.
+        assert_eq!(&samples[(SILENCE_WINDOW + 27)..], remover.process(&samples));
+        assert_eq!(SILENCE_WINDOW + 27, remover.removed());
+    }
+
+    #[test]
+    fn test_split_input() {
+        let mut remover = SilenceRemover::new(50);
+
+        assert!(remover.process(&[0.0; 100]).is_empty());
+        assert!(remover.process(&[10.0; 100]).is_empty());
+        assert_eq!(&[5000.0, 0.0][..], remover.process(&[5000.0, 0.0]));
+        assert_eq!(200, remover.removed());
+    }
+
+    #[test]
+    fn test_zero_threshold() {
+        let mut remover = SilenceRemover::new(0);
+
+        assert_eq!(&[100.0, 0.0][..], remover.process(&[0.0, 0.0, 100.0, 0.0]));
+        assert_eq!(2, remover.removed());
+    }
+}
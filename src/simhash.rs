@@ -0,0 +1,62 @@
+/// Hashes a whole fingerprint into 32 bits, like Chromaprint's `chromaprint_hash_fingerprint`.
+///
+/// Every bit of the hash is set if it is set in more than half of the items. Similar fingerprints
+/// have hashes with a small hamming distance.
+pub fn simhash(fingerprint: &[u32]) -> u32 {
+    let mut votes = [0i32; 32];
+
+    for item in fingerprint {
+        for (bit, vote) in votes.iter_mut().enumerate() {
+            if item & (1 << bit) != 0 {
+                *vote += 1;
+            } else {
+                *vote -= 1;
+            }
+        }
+    }
+
+    votes
+        .iter()
+        .enumerate()
+        .filter(|(_, vote)| **vote > 0)
+        .fold(0, |hash, (bit, _)| hash | (1 << bit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::simhash;
+    use fingerprinter::Fingerprinter;
+    use std::error::Error;
+    use std::path::PathBuf;
+    use tests;
+
+    #[test]
+    fn test_simhash() -> Result<(), Box<dyn Error>> {
+        let samples = tests::load_audio_file(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw"),
+        )?;
+
+        let mut fingerprinter = Fingerprinter::new(44100, 1)?;
+        fingerprinter.feed(&samples)?;
+        fingerprinter.finish()?;
+
+        assert_eq!(4_000_438_583, fingerprinter.fingerprint().simhash());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simhash_votes() {
+        assert_eq!(0, simhash(&[]));
+        assert_eq!(0, simhash(&[0]));
+        assert_eq!(1, simhash(&[1]));
+        assert_eq!(0xffff_ffff, simhash(&[0xffff_ffff]));
+        assert_eq!(0, simhash(&[0, 1]));
+        assert_eq!(1, simhash(&[1, 1, 0]));
+        assert_eq!(0b101, simhash(&[0b111, 0b101, 0b100, 0b001]) & 0b101);
+        assert_eq!(
+            0x8000_0003,
+            simhash(&[0x8000_0003, 0x8000_0001, 0x0000_0002])
+        );
+    }
+}
@@ -40,6 +40,17 @@ where
         }
     }
 
+    /// Drops any buffered data.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Adds data to the buffer. The buffer must be smaller than a slice afterwards.
+    pub fn extend<I: IntoIterator<Item = T>>(&mut self, data: I) {
+        self.buffer.extend(data);
+        debug_assert!(self.buffer.len() < self.slice_size);
+    }
+
     pub fn flush(&mut self) -> Vec<T> {
         std::mem::take(&mut self.buffer)
     }
@@ -69,6 +80,11 @@ where
             increment
         });
     }
+
+    /// Drops any buffered data.
+    pub fn reset(&mut self) {
+        self.slicer.reset();
+    }
 }
 
 #[cfg(test)]
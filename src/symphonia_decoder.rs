@@ -0,0 +1,424 @@
+use decoder::{Decoder, DecoderError, Samples};
+use std::io::{self, Read};
+use symphonia;
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer};
+use symphonia::core::codecs::{self, CodecRegistry, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::conv::ConvertibleSample;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::{MediaSource, MediaSourceStream, ReadOnlySource};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Holds the samples of the last decoded packet, in the narrowest of these formats which holds
+/// them without loss.
+#[derive(Default)]
+struct Buffers {
+    i16: Option<SampleBuffer<i16>>,
+    i32: Option<SampleBuffer<i32>>,
+    f32: Option<SampleBuffer<f32>>,
+    f64: Option<SampleBuffer<f64>>,
+}
+
+/// Decodes the first audio track of any format supported by Symphonia. Samples are yielded in
+/// the format they were decoded to, with 8-bit samples widened to 16-bit and 24-bit samples to
+/// 32-bit.
+pub struct SymphoniaDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn codecs::Decoder>,
+    track_id: u32,
+    sample_rate: u32,
+    channels: u16,
+    buffers: Buffers,
+}
+
+impl SymphoniaDecoder {
+    /// Detects the format of `source` and prepares to decode its first audio track.
+    ///
+    /// # Arguments
+    /// * `extension` - The file extension of the source, if any, which helps to detect formats.
+    pub fn new(
+        source: Box<dyn MediaSource>,
+        extension: Option<&str>,
+    ) -> Result<SymphoniaDecoder, DecoderError> {
+        SymphoniaDecoder::with_codecs(source, extension, symphonia::default::get_codecs())
+    }
+
+    /// Like `new`, decoding with the codecs of `codecs` instead of the ones Symphonia ships.
+    /// Symphonia demuxes Ogg Opus but doesn't decode it, so this is how an Opus decoder, like
+    /// one wrapping libopus, is plugged in.
+    pub fn with_codecs(
+        source: Box<dyn MediaSource>,
+        extension: Option<&str>,
+        codecs: &CodecRegistry,
+    ) -> Result<SymphoniaDecoder, DecoderError> {
+        let mut hint = Hint::new();
+        if let Some(extension) = extension {
+            hint.with_extension(extension);
+        }
+
+        let stream = MediaSourceStream::new(source, Default::default());
+        let format = symphonia::default::get_probe()
+            .format(
+                &hint,
+                stream,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )?
+            .format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(SymphoniaError::Unsupported("no audio track"))?;
+        let sample_rate = track
+            .codec_params
+            .sample_rate
+            .ok_or(SymphoniaError::Unsupported("unknown sample rate"))?;
+        let channels = track
+            .codec_params
+            .channels
+            .ok_or(SymphoniaError::Unsupported("unknown channel layout"))?
+            .count() as u16;
+        let decoder = codecs.make(&track.codec_params, &DecoderOptions::default())?;
+
+        Ok(SymphoniaDecoder {
+            track_id: track.id,
+            format,
+            decoder,
+            sample_rate,
+            channels,
+            buffers: Buffers::default(),
+        })
+    }
+
+    /// Like `new`, for a source which can't seek. Some formats, like MP4 files with their index
+    /// at the end, can't be read this way.
+    pub fn from_reader<R: Read + Send + Sync + 'static>(
+        reader: R,
+        extension: Option<&str>,
+    ) -> Result<SymphoniaDecoder, DecoderError> {
+        SymphoniaDecoder::new(Box::new(ReadOnlySource::new(reader)), extension)
+    }
+}
+
+impl Decoder for SymphoniaDecoder {
+    type Error = DecoderError;
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn next_block(&mut self) -> Result<Option<Samples<'_>>, DecoderError> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                // Symphonia signals the end of the stream this way.
+                Err(SymphoniaError::IoError(ref err))
+                    if err.kind() == io::ErrorKind::UnexpectedEof =>
+                {
+                    return Ok(None)
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // Corrupt packets are skipped, like players do.
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(err) => return Err(err.into()),
+            };
+            if decoded.frames() == 0 {
+                continue;
+            }
+
+            let spec = *decoded.spec();
+            if spec.rate != self.sample_rate || spec.channels.count() != self.channels as usize {
+                return Err(SymphoniaError::Unsupported("audio format changes mid-stream").into());
+            }
+
+            let buffers = &mut self.buffers;
+            return Ok(Some(match decoded {
+                AudioBufferRef::U8(_)
+                | AudioBufferRef::S8(_)
+                | AudioBufferRef::U16(_)
+                | AudioBufferRef::S16(_) => {
+                    Samples::I16(copy_interleaved(&mut buffers.i16, decoded))
+                }
+                AudioBufferRef::U24(_)
+                | AudioBufferRef::S24(_)
+                | AudioBufferRef::U32(_)
+                | AudioBufferRef::S32(_) => {
+                    Samples::I32(copy_interleaved(&mut buffers.i32, decoded))
+                }
+                AudioBufferRef::F32(_) => Samples::F32(copy_interleaved(&mut buffers.f32, decoded)),
+                AudioBufferRef::F64(_) => Samples::F64(copy_interleaved(&mut buffers.f64, decoded)),
+            }));
+        }
+    }
+}
+
+/// Copies decoded audio into `buffer` as interleaved samples, replacing the buffer if it is too
+/// small.
+fn copy_interleaved<'a, S: ConvertibleSample>(
+    buffer: &'a mut Option<SampleBuffer<S>>,
+    decoded: AudioBufferRef,
+) -> &'a [S] {
+    let spec = *decoded.spec();
+    if buffer
+        .as_ref()
+        .is_none_or(|buffer| buffer.capacity() < decoded.capacity() * spec.channels.count())
+    {
+        *buffer = None;
+    }
+
+    let buffer = buffer.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+    buffer.copy_interleaved_ref(decoded);
+    buffer.samples()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SymphoniaDecoder;
+    use decoder::{feed_decoder, Decoder, DecoderError, Samples};
+    use fingerprinter::Fingerprinter;
+    use std::io::Cursor;
+    use symphonia::core::audio::{
+        AsAudioBufferRef, AudioBuffer, AudioBufferRef, Signal, SignalSpec,
+    };
+    use symphonia::core::checksum::Crc32;
+    use symphonia::core::codecs::{
+        self, CodecDescriptor, CodecParameters, CodecRegistry, DecoderOptions, FinalizeResult,
+        CODEC_TYPE_OPUS,
+    };
+    use symphonia::core::errors::Result as SymphoniaResult;
+    use symphonia::core::formats::Packet;
+    use symphonia::core::io::Monitor;
+    use tests;
+    use wav::WavReader;
+
+    /// The samples in a 20 ms Opus frame.
+    const OPUS_FRAME: usize = 960;
+
+    /// Stands in for a real Opus decoder: its mono packets hold the TOC byte of a 20 ms frame
+    /// followed by the 16-bit samples of the frame.
+    struct FakeOpusDecoder {
+        params: CodecParameters,
+        buffer: AudioBuffer<f32>,
+    }
+
+    impl codecs::Decoder for FakeOpusDecoder {
+        fn try_new(params: &CodecParameters, _: &DecoderOptions) -> SymphoniaResult<Self> {
+            let spec = SignalSpec::new(48000, params.channels.unwrap());
+            Ok(FakeOpusDecoder {
+                params: params.clone(),
+                buffer: AudioBuffer::new(OPUS_FRAME as u64, spec),
+            })
+        }
+
+        fn supported_codecs() -> &'static [CodecDescriptor] {
+            &[CodecDescriptor {
+                codec: CODEC_TYPE_OPUS,
+                short_name: "opus",
+                long_name: "Fake Opus",
+                inst_func: |params, options| {
+                    Ok(Box::new(FakeOpusDecoder::try_new(params, options)?))
+                },
+            }]
+        }
+
+        fn reset(&mut self) {}
+
+        fn codec_params(&self) -> &CodecParameters {
+            &self.params
+        }
+
+        fn decode(&mut self, packet: &Packet) -> SymphoniaResult<AudioBufferRef<'_>> {
+            let samples = &packet.data[1..];
+            self.buffer.clear();
+            self.buffer.render_reserved(Some(samples.len() / 2));
+            for (decoded, bytes) in self.buffer.chan_mut(0).iter_mut().zip(samples.chunks(2)) {
+                *decoded = i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0;
+            }
+            Ok(self.buffer.as_audio_buffer_ref())
+        }
+
+        fn finalize(&mut self) -> FinalizeResult {
+            FinalizeResult::default()
+        }
+
+        fn last_decoded(&self) -> AudioBufferRef<'_> {
+            self.buffer.as_audio_buffer_ref()
+        }
+    }
+
+    /// Writes an Ogg page holding a single packet.
+    fn ogg_page(flags: u8, granule_position: u64, sequence: u32, packet: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\0".to_vec();
+        page.push(flags);
+        page.extend_from_slice(&granule_position.to_le_bytes());
+        page.extend_from_slice(&1u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        // The checksum, computed over the page with this field zeroed.
+        page.extend_from_slice(&[0; 4]);
+        page.push((packet.len() / 255 + 1) as u8);
+        page.resize(page.len() + packet.len() / 255, 255);
+        page.push((packet.len() % 255) as u8);
+        page.extend_from_slice(packet);
+
+        let mut crc = Crc32::new(0);
+        crc.process_buf_bytes(&page);
+        page[22..26].copy_from_slice(&crc.crc().to_le_bytes());
+        page
+    }
+
+    /// Writes a mono Ogg Opus stream for `FakeOpusDecoder`.
+    fn ogg_opus(samples: &[i16]) -> Vec<u8> {
+        let mut head = b"OpusHead".to_vec();
+        // Version, channels, pre-skip, input sample rate, gain and channel mapping.
+        head.extend_from_slice(&[1, 1, 0, 0]);
+        head.extend_from_slice(&48000u32.to_le_bytes());
+        head.extend_from_slice(&[0, 0, 0]);
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&4u32.to_le_bytes());
+        tags.extend_from_slice(b"test");
+        tags.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut stream = ogg_page(2, 0, 0, &head);
+        stream.extend(ogg_page(0, 0, 1, &tags));
+        let frames = samples.chunks(OPUS_FRAME).count();
+        for (i, frame) in samples.chunks(OPUS_FRAME).enumerate() {
+            let mut packet = vec![1 << 3];
+            packet.extend(tests::pcm16_bytes(frame));
+            let flags = if i + 1 == frames { 4 } else { 0 };
+            let granule_position = ((i + 1) * OPUS_FRAME) as u64;
+            stream.extend(ogg_page(flags, granule_position, i as u32 + 2, &packet));
+        }
+        stream
+    }
+
+    #[test]
+    fn test_decode_wav() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(22050, 10);
+        let stereo: Vec<i16> = samples.iter().flat_map(|&s| vec![s, -s]).collect();
+        let file = tests::wav_bytes(&stereo, 22050, 2);
+
+        let mut decoder = SymphoniaDecoder::from_reader(Cursor::new(file.clone()), Some("wav"))?;
+        assert_eq!(22050, decoder.sample_rate());
+        assert_eq!(2, decoder.channels());
+
+        let mut decoded = Vec::new();
+        while let Some(block) = decoder.next_block()? {
+            match block {
+                Samples::I16(block) => decoded.extend_from_slice(block),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(stereo, decoded);
+
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        let mut decoder = SymphoniaDecoder::new(Box::new(Cursor::new(file.clone())), None)?;
+        feed_decoder(&mut decoder, &mut fingerprinter)?;
+        fingerprinter.finish()?;
+
+        let mut expected = Fingerprinter::new(11025, 1)?;
+        WavReader::new(Cursor::new(file))?.feed(&mut expected)?;
+        expected.finish()?;
+        assert_eq!(
+            expected.fingerprint().items(),
+            fingerprinter.fingerprint().items()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_native_samples() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(22050, 10);
+
+        // 24-bit samples come out as 32-bit ones.
+        let pcm24: Vec<u8> = samples
+            .iter()
+            .flat_map(|&s| (s as i32 * 3).to_le_bytes()[..3].to_vec())
+            .collect();
+        let file = tests::wav_with_format(1, 22050, 1, 24, &pcm24);
+        let mut decoder = SymphoniaDecoder::from_reader(Cursor::new(file.clone()), Some("wav"))?;
+        match decoder.next_block()? {
+            Some(Samples::I32(block)) => {
+                for (&sample, &decoded) in samples.iter().zip(block) {
+                    assert_eq!((sample as i32 * 3) << 8, decoded);
+                }
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut decoder = SymphoniaDecoder::from_reader(Cursor::new(file.clone()), Some("wav"))?;
+        let mut fingerprinter = Fingerprinter::new(11025, 1)?;
+        feed_decoder(&mut decoder, &mut fingerprinter)?;
+        fingerprinter.finish()?;
+
+        let mut expected = Fingerprinter::new(11025, 1)?;
+        WavReader::new(Cursor::new(file))?.feed(&mut expected)?;
+        expected.finish()?;
+        assert_eq!(
+            expected.fingerprint().items(),
+            fingerprinter.fingerprint().items()
+        );
+
+        let float32: Vec<u8> = samples
+            .iter()
+            .flat_map(|&s| (s as f32 / 32768.0).to_le_bytes())
+            .collect();
+        let file = tests::wav_with_format(3, 22050, 1, 32, &float32);
+        let mut decoder = SymphoniaDecoder::from_reader(Cursor::new(file), Some("wav"))?;
+        assert!(matches!(decoder.next_block()?, Some(Samples::F32(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_opus() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(48000, 10);
+        let file = ogg_opus(&samples);
+
+        // Symphonia doesn't decode Opus itself.
+        assert!(matches!(
+            SymphoniaDecoder::from_reader(Cursor::new(file.clone()), Some("opus")),
+            Err(DecoderError::Symphonia(_))
+        ));
+
+        let mut codecs = CodecRegistry::new();
+        codecs.register_all::<FakeOpusDecoder>();
+        let mut decoder =
+            SymphoniaDecoder::with_codecs(Box::new(Cursor::new(file)), Some("opus"), &codecs)?;
+        assert_eq!(48000, decoder.sample_rate());
+        assert_eq!(1, decoder.channels());
+
+        let mut decoded = Vec::new();
+        while let Some(block) = decoder.next_block()? {
+            match block {
+                Samples::F32(block) => decoded.extend(block.iter().map(|&s| (s * 32768.0) as i16)),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(samples, decoded);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!(matches!(
+            SymphoniaDecoder::from_reader(Cursor::new(vec![0u8; 1000]), None),
+            Err(DecoderError::Symphonia(_))
+        ));
+    }
+}
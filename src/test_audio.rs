@@ -0,0 +1,110 @@
+//! Test audio shared by the tests of the library and of `fpcalc`, which includes this file.
+
+use super::Fingerprinter;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+
+/// Number of input samples in an item when the input is at the target sample rate.
+pub const ITEM_SAMPLES: usize = 1365;
+
+/// Loads the test recording. Fingerprinting its interleaved samples as 11025 Hz mono audio
+/// stretches it to 16 seconds, which gives enough items to align.
+pub fn load_samples() -> Result<Vec<i16>, Box<dyn Error>> {
+    let bytes = fs::read(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./test_data/test_stereo_44100.raw"),
+    )?;
+
+    Ok(bytes
+        .chunks(2)
+        .map(|chunk| i16::from_ne_bytes([chunk[0], chunk[1]]))
+        .collect())
+}
+
+/// Generates a mono signal of pseudo random chords which change every quarter of a second. The
+/// same signal is produced for every sample rate.
+pub fn generate_chords(sample_rate: u32, seconds: u32) -> Vec<i16> {
+    let chord_length = sample_rate as usize / 4;
+    let mut seed = 12345u32;
+    let mut samples = Vec::with_capacity(sample_rate as usize * seconds as usize);
+
+    for _ in 0..(seconds * 4) {
+        let frequencies: Vec<f64> = (0..3)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let note = 40 + (seed >> 16) % 40;
+                440.0 * 2f64.powf((note as f64 - 69.0) / 12.0)
+            })
+            .collect();
+
+        for _ in 0..chord_length {
+            let time = samples.len() as f64 / sample_rate as f64;
+            let value: f64 = frequencies
+                .iter()
+                .map(|frequency| (2.0 * PI * frequency * time).sin())
+                .sum();
+
+            samples.push((value * 8000.0) as i16);
+        }
+    }
+
+    samples
+}
+
+/// Fingerprints interleaved 16-bit samples with the default algorithm.
+pub fn fingerprint_samples(
+    samples: &[i16],
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Vec<u32>, Box<dyn Error>> {
+    let mut fingerprinter = Fingerprinter::new(sample_rate, channels)?;
+    fingerprinter.feed(samples)?;
+    fingerprinter.finish()?;
+
+    Ok(fingerprinter.fingerprint().items().to_vec())
+}
+
+/// Encodes 16-bit samples as little endian bytes.
+pub fn pcm16_bytes(samples: &[i16]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect()
+}
+
+/// Writes a WAV file holding interleaved 16-bit samples.
+pub fn wav_bytes(samples: &[i16], sample_rate: u32, channels: u16) -> Vec<u8> {
+    wav_with_format(1, sample_rate, channels, 16, &pcm16_bytes(samples))
+}
+
+/// Writes a WAV file with the given format tag and sample width, holding samples already
+/// encoded in `data`.
+pub fn wav_with_format(
+    tag: u16,
+    sample_rate: u32,
+    channels: u16,
+    bits_per_sample: u16,
+    data: &[u8],
+) -> Vec<u8> {
+    let block_align = channels * bits_per_sample / 8;
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&tag.to_le_bytes());
+    fmt.extend_from_slice(&channels.to_le_bytes());
+    fmt.extend_from_slice(&sample_rate.to_le_bytes());
+    fmt.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    fmt.extend_from_slice(&block_align.to_le_bytes());
+    fmt.extend_from_slice(&bits_per_sample.to_le_bytes());
+
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(4 + 8 + fmt.len() as u32 + 8 + data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&fmt);
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(data);
+    wav
+}
@@ -5,15 +5,19 @@ use resampler::Resampler;
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
-use std::mem;
 use std::path::Path;
 use std::path::PathBuf;
 
+pub use test_audio::{
+    fingerprint_samples, generate_chords, pcm16_bytes, wav_bytes, wav_with_format,
+};
+
 const MIN_FREQ: u32 = 28;
 const MAX_FREQ: u32 = 3520;
 const FRAME_SIZE: usize = 4096;
-const TARGET_SAMPLE_RATE: i32 = 11025;
-const INPUT_SAMPLE_RATE: i32 = 44100;
+const FRAME_OVERLAP: usize = FRAME_SIZE - FRAME_SIZE / 3;
+const TARGET_SAMPLE_RATE: u32 = 11025;
+const INPUT_SAMPLE_RATE: u32 = 44100;
 const RESAMPLE_FILTER_LENGTH: i32 = 16;
 const RESAMPLE_PHASE_SHIFT: i32 = 8;
 const RESAMPLE_LINEAR: bool = false;
@@ -31,21 +35,26 @@ fn test_chromaprint() -> Result<(), Box<dyn Error>> {
         RESAMPLE_PHASE_SHIFT,
         RESAMPLE_LINEAR,
         RESAMPLE_SAMPLE_CUTOFF,
-    );
+    )?;
 
-    let mut fft = Fft::new();
+    let mut fft = Fft::new(FRAME_SIZE, FRAME_OVERLAP);
     let chroma = Chroma::new(
         MIN_FREQ,
         MAX_FREQ,
         FRAME_SIZE as u32,
-        TARGET_SAMPLE_RATE as u32,
+        TARGET_SAMPLE_RATE,
+        false,
     );
     let mut image = Vec::new();
 
     let mut resampled = vec![0i16; samples.len()];
     let (_src_consumed, last_idx) = resampler.resample(&samples, &mut resampled);
+    let resampled: Vec<f32> = resampled[..(last_idx + 1)]
+        .iter()
+        .map(|&sample| sample as f32)
+        .collect();
 
-    fft.consume(&resampled[..(last_idx + 1)], |frame| {
+    fft.consume(&resampled, |frame| {
         let chroma_features = chroma.handle_frame(&frame);
         let chroma_features_normalized = normalize_vector(chroma_features);
         image.push(chroma_features_normalized);
@@ -142,5 +151,5 @@ pub fn load_audio_file<T: AsRef<Path>>(path: T) -> Result<Vec<i16>, Box<dyn Erro
 }
 
 pub fn from_ne_bytes(bytes: [u8; 2]) -> i16 {
-    unsafe { mem::transmute(bytes) }
+    i16::from_ne_bytes(bytes)
 }
@@ -0,0 +1,784 @@
+//! Reading of WAV files into a `Fingerprinter`.
+//!
+//! RIFF and RF64 files are supported, holding unsigned 8-bit, signed 16, 24 or 32-bit integer or
+//! 32 or 64-bit floating point samples, in either the plain or the `WAVE_FORMAT_EXTENSIBLE`
+//! format. Files written to a stream, with unknown chunk sizes, are read until their end.
+
+use decoder::{feed_decoder, Decoder, Samples};
+use error::Error;
+use fingerprinter::{FeedStatus, Fingerprinter};
+use sample::I24;
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// The bytes following the format tag in the sub-format GUIDs of extensible formats.
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+/// A chunk size meaning the size is unknown or stored in the `ds64` chunk of RF64 files.
+const UNKNOWN_SIZE: u32 = 0xffff_ffff;
+
+/// Number of frames read and fed at a time.
+const BLOCK_FRAMES: usize = 4096;
+
+/// Encoding of the samples of a WAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Unsigned 8-bit integers.
+    U8,
+    I16,
+
+    /// Packed 24-bit integers.
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    fn from_format(tag: u16, bits_per_sample: u16) -> Result<SampleFormat, WavError> {
+        match (tag, bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => Ok(SampleFormat::U8),
+            (WAVE_FORMAT_PCM, 16) => Ok(SampleFormat::I16),
+            (WAVE_FORMAT_PCM, 24) => Ok(SampleFormat::I24),
+            (WAVE_FORMAT_PCM, 32) => Ok(SampleFormat::I32),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Ok(SampleFormat::F32),
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => Ok(SampleFormat::F64),
+            (WAVE_FORMAT_PCM, _) | (WAVE_FORMAT_IEEE_FLOAT, _) => {
+                Err(WavError::UnsupportedBitsPerSample(bits_per_sample))
+            }
+            _ => Err(WavError::UnsupportedFormat(tag)),
+        }
+    }
+
+    /// Number of bytes of a sample.
+    pub fn size(self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::I16 => 2,
+            SampleFormat::I24 => 3,
+            SampleFormat::I32 | SampleFormat::F32 => 4,
+            SampleFormat::F64 => 8,
+        }
+    }
+}
+
+/// The format of the audio in a WAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub sample_format: SampleFormat,
+
+    /// The speaker positions of the channels, from the `WAVE_FORMAT_EXTENSIBLE` header. These
+    /// don't affect fingerprinting, as all channels are averaged.
+    pub channel_mask: Option<u32>,
+}
+
+impl WavSpec {
+    /// Number of bytes of a sample for every channel.
+    fn frame_size(&self) -> usize {
+        self.sample_format.size() * self.channels as usize
+    }
+}
+
+/// Reasons a WAV file can't be read.
+#[derive(Debug)]
+pub enum WavError {
+    Io(io::Error),
+
+    /// The input doesn't start with a RIFF or RF64 header of a WAVE file.
+    NotWav,
+
+    /// The headers are inconsistent or incomplete.
+    Malformed(&'static str),
+
+    /// The samples are compressed or otherwise encoded with the given format tag.
+    UnsupportedFormat(u16),
+
+    /// The samples have a width which isn't supported for their encoding.
+    UnsupportedBitsPerSample(u16),
+
+    /// The fingerprinter rejected the audio.
+    Fingerprint(Error),
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WavError::Io(ref err) => write!(f, "unable to read WAV file: {}", err),
+            WavError::NotWav => write!(f, "not a WAV file"),
+            WavError::Malformed(reason) => write!(f, "malformed WAV file: {}", reason),
+            WavError::UnsupportedFormat(tag) => {
+                write!(f, "unsupported WAV sample format 0x{:04x}", tag)
+            }
+            WavError::UnsupportedBitsPerSample(bits) => {
+                write!(f, "unsupported WAV sample width of {} bits", bits)
+            }
+            WavError::Fingerprint(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for WavError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            WavError::Io(ref err) => Some(err),
+            WavError::Fingerprint(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WavError {
+    fn from(err: io::Error) -> WavError {
+        WavError::Io(err)
+    }
+}
+
+impl From<Error> for WavError {
+    fn from(err: Error) -> WavError {
+        WavError::Fingerprint(err)
+    }
+}
+
+/// Samples decoded from the bytes of a block, in the format of the file. 8-bit samples are
+/// widened to 16-bit, which is lossless.
+enum SampleBuffer {
+    U8(Vec<i16>),
+    I16(Vec<i16>),
+    I24(Vec<I24>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+impl SampleBuffer {
+    fn new(format: SampleFormat) -> SampleBuffer {
+        match format {
+            SampleFormat::U8 => SampleBuffer::U8(Vec::new()),
+            SampleFormat::I16 => SampleBuffer::I16(Vec::new()),
+            SampleFormat::I24 => SampleBuffer::I24(Vec::new()),
+            SampleFormat::I32 => SampleBuffer::I32(Vec::new()),
+            SampleFormat::F32 => SampleBuffer::F32(Vec::new()),
+            SampleFormat::F64 => SampleBuffer::F64(Vec::new()),
+        }
+    }
+
+    /// Decodes little endian samples, replacing the previous ones.
+    fn decode(&mut self, bytes: &[u8]) -> Samples<'_> {
+        match *self {
+            SampleBuffer::U8(ref mut samples) => Samples::I16(fill(
+                samples,
+                bytes.iter().map(|&byte| (byte as i16 - 128) << 8),
+            )),
+            SampleBuffer::I16(ref mut samples) => Samples::I16(fill(
+                samples,
+                bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]])),
+            )),
+            SampleBuffer::I24(ref mut samples) => Samples::I24(fill(
+                samples,
+                bytes
+                    .chunks_exact(3)
+                    .map(|b| I24::from_le_bytes([b[0], b[1], b[2]])),
+            )),
+            SampleBuffer::I32(ref mut samples) => Samples::I32(fill(
+                samples,
+                bytes
+                    .chunks_exact(4)
+                    .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            )),
+            SampleBuffer::F32(ref mut samples) => Samples::F32(fill(
+                samples,
+                bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            )),
+            SampleBuffer::F64(ref mut samples) => Samples::F64(fill(
+                samples,
+                bytes
+                    .chunks_exact(8)
+                    .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])),
+            )),
+        }
+    }
+}
+
+fn fill<S, I: Iterator<Item = S>>(samples: &mut Vec<S>, values: I) -> &[S] {
+    samples.clear();
+    samples.extend(values);
+    samples
+}
+
+/// Reads the samples of a WAV file in blocks and feeds them into a `Fingerprinter`. As a
+/// `Decoder`, it yields the samples in the format of the file, apart from 8-bit samples which
+/// are widened to 16-bit.
+pub struct WavReader<R> {
+    reader: R,
+    spec: WavSpec,
+
+    /// Number of bytes of samples left, or `None` if the samples continue until the end of the
+    /// input.
+    remaining: Option<u64>,
+    frames_read: u64,
+    buffer: Vec<u8>,
+    samples: SampleBuffer,
+}
+
+impl<R: Read> WavReader<R> {
+    /// Reads the headers of a WAV file, leaving `reader` at the first sample.
+    pub fn new(mut reader: R) -> Result<WavReader<R>, WavError> {
+        let (spec, data_size) = read_headers(&mut reader)?;
+        WavReader::with_spec(reader, spec, data_size)
+    }
+
+    /// Reads headerless samples in the given format, like `fpcalc -format s16le`.
+    ///
+    /// # Arguments
+    /// * `data_size` - The number of bytes of samples, or `None` to read until the end of the
+    ///   input.
+    ///
+    /// # Errors
+    /// `WavError::Fingerprint` with `Error::InvalidChannelCount` if `spec` has no channels.
+    pub fn with_spec(
+        reader: R,
+        spec: WavSpec,
+        data_size: Option<u64>,
+    ) -> Result<WavReader<R>, WavError> {
+        if spec.channels == 0 {
+            return Err(WavError::Fingerprint(Error::InvalidChannelCount(0)));
+        }
+
+        Ok(WavReader {
+            reader,
+            spec,
+            remaining: data_size,
+            frames_read: 0,
+            buffer: Vec::new(),
+            samples: SampleBuffer::new(spec.sample_format),
+        })
+    }
+
+    pub fn spec(&self) -> &WavSpec {
+        &self.spec
+    }
+
+    /// Number of frames, samples for every channel, in the file if it is known from the headers.
+    pub fn frame_count(&self) -> Option<u64> {
+        let frame_size = self.spec.frame_size() as u64;
+        self.remaining
+            .map(|remaining| self.frames_read + remaining / frame_size)
+    }
+
+    /// Number of frames read so far.
+    pub fn frames_read(&self) -> u64 {
+        self.frames_read
+    }
+
+    /// Duration in seconds of the audio read so far.
+    pub fn duration_read(&self) -> f64 {
+        self.frames_read as f64 / self.spec.sample_rate as f64
+    }
+
+    /// Creates a fingerprinter for the format of the file.
+    ///
+    /// # Errors
+    /// The errors of `Fingerprinter::new`, if the format isn't supported.
+    pub fn fingerprinter(&self) -> Result<Fingerprinter, Error> {
+        Fingerprinter::new(self.spec.sample_rate, self.spec.channels)
+    }
+
+    /// Reads a block of samples and feeds it into `fingerprinter`, which must have been set up
+    /// for the format of the file.
+    ///
+    /// # Returns
+    /// The status of the fingerprinter, or `None` once all samples have been read. A trailing
+    /// incomplete frame is dropped.
+    pub fn feed_block(
+        &mut self,
+        fingerprinter: &mut Fingerprinter,
+    ) -> Result<Option<FeedStatus>, WavError> {
+        match self.next_block()? {
+            Some(samples) => Ok(Some(samples.feed(fingerprinter)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Prepares `fingerprinter` for the format of the file with `Fingerprinter::start`, keeping
+    /// its settings, and feeds it samples until the end of the file or until it is done.
+    /// `Fingerprinter::finish` is left to the caller.
+    pub fn feed(&mut self, fingerprinter: &mut Fingerprinter) -> Result<FeedStatus, WavError> {
+        feed_decoder(self, fingerprinter)
+    }
+}
+
+impl<R: Read> Decoder for WavReader<R> {
+    type Error = WavError;
+
+    fn sample_rate(&self) -> u32 {
+        self.spec.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.spec.channels
+    }
+
+    /// Reads a block of samples. A trailing incomplete frame is dropped.
+    fn next_block(&mut self) -> Result<Option<Samples<'_>>, WavError> {
+        let frame_size = self.spec.frame_size();
+        let mut size = BLOCK_FRAMES * frame_size;
+        if let Some(remaining) = self.remaining {
+            size = size.min((remaining - remaining % frame_size as u64) as usize);
+        }
+
+        self.buffer.resize(size, 0);
+        let read = read_full(&mut self.reader, &mut self.buffer)?;
+        let read = read - read % frame_size;
+        if read == 0 {
+            self.remaining = Some(0);
+            return Ok(None);
+        }
+
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining -= read as u64;
+        }
+        self.frames_read += (read / frame_size) as u64;
+
+        Ok(Some(self.samples.decode(&self.buffer[..read])))
+    }
+}
+
+/// Reads the headers up to the start of the samples.
+///
+/// # Returns
+/// The format of the samples and their size in bytes, if known.
+fn read_headers<R: Read>(reader: &mut R) -> Result<(WavSpec, Option<u64>), WavError> {
+    let mut header = [0u8; 12];
+    if read_full(reader, &mut header)? < header.len() || &header[8..] != b"WAVE" {
+        return Err(WavError::NotWav);
+    }
+    let rf64 = match &header[..4] {
+        b"RIFF" => false,
+        b"RF64" => true,
+        _ => return Err(WavError::NotWav),
+    };
+
+    let mut ds64_data_size = None;
+    let mut spec = None;
+    loop {
+        let mut chunk_header = [0u8; 8];
+        if read_full(reader, &mut chunk_header)? < chunk_header.len() {
+            return Err(WavError::Malformed("missing data chunk"));
+        }
+        let size = u32::from_le_bytes([
+            chunk_header[4],
+            chunk_header[5],
+            chunk_header[6],
+            chunk_header[7],
+        ]);
+
+        match &chunk_header[..4] {
+            b"ds64" if rf64 => {
+                let ds64 = read_chunk(reader, size)?;
+                if ds64.len() < 24 {
+                    return Err(WavError::Malformed("truncated ds64 chunk"));
+                }
+                ds64_data_size = Some(u64::from_le_bytes([
+                    ds64[8], ds64[9], ds64[10], ds64[11], ds64[12], ds64[13], ds64[14], ds64[15],
+                ]));
+            }
+            b"fmt " => spec = Some(parse_format(&read_chunk(reader, size)?)?),
+            b"data" => {
+                let spec = spec.ok_or(WavError::Malformed("missing fmt chunk"))?;
+                let data_size = match size {
+                    UNKNOWN_SIZE if rf64 => {
+                        Some(ds64_data_size.ok_or(WavError::Malformed("missing ds64 chunk"))?)
+                    }
+                    UNKNOWN_SIZE => None,
+                    size => Some(size as u64),
+                };
+                return Ok((spec, data_size));
+            }
+            _ => {
+                // Chunks are padded to an even size.
+                let padded = size as u64 + size as u64 % 2;
+                if io::copy(&mut reader.by_ref().take(padded), &mut io::sink())? < padded {
+                    return Err(WavError::Malformed("missing data chunk"));
+                }
+            }
+        }
+    }
+}
+
+/// Reads the contents of a chunk along with its padding.
+fn read_chunk<R: Read>(reader: &mut R, size: u32) -> Result<Vec<u8>, WavError> {
+    let mut contents = Vec::new();
+    let padded = size as u64 + size as u64 % 2;
+    reader.by_ref().take(padded).read_to_end(&mut contents)?;
+    if (contents.len() as u64) < padded {
+        return Err(WavError::Malformed("truncated chunk"));
+    }
+
+    contents.truncate(size as usize);
+    Ok(contents)
+}
+
+fn parse_format(fmt: &[u8]) -> Result<WavSpec, WavError> {
+    if fmt.len() < 16 {
+        return Err(WavError::Malformed("truncated fmt chunk"));
+    }
+    let u16_at = |idx: usize| u16::from_le_bytes([fmt[idx], fmt[idx + 1]]);
+    let u32_at =
+        |idx: usize| u32::from_le_bytes([fmt[idx], fmt[idx + 1], fmt[idx + 2], fmt[idx + 3]]);
+
+    let mut tag = u16_at(0);
+    let channels = u16_at(2);
+    let sample_rate = u32_at(4);
+    let block_align = u16_at(12);
+    let bits_per_sample = u16_at(14);
+
+    let mut channel_mask = None;
+    if tag == WAVE_FORMAT_EXTENSIBLE {
+        if fmt.len() < 40 {
+            return Err(WavError::Malformed("truncated extensible fmt chunk"));
+        }
+        channel_mask = Some(u32_at(20));
+
+        // Valid bits narrower than the container are left-justified, so they are read at the
+        // width of the container.
+        tag = u16_at(24);
+        if fmt[26..40] != SUBFORMAT_GUID_TAIL {
+            return Err(WavError::UnsupportedFormat(WAVE_FORMAT_EXTENSIBLE));
+        }
+    }
+
+    let sample_format = SampleFormat::from_format(tag, bits_per_sample)?;
+    if channels == 0 {
+        return Err(WavError::Fingerprint(Error::InvalidChannelCount(channels)));
+    }
+    if block_align as usize != sample_format.size() * channels as usize {
+        return Err(WavError::Malformed(
+            "block alignment doesn't match the sample format",
+        ));
+    }
+
+    Ok(WavSpec {
+        sample_rate,
+        channels,
+        sample_format,
+        channel_mask,
+    })
+}
+
+/// Reads until `buffer` is full or the input ends.
+///
+/// # Returns
+/// The number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(count) => read += count,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SampleFormat, WavError, WavReader, WavSpec, WAVE_FORMAT_EXTENSIBLE};
+    use decoder::{Decoder, Samples};
+    use error::Error;
+    use fingerprinter::{FeedStatus, Fingerprinter};
+    use sample::I24;
+    use std::io::Cursor;
+    use tests;
+
+    fn fingerprint(wav: Vec<u8>) -> Result<Vec<u32>, WavError> {
+        let mut reader = WavReader::new(Cursor::new(wav))?;
+        let mut fingerprinter = reader.fingerprinter()?;
+        reader.feed(&mut fingerprinter)?;
+        fingerprinter.finish()?;
+
+        Ok(fingerprinter.fingerprint().items().to_vec())
+    }
+
+    fn encode<F: Fn(i16) -> Vec<u8>>(samples: &[i16], convert: F) -> Vec<u8> {
+        samples.iter().flat_map(|&sample| convert(sample)).collect()
+    }
+
+    #[test]
+    fn test_sample_formats() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let items = tests::fingerprint_samples(&samples, 11025, 1)?;
+
+        assert_eq!(items, fingerprint(tests::wav_bytes(&samples, 11025, 1))?);
+
+        let pcm24 = encode(&samples, |s| {
+            let bytes = s.to_le_bytes();
+            vec![0, bytes[0], bytes[1]]
+        });
+        assert_eq!(
+            items,
+            fingerprint(tests::wav_with_format(1, 11025, 1, 24, &pcm24))?
+        );
+
+        let pcm32 = encode(&samples, |s| ((s as i32) << 16).to_le_bytes().to_vec());
+        assert_eq!(
+            items,
+            fingerprint(tests::wav_with_format(1, 11025, 1, 32, &pcm32))?
+        );
+
+        let float32 = encode(&samples, |s| (s as f32 / 32768.0).to_le_bytes().to_vec());
+        assert_eq!(
+            items,
+            fingerprint(tests::wav_with_format(3, 11025, 1, 32, &float32))?
+        );
+
+        let float64 = encode(&samples, |s| (s as f64 / 32768.0).to_le_bytes().to_vec());
+        assert_eq!(
+            items,
+            fingerprint(tests::wav_with_format(3, 11025, 1, 64, &float64))?
+        );
+
+        // 8-bit samples lose the lower byte.
+        let quantized: Vec<i16> = samples.iter().map(|&s| s & !0xff).collect();
+        let pcm8 = encode(&samples, |s| vec![((s >> 8) + 128) as u8]);
+        assert_eq!(
+            tests::fingerprint_samples(&quantized, 11025, 1)?,
+            fingerprint(tests::wav_with_format(1, 11025, 1, 8, &pcm8))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_native_samples() -> Result<(), Box<dyn std::error::Error>> {
+        // A signal so quiet that it mostly rounds to 0 in 16 bits.
+        let quiet: Vec<I24> = tests::generate_chords(11025, 10)
+            .iter()
+            .map(|&s| I24::from_i32(s as i32 / 64))
+            .collect();
+        let pcm24: Vec<u8> = quiet
+            .iter()
+            .flat_map(|s| s.to_i32().to_le_bytes()[..3].to_vec())
+            .collect();
+        let file = tests::wav_with_format(1, 11025, 1, 24, &pcm24);
+
+        let mut reader = WavReader::new(Cursor::new(file.clone()))?;
+        match reader.next_block()? {
+            Some(Samples::I24(block)) => assert_eq!(&quiet[..block.len()], block),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut expected = Fingerprinter::new(11025, 1)?;
+        expected.feed(&quiet)?;
+        expected.finish()?;
+        assert_eq!(expected.fingerprint().items(), &fingerprint(file)?[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stereo_extensible() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let stereo = encode(&samples, |s| {
+            let bytes = s.to_le_bytes();
+            vec![bytes[0], bytes[1], bytes[0], bytes[1]]
+        });
+
+        let mut file = tests::wav_with_format(WAVE_FORMAT_EXTENSIBLE, 11025, 2, 16, &stereo);
+        // Grows the fmt chunk with the extension, a front left and right channel mask and the
+        // PCM sub-format.
+        let mut extension = vec![22, 0, 16, 0, 3, 0, 0, 0, 1, 0];
+        extension.extend_from_slice(&super::SUBFORMAT_GUID_TAIL);
+        file[16] = 40;
+        for (idx, &byte) in extension.iter().enumerate() {
+            file.insert(36 + idx, byte);
+        }
+
+        let mut reader = WavReader::new(Cursor::new(file.clone()))?;
+        assert_eq!(
+            &WavSpec {
+                sample_rate: 11025,
+                channels: 2,
+                sample_format: SampleFormat::I16,
+                channel_mask: Some(3),
+            },
+            reader.spec()
+        );
+        assert_eq!(Some(samples.len() as u64), reader.frame_count());
+
+        let mut fingerprinter = Fingerprinter::new(44100, 1)?;
+        reader.feed(&mut fingerprinter)?;
+        fingerprinter.finish()?;
+        assert_eq!(
+            tests::fingerprint_samples(&samples, 11025, 1)?,
+            fingerprinter.fingerprint().items()
+        );
+        assert_eq!(samples.len() as u64, reader.frames_read());
+
+        // An unknown sub-format.
+        file[50] = 0x11;
+        assert!(matches!(
+            WavReader::new(Cursor::new(file)),
+            Err(WavError::UnsupportedFormat(WAVE_FORMAT_EXTENSIBLE))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rf64() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let data = tests::pcm16_bytes(&samples);
+        let riff = tests::wav_with_format(1, 11025, 1, 16, &data);
+
+        let mut rf64 = Vec::new();
+        rf64.extend_from_slice(b"RF64");
+        rf64.extend_from_slice(&0xffff_ffffu32.to_le_bytes());
+        rf64.extend_from_slice(b"WAVE");
+        rf64.extend_from_slice(b"ds64");
+        rf64.extend_from_slice(&28u32.to_le_bytes());
+        rf64.extend_from_slice(&(riff.len() as u64 + 36).to_le_bytes());
+        rf64.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        rf64.extend_from_slice(&(samples.len() as u64).to_le_bytes());
+        rf64.extend_from_slice(&0u32.to_le_bytes());
+        rf64.extend_from_slice(&riff[12..(riff.len() - data.len() - 4)]);
+        rf64.extend_from_slice(&0xffff_ffffu32.to_le_bytes());
+        rf64.extend_from_slice(&data);
+        // Trailing chunks aren't read as samples.
+        rf64.extend_from_slice(b"LIST\x04\0\0\0INFO");
+
+        let reader = WavReader::new(Cursor::new(rf64.clone()))?;
+        assert_eq!(Some(samples.len() as u64), reader.frame_count());
+        assert_eq!(
+            tests::fingerprint_samples(&samples, 11025, 1)?,
+            fingerprint(rf64)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_streamed() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let mut file = tests::wav_bytes(&samples, 11025, 1);
+        // Sizes which are unknown when writing to a stream, with an incomplete last sample.
+        file[4..8].copy_from_slice(&[0xff; 4]);
+        file[40..44].copy_from_slice(&[0xff; 4]);
+        file.push(1);
+
+        let mut reader = WavReader::new(Cursor::new(file.clone()))?;
+        assert_eq!(None, reader.frame_count());
+        let mut fingerprinter = reader.fingerprinter()?;
+        fingerprinter.set_max_duration(Some(5.0))?;
+        assert_eq!(FeedStatus::Done, reader.feed(&mut fingerprinter)?);
+        assert!(reader.frames_read() < samples.len() as u64);
+
+        while reader.feed_block(&mut fingerprinter)?.is_some() {}
+        assert_eq!(samples.len() as u64, reader.frames_read());
+        assert_eq!(samples.len() as f64 / 11025.0, reader.duration_read());
+
+        assert_eq!(
+            tests::fingerprint_samples(&samples, 11025, 1)?,
+            fingerprint(file)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_headerless() -> Result<(), Box<dyn std::error::Error>> {
+        let samples = tests::generate_chords(11025, 10);
+        let spec = WavSpec {
+            sample_rate: 11025,
+            channels: 1,
+            sample_format: SampleFormat::I16,
+            channel_mask: None,
+        };
+
+        let mut reader =
+            WavReader::with_spec(Cursor::new(tests::pcm16_bytes(&samples)), spec, None)?;
+        let mut fingerprinter = reader.fingerprinter()?;
+        reader.feed(&mut fingerprinter)?;
+        fingerprinter.finish()?;
+        assert_eq!(
+            tests::fingerprint_samples(&samples, 11025, 1)?,
+            fingerprinter.fingerprint().items()
+        );
+
+        // Without channels, frames would have no size.
+        let spec = WavSpec {
+            channels: 0,
+            ..spec
+        };
+        assert!(matches!(
+            WavReader::with_spec(Cursor::new(vec![0; 4]), spec, None),
+            Err(WavError::Fingerprint(Error::InvalidChannelCount(0)))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let data = [0u8; 64];
+        let reader = |file: Vec<u8>| WavReader::new(Cursor::new(file)).err();
+
+        assert!(matches!(reader(Vec::new()), Some(WavError::NotWav)));
+        assert!(matches!(
+            reader(b"RIFF\0\0\0\0AVI LIST".to_vec()),
+            Some(WavError::NotWav)
+        ));
+        assert!(matches!(
+            reader(tests::wav_with_format(2, 11025, 1, 4, &data)),
+            Some(WavError::UnsupportedFormat(2))
+        ));
+        assert!(matches!(
+            reader(tests::wav_with_format(1, 11025, 1, 12, &data)),
+            Some(WavError::UnsupportedBitsPerSample(12))
+        ));
+        assert!(matches!(
+            reader(tests::wav_with_format(3, 11025, 1, 16, &data)),
+            Some(WavError::UnsupportedBitsPerSample(16))
+        ));
+        assert!(matches!(
+            reader(tests::wav_with_format(1, 11025, 0, 16, &data)),
+            Some(WavError::Fingerprint(Error::InvalidChannelCount(0)))
+        ));
+
+        let mut missing_data = tests::wav_with_format(1, 11025, 1, 16, &data);
+        missing_data.truncate(36);
+        assert!(matches!(reader(missing_data), Some(WavError::Malformed(_))));
+
+        let mut missing_fmt = tests::wav_with_format(1, 11025, 1, 16, &data);
+        missing_fmt.drain(12..36);
+        assert!(matches!(reader(missing_fmt), Some(WavError::Malformed(_))));
+
+        let mut low_sample_rate = tests::wav_with_format(1, 11025, 1, 16, &data);
+        low_sample_rate[24..28].copy_from_slice(&8u32.to_le_bytes());
+        let mut wav_reader = WavReader::new(Cursor::new(low_sample_rate)).unwrap();
+        assert!(matches!(
+            wav_reader.fingerprinter(),
+            Err(Error::InvalidSampleRate(8))
+        ));
+        let mut fingerprinter = Fingerprinter::new(11025, 1).unwrap();
+        assert!(matches!(
+            wav_reader.feed(&mut fingerprinter),
+            Err(WavError::Fingerprint(Error::InvalidSampleRate(8)))
+        ));
+    }
+}